@@ -0,0 +1,154 @@
+use crate::mcos::SubsystemWriter;
+use crate::parse::{self, ElementLocation};
+use crate::write::encode_value;
+use crate::{Error, MatFile, WriteOptions};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+impl MatFile {
+    /// Adds all values to the ".mat" file at the given path using the
+    /// default options, like Matlab's `save -append`. Variables of the file
+    /// with the same name as one of the values are replaced, all other
+    /// variables are kept. If the file doesn't exist yet it is created.
+    ///
+    /// ```rust
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = std::env::temp_dir().join("runs.mat");
+    /// # let _ = std::fs::remove_file(&path);
+    /// for run in 1..=3 {
+    ///     let label = format!("run{}", run);
+    ///     let latest = matfile::CharArray::from_text("latest", &label);
+    ///     let result = matfile::Array::new_logical(&label, vec![1, 1], &[run % 2 == 1])?;
+    ///     matfile::MatFile::from_values(vec![
+    ///         matfile::Value::Char(latest),
+    ///         matfile::Value::Numeric(result),
+    ///     ])?
+    ///     .append_to(&path)?;
+    /// }
+    /// let mat_file = matfile::MatFile::parse(std::fs::File::open(&path)?)?;
+    /// assert_eq!(mat_file.values().len(), 4);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn append_to<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.append_to_with_options(path, &WriteOptions::default())
+    }
+
+    /// Adds all values to the ".mat" file at the given path, replacing
+    /// variables with the same name.
+    ///
+    /// Variables preceding the first replaced one are left untouched: the
+    /// remaining variables are moved up byte by byte and the new values are
+    /// written at the end, followed by the subsystem data holding the
+    /// objects of the file. Only if the new values contain objects (which
+    /// have to be merged with those of the file), or if the file is
+    /// big-endian, is the whole file decoded and written again.
+    ///
+    /// Files with bytes after their last readable data element are left
+    /// unchanged and an error is returned, as these bytes would be lost.
+    pub fn append_to_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &WriteOptions,
+    ) -> Result<(), Error> {
+        let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return self
+                    .write_with_options(File::create(&path).map_err(Error::IOError)?, options);
+            }
+            Err(err) => return Err(Error::IOError(err)),
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).map_err(Error::IOError)?;
+        let (remaining, (header, locations)) = parse::locate_data_elements(&data)
+            .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
+        if !remaining.is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "the last {} bytes of the file can't be read as a data element",
+                remaining.len()
+            )));
+        }
+        let mut subsystem = SubsystemWriter::default();
+        let mut body = Vec::new();
+        for value in &self.values {
+            body.extend_from_slice(&encode_value(value, options, &mut subsystem)?);
+        }
+        if !subsystem.is_empty() || !header.is_little_endian() {
+            return self.rewrite(&mut file, &data, options);
+        }
+        // The subsystem data element is kept as it is and moved to the end.
+        // Raw values referencing objects can only be added along with the
+        // subsystem data they were read with.
+        let file_subsystem = locations
+            .iter()
+            .position(|location| header.subsystem_offset() == Some(location.start as u64));
+        let subsystem_element = match (subsystem.take_original(), file_subsystem) {
+            (Some(original), Some(index))
+                if *original != data[locations[index].start..locations[index].end] =>
+            {
+                return self.rewrite(&mut file, &data, options);
+            }
+            (Some(original), _) => Some(original.to_vec()),
+            (None, Some(index)) => {
+                Some(data[locations[index].start..locations[index].end].to_vec())
+            }
+            (None, None) => None,
+        };
+        let is_replaced = |index: usize, location: &ElementLocation| {
+            Some(index) != file_subsystem
+                && location
+                    .name
+                    .as_ref()
+                    .is_some_and(|name| self.find_value_by_name(name).is_some())
+        };
+        let end = locations.last().map_or(128, |location| location.end);
+        let start = locations
+            .iter()
+            .enumerate()
+            .find(|&(index, location)| {
+                is_replaced(index, location) || Some(index) == file_subsystem
+            })
+            .map_or(end, |(_, location)| location.start);
+        let mut tail = Vec::new();
+        for (index, location) in locations.iter().enumerate() {
+            if location.start >= start
+                && !is_replaced(index, location)
+                && Some(index) != file_subsystem
+            {
+                tail.extend_from_slice(&data[location.start..location.end]);
+            }
+        }
+        tail.extend_from_slice(&body);
+        let subsystem_offset = subsystem_element.map(|element| {
+            let offset = (start + tail.len()) as u64;
+            tail.extend_from_slice(&element);
+            offset
+        });
+        file.seek(SeekFrom::Start(start as u64))
+            .map_err(Error::IOError)?;
+        file.write_all(&tail).map_err(Error::IOError)?;
+        file.set_len((start + tail.len()) as u64)
+            .map_err(Error::IOError)?;
+        if subsystem_offset != header.subsystem_offset() {
+            file.seek(SeekFrom::Start(116)).map_err(Error::IOError)?;
+            file.write_all(&subsystem_offset.unwrap_or(0).to_le_bytes())
+                .map_err(Error::IOError)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of a file with its decoded values merged with
+    /// the new ones.
+    fn rewrite(&self, file: &mut File, data: &[u8], options: &WriteOptions) -> Result<(), Error> {
+        let mut values = MatFile::parse(data)?.values;
+        values.retain(|value| self.find_value_by_name(value.name()).is_none());
+        values.extend(self.values.iter().cloned());
+        let mut data = Vec::new();
+        MatFile::from_values_unchecked(values).write_with_options(&mut data, options)?;
+        file.seek(SeekFrom::Start(0)).map_err(Error::IOError)?;
+        file.write_all(&data).map_err(Error::IOError)?;
+        file.set_len(data.len() as u64).map_err(Error::IOError)
+    }
+}
@@ -2,8 +2,6 @@ use matfile::MatFile;
 #[cfg(feature = "ndarray")]
 use ndarr as ndarray;
 #[cfg(feature = "ndarray")]
-use num_complex;
-#[cfg(feature = "ndarray")]
 use std::convert::TryInto;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -0,0 +1,203 @@
+use crate::{check_size, Array, ClassdefArray, Error, NumericData, Value};
+
+/// A Matlab `categorical` array, stored in column-major order.
+///
+/// Every element is either one of a fixed list of categories or undefined
+/// (`<undefined>` in Matlab). The elements are stored as codes referring to
+/// the categories.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/categorical.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::Categorical(sizes)) = mat_file.find_value_by_name("sizes") {
+///     for label in sizes.labels() {
+///         println!("{}", label.unwrap_or("<undefined>"));
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct CategoricalArray {
+    pub(crate) name: String,
+    size: Vec<usize>,
+    categories: Vec<String>,
+    codes: Vec<Option<usize>>,
+    ordinal: bool,
+    protected: bool,
+    /// The properties of the object this was loaded from, which are written
+    /// instead of encoding the fields again
+    saved_properties: Option<Vec<(String, Value)>>,
+}
+
+impl std::fmt::Debug for CategoricalArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CategoricalArray")
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("categories", &self.categories)
+            .field("codes", &self.codes)
+            .field("ordinal", &self.ordinal)
+            .field("protected", &self.protected)
+            .finish()
+    }
+}
+
+impl CategoricalArray {
+    /// Creates an (unordered, unprotected) categorical array of the given
+    /// size from the (zero-based) category index of every element in
+    /// column-major order, `None` for undefined elements.
+    pub fn new(
+        name: &str,
+        size: Vec<usize>,
+        categories: Vec<String>,
+        codes: Vec<Option<usize>>,
+    ) -> Result<Self, Error> {
+        let num_elements = check_size(&size)?;
+        if codes.len() != num_elements {
+            return Err(Error::InvalidArgument(format!(
+                "a categorical array of size {:?} needs {} codes",
+                size, num_elements
+            )));
+        }
+        if codes.iter().flatten().any(|&code| code >= categories.len()) {
+            return Err(Error::InvalidArgument(format!(
+                "there are only {} categories",
+                categories.len()
+            )));
+        }
+        Ok(CategoricalArray {
+            name: name.to_owned(),
+            size,
+            categories,
+            codes,
+            ordinal: false,
+            protected: false,
+            saved_properties: None,
+        })
+    }
+
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.size.len()
+    }
+
+    /// The names of the categories. For ordinal arrays they are sorted from
+    /// smallest to largest.
+    pub fn categories(&self) -> &Vec<String> {
+        &self.categories
+    }
+
+    /// The (zero-based) index into `categories` of every element, `None` for
+    /// undefined elements.
+    pub fn codes(&self) -> &Vec<Option<usize>> {
+        &self.codes
+    }
+
+    /// Whether the categories have a mathematical order.
+    pub fn is_ordinal(&self) -> bool {
+        self.ordinal
+    }
+
+    /// Whether the list of categories is protected, i.e. values of other
+    /// categories can't be assigned to the array. Ordinal arrays are always
+    /// protected.
+    pub fn is_protected(&self) -> bool {
+        self.protected
+    }
+
+    /// The category name of every element, `None` for undefined elements.
+    pub fn labels(&self) -> Vec<Option<&str>> {
+        self.codes
+            .iter()
+            .map(|code| code.map(|code| self.categories[code].as_str()))
+            .collect()
+    }
+
+    /// Decodes a `categorical` object. The property `codes` is an unsigned
+    /// integer array with the one-based category of every element, or zero
+    /// for undefined elements. The categories are listed in `categoryNames`.
+    pub(crate) fn from_classdef(array: &ClassdefArray) -> Option<Self> {
+        if array.size().iter().product::<usize>() != 1 {
+            return None;
+        }
+        let codes = array.numeric_property(0, "codes")?;
+        let categories = array.property(0, "categoryNames")?.to_strings()?;
+        let size = codes.size().clone();
+        let codes = codes
+            .data()
+            .real_to_f64()
+            .into_iter()
+            .map(|code| match code as usize {
+                0 => Some(None),
+                code if code <= categories.len() => Some(Some(code - 1)),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        Some(CategoricalArray {
+            name: array.name().to_owned(),
+            size,
+            categories,
+            codes,
+            ordinal: array.flag_property(0, "isOrdinal"),
+            protected: array.flag_property(0, "isProtected"),
+            saved_properties: array.properties(0).cloned(),
+        })
+    }
+
+    /// Encodes the array as the properties of a `categorical` object, see
+    /// `from_classdef`. Like Matlab, the codes are stored using the smallest
+    /// unsigned integer type able to hold them. Loaded arrays keep their
+    /// saved properties.
+    pub(crate) fn to_properties(&self) -> Vec<(String, Value)> {
+        if let Some(saved_properties) = &self.saved_properties {
+            return saved_properties.clone();
+        }
+        let codes = self
+            .codes
+            .iter()
+            .map(|code| code.map_or(0, |code| code + 1));
+        let data = if self.categories.len() < u8::MAX as usize {
+            NumericData::UInt8 {
+                real: codes.map(|code| code as u8).collect(),
+                imag: None,
+            }
+        } else if self.categories.len() < u16::MAX as usize {
+            NumericData::UInt16 {
+                real: codes.map(|code| code as u16).collect(),
+                imag: None,
+            }
+        } else {
+            NumericData::UInt32 {
+                real: codes.map(|code| code as u32).collect(),
+                imag: None,
+            }
+        };
+        let codes = Array {
+            name: String::new(),
+            size: self.size.clone(),
+            data,
+            logical: false,
+        };
+        vec![
+            ("codes".to_owned(), Value::Numeric(codes)),
+            (
+                "categoryNames".to_owned(),
+                Value::from_strings(vec![self.categories.len(), 1], &self.categories),
+            ),
+            ("isProtected".to_owned(), Value::from_bool(self.protected)),
+            ("isOrdinal".to_owned(), Value::from_bool(self.ordinal)),
+        ]
+    }
+}
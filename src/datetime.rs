@@ -0,0 +1,329 @@
+use crate::{Array, CharArray, ClassdefArray, NumericData, Value};
+
+/// The datenum of the Unix epoch (1970-01-01 00:00:00)
+const UNIX_EPOCH_DATENUM: f64 = 719_529.0;
+
+const MILLISECONDS_PER_DAY: f64 = 86_400_000.0;
+
+/// An array of Matlab `datetime` values, stored in column-major order.
+///
+/// Matlab stores points in time as milliseconds since 1970-01-01 00:00:00.
+/// For arrays with a time zone these are milliseconds since the epoch in
+/// UTC, while for arrays without a time zone (the default in Matlab) they
+/// count from the epoch in local "wall clock" time. Missing values (`NaT`)
+/// are NaN.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/datetime.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::Datetime(dates)) = mat_file.find_value_by_name("launch") {
+///     println!("Time zone: {}", dates.time_zone());
+///     println!("Milliseconds: {:?}", dates.milliseconds());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DatetimeArray {
+    pub(crate) name: String,
+    size: Vec<usize>,
+    milliseconds: Vec<f64>,
+    sub_milliseconds: Option<Vec<f64>>,
+    time_zone: String,
+    format: String,
+    date_only: bool,
+    /// The properties of the object this was loaded from, which are written
+    /// instead of encoding the fields again
+    saved_properties: Option<Vec<(String, Value)>>,
+}
+
+impl std::fmt::Debug for DatetimeArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatetimeArray")
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("milliseconds", &self.milliseconds)
+            .field("sub_milliseconds", &self.sub_milliseconds)
+            .field("time_zone", &self.time_zone)
+            .field("format", &self.format)
+            .field("date_only", &self.date_only)
+            .finish()
+    }
+}
+
+/// An array of Matlab `duration` values, stored in column-major order.
+///
+/// Durations are stored as (possibly fractional) milliseconds, missing
+/// values are NaN.
+#[derive(Clone)]
+pub struct DurationArray {
+    pub(crate) name: String,
+    size: Vec<usize>,
+    milliseconds: Vec<f64>,
+    format: String,
+    /// The properties of the object this was loaded from, which are written
+    /// instead of encoding the fields again
+    saved_properties: Option<Vec<(String, Value)>>,
+}
+
+impl std::fmt::Debug for DurationArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DurationArray")
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("milliseconds", &self.milliseconds)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl DatetimeArray {
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.size.len()
+    }
+
+    /// The milliseconds since 1970-01-01 00:00:00, NaN for `NaT`.
+    pub fn milliseconds(&self) -> &Vec<f64> {
+        &self.milliseconds
+    }
+
+    /// Matlab can store a second, much smaller value per element that has to
+    /// be added to `milliseconds` to get times with sub-microsecond
+    /// precision. `None` if the file didn't need them.
+    pub fn sub_milliseconds(&self) -> Option<&Vec<f64>> {
+        self.sub_milliseconds.as_ref()
+    }
+
+    /// The time zone (e.g. `Europe/Berlin` or `UTC`), empty if the values are
+    /// not associated with a time zone.
+    pub fn time_zone(&self) -> &str {
+        &self.time_zone
+    }
+
+    /// The display format (e.g. `yyyy-MM-dd HH:mm:ss`), empty for Matlab's
+    /// default format.
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    /// Whether the values only represent dates without a time of day.
+    pub fn is_date_only(&self) -> bool {
+        self.date_only
+    }
+
+    /// Interprets a numeric array of classic Matlab serial date numbers
+    /// (days since the year 0, as returned by `datenum` or `now`) as an
+    /// array of datetimes without a time zone.
+    ///
+    /// Complex arrays are not accepted.
+    pub fn from_datenums(array: &Array) -> Option<Self> {
+        if array.data().is_complex() {
+            return None;
+        }
+        let milliseconds = array
+            .data()
+            .real_to_f64()
+            .into_iter()
+            .map(|datenum| (datenum - UNIX_EPOCH_DATENUM) * MILLISECONDS_PER_DAY)
+            .collect();
+        Some(DatetimeArray {
+            name: array.name().to_owned(),
+            size: array.size().clone(),
+            milliseconds,
+            sub_milliseconds: None,
+            time_zone: String::new(),
+            format: String::new(),
+            date_only: false,
+            saved_properties: None,
+        })
+    }
+
+    /// Converts the values to `chrono` date times, `None` for `NaT` and
+    /// values out of range. Values without a time zone are returned as if
+    /// they were in UTC.
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono(&self) -> Vec<Option<chrono::DateTime<chrono::Utc>>> {
+        (0..self.milliseconds.len())
+            .map(|index| {
+                let sub_milliseconds = self
+                    .sub_milliseconds
+                    .as_ref()
+                    .and_then(|sub_milliseconds| sub_milliseconds.get(index))
+                    .copied()
+                    .unwrap_or(0.0);
+                let (seconds, nanoseconds) =
+                    split_milliseconds(self.milliseconds[index], sub_milliseconds)?;
+                chrono::DateTime::from_timestamp(seconds, nanoseconds)
+            })
+            .collect()
+    }
+
+    /// Decodes a `datetime` object. Its property `data` holds the
+    /// milliseconds, with the imaginary part (if any) holding a small
+    /// correction to be added to them. The properties `tz` and `fmt` hold
+    /// the time zone and the display format.
+    pub(crate) fn from_classdef(array: &ClassdefArray) -> Option<Self> {
+        if array.size().iter().product::<usize>() != 1 {
+            return None;
+        }
+        let data = array.numeric_property(0, "data")?;
+        let (milliseconds, sub_milliseconds) = match data.data() {
+            NumericData::Double { real, imag } => (real.clone(), imag.clone()),
+            other => (other.real_to_f64(), None),
+        };
+        Some(DatetimeArray {
+            name: array.name().to_owned(),
+            size: data.size().clone(),
+            milliseconds,
+            sub_milliseconds,
+            time_zone: array.text_property(0, "tz").unwrap_or_default(),
+            format: array.text_property(0, "fmt").unwrap_or_default(),
+            date_only: array.flag_property(0, "isDateOnly"),
+            saved_properties: array.properties(0).cloned(),
+        })
+    }
+
+    /// Encodes the array as the properties of a `datetime` object, see
+    /// `from_classdef`. The flag `isDateOnly` is only stored if it is set.
+    /// Loaded arrays keep their saved properties.
+    pub(crate) fn to_properties(&self) -> Vec<(String, Value)> {
+        if let Some(saved_properties) = &self.saved_properties {
+            return saved_properties.clone();
+        }
+        let data = Array {
+            name: String::new(),
+            size: self.size.clone(),
+            data: NumericData::Double {
+                real: self.milliseconds.clone(),
+                imag: self.sub_milliseconds.clone(),
+            },
+            logical: false,
+        };
+        let mut properties = vec![
+            ("data".to_owned(), Value::Numeric(data)),
+            (
+                "tz".to_owned(),
+                Value::Char(CharArray::from_text("", &self.time_zone)),
+            ),
+            (
+                "fmt".to_owned(),
+                Value::Char(CharArray::from_text("", &self.format)),
+            ),
+        ];
+        if self.date_only {
+            properties.push(("isDateOnly".to_owned(), Value::from_bool(true)));
+        }
+        properties
+    }
+}
+
+impl DurationArray {
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.size.len()
+    }
+
+    /// The durations in milliseconds, NaN for missing values.
+    pub fn milliseconds(&self) -> &Vec<f64> {
+        &self.milliseconds
+    }
+
+    /// The display format (e.g. `hh:mm:ss`).
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    /// Converts the values to `chrono` durations, `None` for missing values
+    /// and values out of range.
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono(&self) -> Vec<Option<chrono::Duration>> {
+        self.milliseconds
+            .iter()
+            .map(|&milliseconds| {
+                let (seconds, nanoseconds) = split_milliseconds(milliseconds, 0.0)?;
+                chrono::Duration::new(seconds, nanoseconds)
+            })
+            .collect()
+    }
+
+    /// Decodes a `duration` object, whose properties `millis` and `fmt` hold
+    /// the milliseconds and the display format.
+    pub(crate) fn from_classdef(array: &ClassdefArray) -> Option<Self> {
+        if array.size().iter().product::<usize>() != 1 {
+            return None;
+        }
+        let data = array.numeric_property(0, "millis")?;
+        Some(DurationArray {
+            name: array.name().to_owned(),
+            size: data.size().clone(),
+            milliseconds: data.data().real_to_f64(),
+            format: array.text_property(0, "fmt").unwrap_or_default(),
+            saved_properties: array.properties(0).cloned(),
+        })
+    }
+
+    /// Encodes the array as the properties of a `duration` object, see
+    /// `from_classdef`, unless it was loaded with its properties.
+    pub(crate) fn to_properties(&self) -> Vec<(String, Value)> {
+        if let Some(saved_properties) = &self.saved_properties {
+            return saved_properties.clone();
+        }
+        let millis = Array {
+            name: String::new(),
+            size: self.size.clone(),
+            data: NumericData::Double {
+                real: self.milliseconds.clone(),
+                imag: None,
+            },
+            logical: false,
+        };
+        vec![
+            ("millis".to_owned(), Value::Numeric(millis)),
+            (
+                "fmt".to_owned(),
+                Value::Char(CharArray::from_text("", &self.format)),
+            ),
+        ]
+    }
+}
+
+/// Splits milliseconds into whole seconds and the remaining nanoseconds,
+/// which is how `chrono` represents both points in time and durations.
+#[cfg(feature = "chrono")]
+fn split_milliseconds(milliseconds: f64, sub_milliseconds: f64) -> Option<(i64, u32)> {
+    if !milliseconds.is_finite() || !sub_milliseconds.is_finite() {
+        return None;
+    }
+    let seconds = (milliseconds / 1000.0).floor();
+    if seconds < i64::MIN as f64 || seconds > i64::MAX as f64 {
+        return None;
+    }
+    let remainder = (milliseconds - seconds * 1000.0) + sub_milliseconds;
+    let nanoseconds = (remainder * 1_000_000.0).round();
+    // The correction may move the value across a second boundary
+    let carry = (nanoseconds / 1e9).floor();
+    let nanoseconds = nanoseconds - carry * 1e9;
+    Some(((seconds + carry) as i64, nanoseconds as u32))
+}
@@ -0,0 +1,154 @@
+use crate::{CharArray, Error, StructArray, Value};
+
+/// The kind of function a function handle refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FunctionHandleKind {
+    /// A handle to a named function, e.g. `@sin`
+    Simple,
+    /// An anonymous function, e.g. `@(x) x.^2`
+    Anonymous,
+    /// A handle to a nested function
+    Nested,
+    /// A handle to a local or private function, only visible from within
+    /// the file it is defined in
+    Scoped,
+}
+
+/// A Matlab function handle.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/function_handles.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::FunctionHandle(handle)) = mat_file.find_value_by_name("square") {
+///     println!("{:?} function {}", handle.kind(), handle.function());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FunctionHandle {
+    pub(crate) name: String,
+    kind: FunctionHandleKind,
+    function: String,
+    file: String,
+    workspace: Vec<(String, Value)>,
+}
+
+impl FunctionHandle {
+    /// The name of this function handle.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The kind of function this handle refers to.
+    pub fn kind(&self) -> FunctionHandleKind {
+        self.kind
+    }
+
+    /// The name of the function, or the text of the expression (like
+    /// `@(x)x.^2`) for anonymous functions.
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+
+    /// The file the function is defined in. Empty for anonymous functions
+    /// and functions that are not defined in a file (like built-ins).
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// The variables captured by an anonymous or nested function together
+    /// with their values. Empty for all other kinds of functions.
+    pub fn workspace(&self) -> &Vec<(String, Value)> {
+        &self.workspace
+    }
+
+    /// Decodes the structure Matlab uses to describe a function handle. It
+    /// has the fields `matlabroot`, `separator`, `sentinel` and
+    /// `function_handle`, the latter holding the actual information.
+    pub(crate) fn from_description(name: String, description: &StructArray) -> Option<Self> {
+        let function_handle = match description.field(0, "function_handle")? {
+            Value::Struct(function_handle) => function_handle,
+            _ => return None,
+        };
+        let text_field = |field_name| match function_handle.field(0, field_name) {
+            Some(Value::Char(text)) => Some(text.to_string()),
+            _ => None,
+        };
+        let kind = match text_field("type")?.as_str() {
+            "simple" | "classsimple" => FunctionHandleKind::Simple,
+            "anonymous" => FunctionHandleKind::Anonymous,
+            "nested" => FunctionHandleKind::Nested,
+            "scopedfunction" => FunctionHandleKind::Scoped,
+            _ => return None,
+        };
+        let function = text_field("function")?;
+        let file = text_field("file").unwrap_or_default();
+        // The captured variables are the fields of a scalar struct, which
+        // might itself be wrapped in a cell
+        let workspace = match function_handle.field(0, "workspace") {
+            Some(Value::Struct(workspace)) => Some(workspace),
+            Some(Value::Cell(cell)) => match cell.cells().first() {
+                Some(Value::Struct(workspace)) => Some(workspace),
+                _ => None,
+            },
+            _ => None,
+        };
+        let workspace = workspace
+            .map(|workspace| {
+                workspace
+                    .field_names()
+                    .iter()
+                    .filter_map(|field_name| {
+                        let value = workspace.field(0, field_name)?;
+                        Some((field_name.clone(), value.clone()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(FunctionHandle {
+            name,
+            kind,
+            function,
+            file,
+            workspace,
+        })
+    }
+
+    /// Encodes the function handle as the structure read by
+    /// `from_description`. The captured variables are only stored for
+    /// anonymous and nested functions.
+    pub(crate) fn to_description(&self) -> Result<StructArray, Error> {
+        let text = |text: &str| Value::Char(CharArray::from_text("", text));
+        let kind = match self.kind {
+            FunctionHandleKind::Simple => "simple",
+            FunctionHandleKind::Anonymous => "anonymous",
+            FunctionHandleKind::Nested => "nested",
+            FunctionHandleKind::Scoped => "scopedfunction",
+        };
+        let mut field_names = vec!["function".to_owned(), "type".to_owned(), "file".to_owned()];
+        let mut values = vec![text(&self.function), text(kind), text(&self.file)];
+        if let FunctionHandleKind::Anonymous | FunctionHandleKind::Nested = self.kind {
+            let (names, captured) = self.workspace.iter().cloned().unzip();
+            let workspace = StructArray::new("", vec![1, 1], names, captured)?;
+            field_names.push("workspace".to_owned());
+            values.push(Value::Struct(workspace));
+        }
+        let function_handle = StructArray::new("", vec![1, 1], field_names, values)?;
+        StructArray::new(
+            "",
+            vec![1, 1],
+            ["matlabroot", "separator", "sentinel", "function_handle"]
+                .iter()
+                .map(|&field_name| field_name.to_owned())
+                .collect(),
+            vec![
+                text(""),
+                text("/"),
+                text("@"),
+                Value::Struct(function_handle),
+            ],
+        )
+    }
+}
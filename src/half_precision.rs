@@ -0,0 +1,90 @@
+use crate::{Array, ClassdefArray, NumericData, Value};
+
+/// Converts the bits of an IEEE 754 binary16 number to `f32`. Every half
+/// precision number can be represented exactly as `f32`.
+pub(crate) fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    match exponent {
+        // Zero and subnormal numbers
+        0 => {
+            let magnitude = mantissa as f32 * 2f32.powi(-24);
+            if sign != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        }
+        // Infinity and NaN
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+impl NumericData {
+    /// Converts half precision data to `f32`, returning the real and the
+    /// (optional) imaginary part. `None` if this isn't half precision data.
+    pub fn half_to_f32(&self) -> Option<(Vec<f32>, Option<Vec<f32>>)> {
+        match self {
+            NumericData::Half { real, imag } => {
+                let convert = |bits: &Vec<u16>| bits.iter().map(|&b| f16_bits_to_f32(b)).collect();
+                Some((convert(real), imag.as_ref().map(convert)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Converts half precision data to `half::f16`, returning the real and
+    /// the (optional) imaginary part. `None` if this isn't half precision
+    /// data.
+    #[cfg(feature = "half")]
+    pub fn half_to_f16(&self) -> Option<(Vec<half::f16>, Option<Vec<half::f16>>)> {
+        match self {
+            NumericData::Half { real, imag } => {
+                let convert =
+                    |bits: &Vec<u16>| bits.iter().map(|&b| half::f16::from_bits(b)).collect();
+                Some((convert(real), imag.as_ref().map(convert)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a `half` object into a numeric array. Its property `codedValue`
+/// holds the bits of the numbers as a uint16 array of the same size.
+pub(crate) fn from_classdef(array: &ClassdefArray) -> Option<Array> {
+    if array.size().iter().product::<usize>() != 1 {
+        return None;
+    }
+    let coded_value = array.numeric_property(0, "codedValue")?;
+    let data = match coded_value.data() {
+        NumericData::UInt16 { real, imag } => NumericData::Half {
+            real: real.clone(),
+            imag: imag.clone(),
+        },
+        _ => return None,
+    };
+    Some(Array {
+        name: array.name().to_owned(),
+        size: coded_value.size().clone(),
+        data,
+        logical: false,
+    })
+}
+
+/// Encodes a half precision array as the properties of a `half` object, see
+/// `from_classdef`. `None` if the array doesn't hold half precision data.
+pub(crate) fn to_properties(array: &Array) -> Option<Vec<(String, Value)>> {
+    let (real, imag) = match &array.data {
+        NumericData::Half { real, imag } => (real.clone(), imag.clone()),
+        _ => return None,
+    };
+    let coded_value = Array {
+        name: String::new(),
+        size: array.size.clone(),
+        data: NumericData::UInt16 { real, imag },
+        logical: false,
+    };
+    Some(vec![("codedValue".to_owned(), Value::Numeric(coded_value))])
+}
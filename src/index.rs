@@ -0,0 +1,985 @@
+//! An index of the variables of a ".mat" file allows reading parts of them
+//! without loading the whole file.
+//!
+//! Compressed variables can only be inflated from the start, unless the
+//! index holds checkpoints for them: the position of a deflate block along
+//! with the 32 KiB of inflated data preceding it, which is all that's needed
+//! to continue inflating from there.
+//!
+//! Indices are saved as the bytes "MATINDEX" and a version number, followed
+//! by zlib compressed little-endian data.
+
+use crate::inflate::{Checkpoint, Inflater, WINDOW_SIZE};
+use crate::mcos::Subsystem;
+use crate::parse::{self, ArrayFlags, ArrayType, DataElementTag, DataType, MatrixHeader};
+use crate::{not_found, Array, Error, NumericData, RawValue, Value};
+use libflate::zlib;
+use nom::combinator::{map, map_opt, map_res, verify};
+use nom::multi::{length_count, length_data};
+use nom::number::complete::{le_i32, le_u32, le_u64, le_u8};
+use nom::sequence::tuple;
+use nom::IResult;
+use num_traits::FromPrimitive;
+use std::io::{Read, Seek, SeekFrom, Take, Write};
+use std::ops::Range;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+const MAGIC: &[u8; 8] = b"MATINDEX";
+const VERSION: u32 = 1;
+
+/// The location and layout of every variable of a ".mat" file, which allows
+/// reading parts of variables without loading the whole file.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use matfile::{Array, MatFile, MatFileIndex, NumericData, Value};
+///
+/// let data = NumericData::Double {
+///     real: (0..10_000).map(|x| x as f64).collect(),
+///     imag: None,
+/// };
+/// let array = Array::new("samples", vec![1000, 10], data)?;
+/// let path = std::env::temp_dir().join("samples_index.mat");
+/// MatFile::from_values(vec![Value::Numeric(array)])?.write(std::fs::File::create(&path)?)?;
+///
+/// let mut file = std::fs::File::open(&path)?;
+/// let index = MatFileIndex::scan(&mut file)?;
+/// // Rows 100 to 199 of the columns 5 to 7
+/// let region = index.read_region(&mut file, "samples", &[100..200, 5..8])?;
+/// assert_eq!(region.size(), &vec![100, 3]);
+/// if let NumericData::Double { real, .. } = region.data() {
+///     assert_eq!(real[0], 5100.0);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MatFileIndex {
+    is_little_endian: bool,
+    /// The positions of the subsystem data element, if there is one
+    subsystem: Option<Range<u64>>,
+    variables: Vec<VariableInfo>,
+}
+
+/// Describes a variable of a ".mat" file found by `MatFileIndex::scan`.
+#[derive(Clone, Debug)]
+pub struct VariableInfo {
+    header: MatrixHeader,
+    size: Option<Vec<usize>>,
+    /// The positions of the whole data element in the scanned reader
+    element: Range<u64>,
+    /// The position of the data following the tag of the element
+    data_position: u64,
+    compressed: bool,
+    /// Points from which compressed data can be inflated besides its start
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl MatFileIndex {
+    /// Reads the tag and the header of every variable starting at the
+    /// current position of the reader, skipping over their data. Elements
+    /// that aren't readable arrays are left out.
+    pub fn scan<R: Read + Seek>(reader: &mut R) -> Result<Self, Error> {
+        Self::scan_with(reader, None)
+    }
+
+    /// Like `scan`, but also inflates every compressed numeric array once
+    /// and records a checkpoint about every `spacing` bytes of its inflated
+    /// data, so that `read_region` can start inflating close to the region.
+    /// Every checkpoint takes up 32 KiB, a spacing of a few megabytes is a
+    /// good compromise.
+    ///
+    /// Checkpoints can only be placed between deflate blocks. Matlab ends a
+    /// block every few dozen kilobytes, but other writers might put all data
+    /// into a single block.
+    pub fn scan_with_checkpoints<R: Read + Seek>(
+        reader: &mut R,
+        spacing: usize,
+    ) -> Result<Self, Error> {
+        Self::scan_with(reader, Some(check_spacing(spacing)?))
+    }
+
+    fn scan_with<R: Read + Seek>(reader: &mut R, spacing: Option<u64>) -> Result<Self, Error> {
+        let start = reader.stream_position().map_err(Error::IOError)?;
+        let mut buf = [0; 128];
+        reader.read_exact(&mut buf).map_err(Error::IOError)?;
+        let mut scan = Scan::new(start, &buf, spacing)?;
+        loop {
+            reader
+                .seek(SeekFrom::Start(scan.position))
+                .map_err(Error::IOError)?;
+            let mut tag = Vec::new();
+            reader
+                .by_ref()
+                .take(8)
+                .read_to_end(&mut tag)
+                .map_err(Error::IOError)?;
+            let element = match scan.next_element(&tag)? {
+                Some(element) => element,
+                None => break,
+            };
+            if scan.skip_subsystem(&element) {
+                continue;
+            }
+            reader
+                .seek(SeekFrom::Start(element.data_position))
+                .map_err(Error::IOError)?;
+            let matrix_header = parse::read_matrix_header(
+                scan.endianness(),
+                element.data_type,
+                reader.by_ref().take(element.data_len),
+            );
+            let checkpoints = match scan.checkpoint_spacing(&element, matrix_header.as_ref()) {
+                Some(spacing) => {
+                    reader
+                        .seek(SeekFrom::Start(element.data_position))
+                        .map_err(Error::IOError)?;
+                    record_checkpoints(reader.by_ref().take(element.data_len), spacing)?
+                }
+                None => Vec::new(),
+            };
+            scan.push(element, matrix_header, checkpoints);
+        }
+        Ok(scan.finish())
+    }
+
+    /// Loads an index saved with `write`.
+    pub fn parse<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut prefix = [0; 12];
+        reader.read_exact(&mut prefix).map_err(Error::IOError)?;
+        if prefix[..8] != MAGIC[..] || prefix[8..] != VERSION.to_le_bytes() {
+            return Err(invalid_data());
+        }
+        let mut buf = Vec::new();
+        zlib::Decoder::new(reader)
+            .and_then(|mut decoder| decoder.read_to_end(&mut buf))
+            .map_err(Error::IOError)?;
+        let (_remaining, index) = parse_index(&buf)
+            .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
+        Ok(index)
+    }
+
+    /// Saves this index, e.g. next to the file it describes. It is only
+    /// valid for as long as that file doesn't change.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut buf = vec![self.is_little_endian as u8, self.subsystem.is_some() as u8];
+        if let Some(subsystem) = &self.subsystem {
+            buf.extend_from_slice(&subsystem.start.to_le_bytes());
+            buf.extend_from_slice(&subsystem.end.to_le_bytes());
+        }
+        buf.extend_from_slice(&(self.variables.len() as u32).to_le_bytes());
+        for variable in &self.variables {
+            variable.encode(&mut buf);
+        }
+        writer.write_all(MAGIC).map_err(Error::IOError)?;
+        writer
+            .write_all(&VERSION.to_le_bytes())
+            .map_err(Error::IOError)?;
+        let mut encoder = zlib::Encoder::new(writer).map_err(Error::IOError)?;
+        encoder.write_all(&buf).map_err(Error::IOError)?;
+        encoder.finish().into_result().map_err(Error::IOError)?;
+        Ok(())
+    }
+
+    /// All variables in the order in which they are stored.
+    pub fn variables(&self) -> &[VariableInfo] {
+        &self.variables
+    }
+
+    /// Returns the variable with the given name if it exists. Case
+    /// sensitive.
+    pub fn find_by_name(&self, name: &str) -> Option<&VariableInfo> {
+        self.variables
+            .iter()
+            .find(|variable| variable.name() == name)
+    }
+
+    /// Loads a whole variable from the reader that was scanned. Like with
+    /// `MatFile::parse`, a variable of an unsupported type is returned as a
+    /// raw value.
+    ///
+    /// Variables that might hold objects, like `string` arrays or tables,
+    /// also need the subsystem data at the end of the file, which is read
+    /// again for every such variable.
+    pub fn read_value<R: Read + Seek>(&self, reader: &mut R, name: &str) -> Result<Value, Error> {
+        let variable = self.find_by_name(name).ok_or_else(|| not_found(name))?;
+        let element = read_range(reader, variable.byte_range())?;
+        let subsystem = match self.subsystem_of(variable) {
+            Some(range) => Some(read_range(reader, range)?),
+            None => None,
+        };
+        self.decode(&element, subsystem.as_deref())
+    }
+
+    /// Reads a rectangular part of a numeric array, given by one range of
+    /// indices per dimension, from the reader that was scanned.
+    ///
+    /// Only the data of the part is read from uncompressed arrays.
+    /// Compressed arrays are inflated up to the end of the part, starting
+    /// at the closest checkpoint if there is one.
+    pub fn read_region<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        name: &str,
+        ranges: &[Range<usize>],
+    ) -> Result<Array, Error> {
+        let variable = self.find_by_name(name).ok_or_else(|| not_found(name))?;
+        let (real_tag, real_offset) = match (&variable.size, variable.header.real_part) {
+            (Some(_), Some(real_part)) => real_part,
+            _ => {
+                return Err(Error::InvalidArgument(format!(
+                    "\"{}\" is not a numeric array",
+                    name
+                )))
+            }
+        };
+        let size = variable.size.as_deref().unwrap_or_default();
+        check_region(size, ranges)?;
+        let endianness = self.endianness();
+        let num_elements = size.iter().product::<usize>();
+        let runs = runs(size, ranges);
+        let mut data = VariableData::new(reader, variable)?;
+        check_data_size(real_tag, num_elements)?;
+        let real = read_runs(
+            &mut data,
+            endianness,
+            real_tag.data_type(),
+            real_offset as u64,
+            &runs,
+        )?;
+        let imag = if variable.header.flags.complex {
+            let tag_offset = real_offset + real_tag.data_byte_size() + real_tag.padding_byte_size();
+            let mut tag = [0; 8];
+            data.read_at(tag_offset as u64, &mut tag)?;
+            let (remaining, imag_tag) = parse::parse_data_element_tag(endianness)(&tag)
+                .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
+            check_data_size(imag_tag, num_elements)?;
+            let imag_offset = tag_offset + tag.len() - remaining.len();
+            Some(read_runs(
+                &mut data,
+                endianness,
+                imag_tag.data_type(),
+                imag_offset as u64,
+                &runs,
+            )?)
+        } else {
+            None
+        };
+        Ok(Array {
+            name: variable.header.name.clone(),
+            size: ranges.iter().map(|range| range.len()).collect(),
+            data: NumericData::try_from(variable.header.flags.class, real, imag)?,
+            logical: variable.header.flags.logical,
+        })
+    }
+
+    /// The positions of the subsystem data, if the variable might need it.
+    fn subsystem_of(&self, variable: &VariableInfo) -> Option<Range<u64>> {
+        let might_hold_objects = matches!(
+            variable.header.flags.class,
+            ArrayType::Cell | ArrayType::Struct | ArrayType::Object | ArrayType::Opaque
+        );
+        self.subsystem.clone().filter(|_| might_hold_objects)
+    }
+
+    /// Decodes the data element of a variable, given with its tag, along
+    /// with the subsystem data element if the variable needs it.
+    fn decode(&self, element: &[u8], subsystem: Option<&[u8]>) -> Result<Value, Error> {
+        let endianness = self.endianness();
+        // Like `MatFile::parse`, values holding objects are kept as raw
+        // values if the subsystem data can't be read
+        let subsystem = match subsystem {
+            Some(data) => parse::parse_subsystem_element(endianness)(data)
+                .ok()
+                .and_then(|(_, subsystem)| subsystem)
+                .and_then(|subsystem| Subsystem::from_data_element(subsystem, endianness).ok())
+                .unwrap_or_default()
+                .with_element(data),
+            None => Subsystem::default(),
+        };
+        let value = match parse::parse_next_data_element(endianness)(element) {
+            Ok((_, data_element)) => Value::try_from(data_element, &subsystem)?,
+            Err(_) => None,
+        };
+        Ok(value.unwrap_or_else(|| Value::Raw(RawValue::new(element, endianness, &subsystem))))
+    }
+
+    fn endianness(&self) -> nom::number::Endianness {
+        if self.is_little_endian {
+            nom::number::Endianness::Little
+        } else {
+            nom::number::Endianness::Big
+        }
+    }
+}
+
+/// Async variants for readers of the `tokio` runtime, which share the
+/// parsing of the format with the blocking methods.
+#[cfg(feature = "tokio")]
+impl MatFileIndex {
+    /// Like `scan`, but reading asynchronously.
+    ///
+    /// ```rust
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    /// # runtime.block_on(async {
+    /// use matfile::MatFileIndex;
+    ///
+    /// let mut file = tokio::fs::File::open("tests/two_arrays.mat").await?;
+    /// let index = MatFileIndex::scan_async(&mut file).await?;
+    /// for variable in index.variables() {
+    ///     let value = index.read_value_async(&mut file, variable.name()).await?;
+    ///     println!("{:?}", value);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// # })
+    /// # }
+    /// ```
+    pub async fn scan_async<R: AsyncRead + AsyncSeek + Unpin>(
+        reader: &mut R,
+    ) -> Result<Self, Error> {
+        Self::scan_with_async(reader, None).await
+    }
+
+    /// Like `scan_with_checkpoints`, but reading asynchronously. Every
+    /// compressed numeric array is read into memory in order to record its
+    /// checkpoints.
+    pub async fn scan_with_checkpoints_async<R: AsyncRead + AsyncSeek + Unpin>(
+        reader: &mut R,
+        spacing: usize,
+    ) -> Result<Self, Error> {
+        Self::scan_with_async(reader, Some(check_spacing(spacing)?)).await
+    }
+
+    async fn scan_with_async<R: AsyncRead + AsyncSeek + Unpin>(
+        reader: &mut R,
+        spacing: Option<u64>,
+    ) -> Result<Self, Error> {
+        let start = reader.stream_position().await.map_err(Error::IOError)?;
+        let mut buf = [0; 128];
+        reader.read_exact(&mut buf).await.map_err(Error::IOError)?;
+        let mut scan = Scan::new(start, &buf, spacing)?;
+        loop {
+            reader
+                .seek(SeekFrom::Start(scan.position))
+                .await
+                .map_err(Error::IOError)?;
+            let mut tag = Vec::new();
+            (&mut *reader)
+                .take(8)
+                .read_to_end(&mut tag)
+                .await
+                .map_err(Error::IOError)?;
+            let element = match scan.next_element(&tag)? {
+                Some(element) => element,
+                None => break,
+            };
+            if scan.skip_subsystem(&element) {
+                continue;
+            }
+            reader
+                .seek(SeekFrom::Start(element.data_position))
+                .await
+                .map_err(Error::IOError)?;
+            let matrix_header =
+                read_matrix_header_async(reader, scan.endianness(), &element).await?;
+            let checkpoints = match scan.checkpoint_spacing(&element, matrix_header.as_ref()) {
+                Some(spacing) => {
+                    let data = read_range_async(
+                        reader,
+                        element.data_position..element.data_position + element.data_len,
+                    )
+                    .await?;
+                    record_checkpoints(data.as_slice(), spacing)?
+                }
+                None => Vec::new(),
+            };
+            scan.push(element, matrix_header, checkpoints);
+        }
+        Ok(scan.finish())
+    }
+
+    /// Like `read_value`, but reading asynchronously.
+    pub async fn read_value_async<R: AsyncRead + AsyncSeek + Unpin>(
+        &self,
+        reader: &mut R,
+        name: &str,
+    ) -> Result<Value, Error> {
+        let variable = self.find_by_name(name).ok_or_else(|| not_found(name))?;
+        let element = read_range_async(reader, variable.byte_range()).await?;
+        let subsystem = match self.subsystem_of(variable) {
+            Some(range) => Some(read_range_async(reader, range).await?),
+            None => None,
+        };
+        self.decode(&element, subsystem.as_deref())
+    }
+}
+
+impl VariableInfo {
+    fn new(
+        header: MatrixHeader,
+        element: Range<u64>,
+        data_position: u64,
+        compressed: bool,
+        checkpoints: Vec<Checkpoint>,
+    ) -> Self {
+        VariableInfo {
+            size: header
+                .dimensions
+                .as_ref()
+                .map(|dimensions| dimensions.iter().map(|&d| d as usize).collect()),
+            header,
+            element,
+            data_position,
+            compressed,
+            checkpoints,
+        }
+    }
+
+    /// The name of this variable.
+    pub fn name(&self) -> &str {
+        &self.header.name
+    }
+
+    /// The Matlab class of this variable, e.g. "double", "cell" or the name
+    /// of the class of an object.
+    pub fn class_name(&self) -> &str {
+        &self.header.class_name
+    }
+
+    /// The dimensions of this variable. Opaque values such as `string` or
+    /// `table` objects store their size elsewhere.
+    pub fn size(&self) -> Option<&[usize]> {
+        self.size.as_deref()
+    }
+
+    /// Whether this is an array of complex numbers.
+    pub fn is_complex(&self) -> bool {
+        self.header.flags.complex
+    }
+
+    /// Whether this variable is stored compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// The number of checkpoints recorded for this compressed variable by
+    /// `MatFileIndex::scan_with_checkpoints`.
+    pub fn num_checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// The positions of the bytes of this variable in the scanned reader,
+    /// including the tag of its data element.
+    pub fn byte_range(&self) -> Range<u64> {
+        self.element.clone()
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.element.start.to_le_bytes());
+        buf.extend_from_slice(&self.element.end.to_le_bytes());
+        buf.extend_from_slice(&self.data_position.to_le_bytes());
+        buf.push(self.compressed as u8);
+        let flags = &self.header.flags;
+        buf.push(flags.class as u8);
+        buf.push(flags.complex as u8 | (flags.global as u8) << 1 | (flags.logical as u8) << 2);
+        buf.extend_from_slice(&(flags.nzmax as u32).to_le_bytes());
+        buf.push(self.header.dimensions.is_some() as u8);
+        if let Some(dimensions) = &self.header.dimensions {
+            buf.extend_from_slice(&(dimensions.len() as u32).to_le_bytes());
+            for d in dimensions {
+                buf.extend_from_slice(&d.to_le_bytes());
+            }
+        }
+        encode_bytes(buf, self.header.name.as_bytes());
+        encode_bytes(buf, self.header.class_name.as_bytes());
+        buf.push(self.header.real_part.is_some() as u8);
+        if let Some((tag, offset)) = &self.header.real_part {
+            buf.extend_from_slice(&(tag.data_type() as u32).to_le_bytes());
+            buf.extend_from_slice(&(tag.data_byte_size() as u32).to_le_bytes());
+            buf.extend_from_slice(&(tag.padding_byte_size() as u32).to_le_bytes());
+            buf.extend_from_slice(&(*offset as u64).to_le_bytes());
+        }
+        buf.extend_from_slice(&(self.checkpoints.len() as u32).to_le_bytes());
+        for checkpoint in &self.checkpoints {
+            buf.extend_from_slice(&checkpoint.input_bits.to_le_bytes());
+            buf.extend_from_slice(&checkpoint.output.to_le_bytes());
+            encode_bytes(buf, &checkpoint.window);
+        }
+    }
+}
+
+/// The state of a scan, shared by `scan` and `scan_async` which only differ
+/// in how they read.
+struct Scan {
+    /// The position of the file header in the reader
+    start: u64,
+    header: parse::Header,
+    spacing: Option<u64>,
+    /// The position of the next data element
+    position: u64,
+    subsystem: Option<Range<u64>>,
+    variables: Vec<VariableInfo>,
+}
+
+/// A data element that might hold a variable.
+struct Element {
+    data_type: DataType,
+    range: Range<u64>,
+    data_position: u64,
+    data_len: u64,
+}
+
+impl Scan {
+    fn new(start: u64, header: &[u8], spacing: Option<u64>) -> Result<Self, Error> {
+        let (_, header) = parse::parse_header(header)
+            .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
+        Ok(Scan {
+            start,
+            header,
+            spacing,
+            position: start + 128,
+            subsystem: None,
+            variables: Vec::new(),
+        })
+    }
+
+    /// Moves on to the data element with the given tag, which is empty at
+    /// the end of the file.
+    fn next_element(&mut self, tag: &[u8]) -> Result<Option<Element>, Error> {
+        if tag.is_empty() {
+            return Ok(None);
+        }
+        let (remaining, data_element_tag) =
+            parse::parse_data_element_tag(self.endianness())(tag)
+                .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
+        let data_position = self.position + (tag.len() - remaining.len()) as u64;
+        let data_len = data_element_tag.data_byte_size() as u64;
+        let end = data_position + data_len + data_element_tag.padding_byte_size() as u64;
+        let element = Element {
+            data_type: data_element_tag.data_type(),
+            range: self.position..end,
+            data_position,
+            data_len,
+        };
+        self.position = end;
+        Ok(Some(element))
+    }
+
+    /// Records the position of the subsystem data shared by all objects,
+    /// which isn't a variable. Returns whether the element holds it.
+    fn skip_subsystem(&mut self, element: &Element) -> bool {
+        let is_subsystem = self.header.subsystem_offset() == Some(element.range.start - self.start);
+        if is_subsystem {
+            self.subsystem = Some(element.range.clone());
+        }
+        is_subsystem
+    }
+
+    /// The spacing of checkpoints to record for the element, if it is a
+    /// compressed numeric array and checkpoints were asked for.
+    fn checkpoint_spacing(&self, element: &Element, header: Option<&MatrixHeader>) -> Option<u64> {
+        let is_numeric = header.is_some_and(|header| header.real_part.is_some());
+        self.spacing
+            .filter(|_| element.data_type == DataType::Compressed && is_numeric)
+    }
+
+    /// Adds the element as a variable, unless it isn't a readable array.
+    fn push(
+        &mut self,
+        element: Element,
+        header: Option<MatrixHeader>,
+        checkpoints: Vec<Checkpoint>,
+    ) {
+        if let Some(header) = header {
+            self.variables.push(VariableInfo::new(
+                header,
+                element.range,
+                element.data_position,
+                element.data_type == DataType::Compressed,
+                checkpoints,
+            ));
+        }
+    }
+
+    fn endianness(&self) -> nom::number::Endianness {
+        self.header.endianness()
+    }
+
+    fn finish(self) -> MatFileIndex {
+        MatFileIndex {
+            is_little_endian: self.header.is_little_endian(),
+            subsystem: self.subsystem,
+            variables: self.variables,
+        }
+    }
+}
+
+/// Inflates compressed data once to record a checkpoint about every
+/// `spacing` bytes of inflated data.
+fn record_checkpoints<R: Read>(data: R, spacing: u64) -> Result<Vec<Checkpoint>, Error> {
+    let mut inflater = Inflater::zlib(data).map_err(Error::IOError)?;
+    inflater.record_checkpoints(spacing);
+    std::io::copy(&mut inflater, &mut std::io::sink()).map_err(Error::IOError)?;
+    Ok(inflater.into_checkpoints())
+}
+
+/// Reads the bytes at the given positions. The padding at the end of the
+/// last data element might be missing.
+fn read_range<R: Read + Seek>(reader: &mut R, range: Range<u64>) -> Result<Vec<u8>, Error> {
+    reader
+        .seek(SeekFrom::Start(range.start))
+        .map_err(Error::IOError)?;
+    let mut buf = Vec::new();
+    reader
+        .take(range.end - range.start)
+        .read_to_end(&mut buf)
+        .map_err(Error::IOError)?;
+    Ok(buf)
+}
+
+/// Reads just enough of the data of an element to parse the header of the
+/// array it holds. Compressed data is read in growing steps until enough of
+/// it can be inflated.
+#[cfg(feature = "tokio")]
+async fn read_matrix_header_async<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    endianness: nom::number::Endianness,
+    element: &Element,
+) -> Result<Option<MatrixHeader>, Error> {
+    let mut prefix = Vec::new();
+    let mut len = element.data_len.min(4096);
+    loop {
+        (&mut *reader)
+            .take(len - prefix.len() as u64)
+            .read_to_end(&mut prefix)
+            .await
+            .map_err(Error::IOError)?;
+        let header = parse::read_matrix_header(endianness, element.data_type, prefix.as_slice());
+        if header.is_some() || (prefix.len() as u64) < len || len == element.data_len {
+            return Ok(header);
+        }
+        len = element.data_len.min(2 * len);
+    }
+}
+
+#[cfg(feature = "tokio")]
+async fn read_range_async<R: AsyncRead + AsyncSeek + Unpin>(
+    reader: &mut R,
+    range: Range<u64>,
+) -> Result<Vec<u8>, Error> {
+    reader
+        .seek(SeekFrom::Start(range.start))
+        .await
+        .map_err(Error::IOError)?;
+    let mut buf = Vec::new();
+    reader
+        .take(range.end - range.start)
+        .read_to_end(&mut buf)
+        .await
+        .map_err(Error::IOError)?;
+    Ok(buf)
+}
+
+fn check_spacing(spacing: usize) -> Result<u64, Error> {
+    if spacing == 0 {
+        return Err(Error::InvalidArgument(
+            "the spacing of checkpoints has to be positive".to_owned(),
+        ));
+    }
+    Ok(spacing as u64)
+}
+
+fn encode_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn parse_index(i: &[u8]) -> IResult<&[u8], MatFileIndex> {
+    let (i, is_little_endian) = map(le_u8, |b| b != 0)(i)?;
+    let (i, subsystem) = parse_option(map(tuple((le_u64, le_u64)), |(start, end)| start..end))(i)?;
+    let (i, variables) = length_count(le_u32, parse_variable)(i)?;
+    Ok((
+        i,
+        MatFileIndex {
+            is_little_endian,
+            subsystem,
+            variables,
+        },
+    ))
+}
+
+/// Parses a variable, checking that its positions are consistent so that
+/// a corrupt or stale index is an error instead of a panic later on.
+fn parse_variable(i: &[u8]) -> IResult<&[u8], VariableInfo> {
+    let (i, (start, end, data_position, compressed)) = verify(
+        tuple((le_u64, le_u64, le_u64, map(le_u8, |b| b != 0))),
+        |&(start, end, data_position, _)| start <= data_position && data_position <= end,
+    )(i)?;
+    let (i, class) = map_opt(le_u8, ArrayType::from_u8)(i)?;
+    let (i, (flags, nzmax)) = tuple((le_u8, le_u32))(i)?;
+    let (i, dimensions) = parse_option(verify(
+        length_count(le_u32, verify(le_i32, |&d| d >= 0)),
+        |dimensions: &Vec<i32>| {
+            dimensions
+                .iter()
+                .try_fold(1usize, |product, &d| product.checked_mul(d as usize))
+                .is_some()
+        },
+    ))(i)?;
+    let (i, name) = parse_string(i)?;
+    let (i, class_name) = parse_string(i)?;
+    // The data of a compressed element can be inflated to at most the size
+    // of the largest data element
+    let data_len = if compressed {
+        8 + u32::MAX as u64
+    } else {
+        end - data_position
+    };
+    let (i, real_part) = parse_option(map(
+        verify(
+            tuple((map_opt(le_u32, DataType::from_u32), le_u32, le_u32, le_u64)),
+            |&(_, data_byte_size, padding_byte_size, offset)| {
+                offset
+                    .checked_add(data_byte_size as u64 + padding_byte_size as u64)
+                    .is_some_and(|end| end <= data_len)
+            },
+        ),
+        |(data_type, data_byte_size, padding_byte_size, offset)| {
+            (
+                DataElementTag::new(data_type, data_byte_size, padding_byte_size),
+                offset as usize,
+            )
+        },
+    ))(i)?;
+    let (i, checkpoints) = length_count(
+        le_u32,
+        map(
+            verify(
+                tuple((le_u64, le_u64, length_data(le_u32))),
+                |&(input_bits, _, window): &(u64, u64, &[u8])| {
+                    input_bits / 8 <= end - data_position && window.len() <= WINDOW_SIZE
+                },
+            ),
+            |(input_bits, output, window): (u64, u64, &[u8])| Checkpoint {
+                input_bits,
+                output,
+                window: window.to_vec(),
+            },
+        ),
+    )(i)?;
+    let header = MatrixHeader {
+        flags: ArrayFlags {
+            complex: flags & 1 != 0,
+            global: flags & 2 != 0,
+            logical: flags & 4 != 0,
+            class,
+            nzmax: nzmax as usize,
+        },
+        dimensions,
+        name,
+        class_name,
+        real_part,
+    };
+    Ok((
+        i,
+        VariableInfo::new(header, start..end, data_position, compressed, checkpoints),
+    ))
+}
+
+fn parse_option<'a, O>(
+    mut parser: impl FnMut(&'a [u8]) -> IResult<&'a [u8], O>,
+) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], Option<O>> {
+    move |i: &'a [u8]| {
+        let (i, is_some) = le_u8(i)?;
+        if is_some == 0 {
+            return Ok((i, None));
+        }
+        let (i, value) = parser(i)?;
+        Ok((i, Some(value)))
+    }
+}
+
+fn parse_string(i: &[u8]) -> IResult<&[u8], String> {
+    map_res(length_data(le_u32), |bytes: &[u8]| {
+        String::from_utf8(bytes.to_vec())
+    })(i)
+}
+
+/// Reads the data of a variable at arbitrary offsets. Compressed data is
+/// inflated from the closest checkpoint before the offset, unless the data
+/// inflated for the previous read ended closer to it.
+enum VariableData<'a, R> {
+    Uncompressed {
+        reader: &'a mut R,
+        position: u64,
+    },
+    Compressed {
+        variable: &'a VariableInfo,
+        inflater: Option<Inflater<Take<&'a mut R>>>,
+    },
+}
+
+impl<'a, R: Read + Seek> VariableData<'a, R> {
+    fn new(reader: &'a mut R, variable: &'a VariableInfo) -> Result<Self, Error> {
+        if variable.compressed {
+            Ok(VariableData::Compressed {
+                variable,
+                inflater: Some(start_inflater(reader, variable, None)?),
+            })
+        } else {
+            Ok(VariableData::Uncompressed {
+                reader,
+                position: variable.data_position,
+            })
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        match self {
+            VariableData::Uncompressed { reader, position } => {
+                reader
+                    .seek(SeekFrom::Start(*position + offset))
+                    .map_err(Error::IOError)?;
+                reader.read_exact(buf).map_err(Error::IOError)
+            }
+            VariableData::Compressed { variable, inflater } => {
+                let mut current = inflater.take().ok_or(Error::InternalError)?;
+                let checkpoint = variable
+                    .checkpoints
+                    .iter()
+                    .rev()
+                    .find(|checkpoint| checkpoint.output <= offset);
+                let position = current.position();
+                if position > offset
+                    || checkpoint.is_some_and(|checkpoint| checkpoint.output > position)
+                {
+                    let reader = current.into_inner().into_inner();
+                    current = start_inflater(reader, variable, checkpoint)?;
+                }
+                let skip = offset - current.position();
+                let skipped = std::io::copy(&mut current.by_ref().take(skip), &mut std::io::sink())
+                    .map_err(Error::IOError)?;
+                if skipped < skip {
+                    return Err(invalid_data());
+                }
+                current.read_exact(buf).map_err(Error::IOError)?;
+                *inflater = Some(current);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn start_inflater<'a, R: Read + Seek>(
+    reader: &'a mut R,
+    variable: &VariableInfo,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<Inflater<Take<&'a mut R>>, Error> {
+    let skip = checkpoint.map_or(0, |checkpoint| checkpoint.input_bits / 8);
+    reader
+        .seek(SeekFrom::Start(variable.data_position + skip))
+        .map_err(Error::IOError)?;
+    let data = reader.take(variable.element.end - variable.data_position - skip);
+    match checkpoint {
+        Some(checkpoint) => Inflater::resume(data, checkpoint),
+        None => Inflater::zlib(data),
+    }
+    .map_err(Error::IOError)
+}
+
+fn invalid_data() -> Error {
+    Error::ParseError(nom::Err::Failure(nom::error::Error::new(
+        &[],
+        nom::error::ErrorKind::Tag,
+    )))
+}
+
+fn check_region(size: &[usize], ranges: &[Range<usize>]) -> Result<(), Error> {
+    if ranges.len() != size.len() {
+        return Err(Error::InvalidArgument(format!(
+            "expected {} ranges for an array of size {:?}, got {}",
+            size.len(),
+            size,
+            ranges.len()
+        )));
+    }
+    match ranges
+        .iter()
+        .zip(size)
+        .find(|(range, &d)| range.start > range.end || range.end > d)
+    {
+        Some((range, _)) => Err(Error::InvalidArgument(format!(
+            "the range {:?} is out of bounds for an array of size {:?}",
+            range, size
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Makes sure that a numeric subelement holds one number per element, so
+/// that the position of every number is known.
+fn check_data_size(tag: DataElementTag, num_elements: usize) -> Result<(), Error> {
+    match tag.data_type().byte_size() {
+        Some(byte_size) if Some(tag.data_byte_size()) == num_elements.checked_mul(byte_size) => {
+            Ok(())
+        }
+        _ => Err(invalid_data()),
+    }
+}
+
+/// The runs of consecutive elements that make up a region of a column-major
+/// array, as the index of their first element and their length.
+fn runs(size: &[usize], ranges: &[Range<usize>]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    if ranges.iter().any(|range| range.is_empty()) {
+        return runs;
+    }
+    let mut indices: Vec<usize> = ranges.iter().map(|range| range.start).collect();
+    loop {
+        let mut start = 0;
+        let mut stride = 1;
+        for (index, d) in indices.iter().zip(size) {
+            start += index * stride;
+            stride *= d;
+        }
+        match runs.last_mut() {
+            Some((run_start, len)) if *run_start + *len == start => *len += ranges[0].len(),
+            _ => runs.push((start, ranges[0].len())),
+        }
+        // Advance to the next run like an odometer, skipping the first
+        // dimension which the runs extend along
+        let mut dimension = 1;
+        loop {
+            if dimension == ranges.len() {
+                return runs;
+            }
+            indices[dimension] += 1;
+            if indices[dimension] < ranges[dimension].end {
+                break;
+            }
+            indices[dimension] = ranges[dimension].start;
+            dimension += 1;
+        }
+    }
+}
+
+fn read_runs<R: Read + Seek>(
+    data: &mut VariableData<R>,
+    endianness: nom::number::Endianness,
+    data_type: DataType,
+    offset: u64,
+    runs: &[(usize, usize)],
+) -> Result<parse::NumericData, Error> {
+    let byte_size = data_type.byte_size().ok_or_else(invalid_data)?;
+    let mut buf = Vec::new();
+    for &(start, len) in runs {
+        let end = buf.len();
+        buf.resize(end + len * byte_size, 0);
+        data.read_at(offset + (start * byte_size) as u64, &mut buf[end..])?;
+    }
+    let (_, numbers) = parse::parse_numeric_values(endianness, data_type, buf.len())(&buf)
+        .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
+    Ok(numbers)
+}
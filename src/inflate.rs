@@ -0,0 +1,531 @@
+use std::io::{self, Read};
+
+/// The distance of back references is limited to 32 KiB, so this much
+/// decoded data is enough to continue decoding at a block boundary.
+pub(crate) const WINDOW_SIZE: usize = 32 * 1024;
+
+/// The amount of data decoded at once.
+const CHUNK_SIZE: usize = 32 * 1024;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order in which the lengths of the code length code are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// A point at the start of a deflate block from which decoding can resume.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Checkpoint {
+    /// The position of the block in the input, in bits
+    pub(crate) input_bits: u64,
+    /// The number of bytes decoded before the block
+    pub(crate) output: u64,
+    /// The decoded data preceding the block, at most `WINDOW_SIZE` bytes
+    pub(crate) window: Vec<u8>,
+}
+
+/// Decoder for zlib and raw deflate streams (RFC 1950 and 1951).
+///
+/// Unlike libflate's decoders, it can start decoding at any block boundary
+/// of a stream given a `Checkpoint`, and it can record checkpoints while
+/// decoding a whole stream. The checksum of zlib streams is not verified.
+pub(crate) struct Inflater<R> {
+    input: BitReader<R>,
+    /// The decoded data, of which at least the last `WINDOW_SIZE` bytes are
+    /// kept for back references
+    history: Vec<u8>,
+    /// The position in `history` of the first byte not returned by `read`
+    unread: usize,
+    /// The number of bytes decoded up to the end of `history`
+    output: u64,
+    state: State,
+    recorder: Option<Recorder>,
+}
+
+enum State {
+    BlockStart,
+    Stored {
+        remaining: usize,
+        last: bool,
+    },
+    Huffman {
+        codes: Box<(Huffman, Huffman)>,
+        last: bool,
+    },
+    Done,
+}
+
+struct Recorder {
+    spacing: u64,
+    next: u64,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl<R: Read> Inflater<R> {
+    /// Starts decoding a zlib stream.
+    pub(crate) fn zlib(reader: R) -> io::Result<Self> {
+        let mut input = BitReader::new(reader, 0);
+        let method = input.read_bits(8)?;
+        let flags = input.read_bits(8)?;
+        if method & 0x0F != 8 || (method << 8 | flags) % 31 != 0 || flags & 0x20 != 0 {
+            return Err(invalid_data("invalid zlib header"));
+        }
+        Ok(Self::from_parts(input, Vec::new(), 0))
+    }
+
+    /// Continues decoding at a checkpoint. The reader has to be positioned
+    /// at the byte holding the first bit of the block.
+    pub(crate) fn resume(reader: R, checkpoint: &Checkpoint) -> io::Result<Self> {
+        let mut input = BitReader::new(reader, checkpoint.input_bits / 8 * 8);
+        input.read_bits((checkpoint.input_bits % 8) as u32)?;
+        Ok(Self::from_parts(
+            input,
+            checkpoint.window.clone(),
+            checkpoint.output,
+        ))
+    }
+
+    fn from_parts(input: BitReader<R>, history: Vec<u8>, output: u64) -> Self {
+        Inflater {
+            input,
+            unread: history.len(),
+            history,
+            output,
+            state: State::BlockStart,
+            recorder: None,
+        }
+    }
+
+    /// Records a checkpoint at the first block boundary after every
+    /// `spacing` bytes of decoded data.
+    pub(crate) fn record_checkpoints(&mut self, spacing: u64) {
+        self.recorder = Some(Recorder {
+            spacing,
+            next: self.output + spacing,
+            checkpoints: Vec::new(),
+        });
+    }
+
+    pub(crate) fn into_checkpoints(self) -> Vec<Checkpoint> {
+        self.recorder
+            .map_or_else(Vec::new, |recorder| recorder.checkpoints)
+    }
+
+    /// The number of bytes decoded before the next byte returned by `read`.
+    pub(crate) fn position(&self) -> u64 {
+        self.output - (self.history.len() - self.unread) as u64
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.input.reader
+    }
+
+    /// Decodes the next piece of data.
+    fn inflate(&mut self) -> io::Result<()> {
+        // Only the window is needed once everything has been read
+        if self.history.len() > 2 * WINDOW_SIZE {
+            self.history.drain(..self.history.len() - WINDOW_SIZE);
+            self.unread = self.history.len();
+        }
+        let len = self.history.len();
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::BlockStart => self.start_block()?,
+            State::Stored { remaining, last } => {
+                let num_bytes = remaining.min(CHUNK_SIZE);
+                for _ in 0..num_bytes {
+                    let byte = self.input.read_bits(8)? as u8;
+                    self.history.push(byte);
+                }
+                self.state = match remaining - num_bytes {
+                    0 if last => State::Done,
+                    0 => State::BlockStart,
+                    remaining => State::Stored { remaining, last },
+                };
+            }
+            State::Huffman { codes, last } => {
+                self.state = if !self.inflate_codes(&codes.0, &codes.1)? {
+                    State::Huffman { codes, last }
+                } else if last {
+                    State::Done
+                } else {
+                    State::BlockStart
+                };
+            }
+            State::Done => (),
+        }
+        self.output += (self.history.len() - len) as u64;
+        Ok(())
+    }
+
+    fn start_block(&mut self) -> io::Result<()> {
+        if let Some(recorder) = &mut self.recorder {
+            if self.output >= recorder.next {
+                let start = self.history.len().saturating_sub(WINDOW_SIZE);
+                recorder.checkpoints.push(Checkpoint {
+                    input_bits: self.input.position,
+                    output: self.output,
+                    window: self.history[start..].to_vec(),
+                });
+                recorder.next = self.output + recorder.spacing;
+            }
+        }
+        let last = self.input.read_bits(1)? == 1;
+        self.state = match self.input.read_bits(2)? {
+            0 => {
+                self.input.align();
+                let len = self.input.read_bits(16)?;
+                let inverted_len = self.input.read_bits(16)?;
+                if len != !inverted_len & 0xFFFF {
+                    return Err(invalid_data("invalid stored block length"));
+                }
+                match len {
+                    0 if last => State::Done,
+                    0 => State::BlockStart,
+                    len => State::Stored {
+                        remaining: len as usize,
+                        last,
+                    },
+                }
+            }
+            1 => State::Huffman {
+                codes: Box::new(fixed_codes()?),
+                last,
+            },
+            2 => State::Huffman {
+                codes: Box::new(self.read_dynamic_codes()?),
+                last,
+            },
+            _ => return Err(invalid_data("invalid block type")),
+        };
+        Ok(())
+    }
+
+    fn read_dynamic_codes(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let num_literal_codes = self.input.read_bits(5)? as usize + 257;
+        let num_distance_codes = self.input.read_bits(5)? as usize + 1;
+        let num_code_length_codes = self.input.read_bits(4)? as usize + 4;
+        if num_literal_codes > 286 || num_distance_codes > 30 {
+            return Err(invalid_data("too many length or distance codes"));
+        }
+        let mut code_length_lengths = [0; 19];
+        for &symbol in CODE_LENGTH_ORDER.iter().take(num_code_length_codes) {
+            code_length_lengths[symbol] = self.input.read_bits(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&code_length_lengths)?;
+        let mut lengths = Vec::with_capacity(num_literal_codes + num_distance_codes);
+        while lengths.len() < num_literal_codes + num_distance_codes {
+            let (length, repeat) = match code_length_code.decode(&mut self.input)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => match lengths.last() {
+                    Some(&previous) => (previous, 3 + self.input.read_bits(2)?),
+                    None => return Err(invalid_data("repeated length without a first length")),
+                },
+                17 => (0, 3 + self.input.read_bits(3)?),
+                _ => (0, 11 + self.input.read_bits(7)?),
+            };
+            if lengths.len() + repeat as usize > num_literal_codes + num_distance_codes {
+                return Err(invalid_data("too many code lengths"));
+            }
+            lengths.extend(std::iter::repeat(length).take(repeat as usize));
+        }
+        if lengths[256] == 0 {
+            return Err(invalid_data("missing end of block code"));
+        }
+        Ok((
+            Huffman::new(&lengths[..num_literal_codes])?,
+            Huffman::new(&lengths[num_literal_codes..])?,
+        ))
+    }
+
+    /// Decodes up to `CHUNK_SIZE` bytes of a compressed block and returns
+    /// whether the end of the block was reached.
+    fn inflate_codes(
+        &mut self,
+        literal_code: &Huffman,
+        distance_code: &Huffman,
+    ) -> io::Result<bool> {
+        let end = self.history.len() + CHUNK_SIZE;
+        while self.history.len() < end {
+            let symbol = literal_code.decode(&mut self.input)? as usize;
+            if symbol < 256 {
+                self.history.push(symbol as u8);
+                continue;
+            } else if symbol == 256 {
+                return Ok(true);
+            } else if symbol > 285 {
+                return Err(invalid_data("invalid length code"));
+            }
+            let length = LENGTH_BASE[symbol - 257] as usize
+                + self.input.read_bits(LENGTH_EXTRA[symbol - 257] as u32)? as usize;
+            let symbol = distance_code.decode(&mut self.input)? as usize;
+            if symbol >= 30 {
+                return Err(invalid_data("invalid distance code"));
+            }
+            let distance = DISTANCE_BASE[symbol] as usize
+                + self.input.read_bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+            if distance > self.history.len() {
+                return Err(invalid_data("distance too far back"));
+            }
+            let start = self.history.len() - distance;
+            if distance >= length {
+                self.history.extend_from_within(start..start + length);
+            } else {
+                // The copied data overlaps the data being written
+                for i in start..start + length {
+                    let byte = self.history[i];
+                    self.history.push(byte);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<R: Read> Read for Inflater<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.unread == self.history.len() {
+            if let State::Done = self.state {
+                return Ok(0);
+            }
+            self.inflate()?;
+        }
+        let num_bytes = buf.len().min(self.history.len() - self.unread);
+        buf[..num_bytes].copy_from_slice(&self.history[self.unread..self.unread + num_bytes]);
+        self.unread += num_bytes;
+        Ok(num_bytes)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the bits of a deflate stream, starting with the least significant
+/// bit of every byte.
+struct BitReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    buf_start: usize,
+    bits: u64,
+    num_bits: u32,
+    /// The number of bits consumed from the start of the input
+    position: u64,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(reader: R, position: u64) -> Self {
+        BitReader {
+            reader,
+            buf: Vec::new(),
+            buf_start: 0,
+            bits: 0,
+            num_bits: 0,
+            position,
+        }
+    }
+
+    fn refill(&mut self) -> io::Result<()> {
+        while self.num_bits <= 56 {
+            if self.buf_start == self.buf.len() {
+                self.buf.resize(4096, 0);
+                let len = loop {
+                    match self.reader.read(&mut self.buf) {
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                        result => break result?,
+                    }
+                };
+                self.buf.truncate(len);
+                self.buf_start = 0;
+                if len == 0 {
+                    break;
+                }
+            }
+            self.bits |= (self.buf[self.buf_start] as u64) << self.num_bits;
+            self.buf_start += 1;
+            self.num_bits += 8;
+        }
+        Ok(())
+    }
+
+    /// The next bits without consuming them. Missing bits at the end of the
+    /// input are zero.
+    fn peek(&mut self, num_bits: u32) -> io::Result<u32> {
+        if self.num_bits < num_bits {
+            self.refill()?;
+        }
+        Ok((self.bits & ((1 << num_bits) - 1)) as u32)
+    }
+
+    fn consume(&mut self, num_bits: u32) -> io::Result<()> {
+        if num_bits > self.num_bits {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.bits >>= num_bits;
+        self.num_bits -= num_bits;
+        self.position += num_bits as u64;
+        Ok(())
+    }
+
+    fn read_bits(&mut self, num_bits: u32) -> io::Result<u32> {
+        let bits = self.peek(num_bits)?;
+        self.consume(num_bits)?;
+        Ok(bits)
+    }
+
+    /// Skips to the next byte boundary.
+    fn align(&mut self) {
+        let num_bits = ((8 - self.position % 8) % 8) as u32;
+        // Whole bytes are always loaded, so these bits are available
+        self.bits >>= num_bits;
+        self.num_bits -= num_bits;
+        self.position += num_bits as u64;
+    }
+}
+
+/// A canonical Huffman code, decoded with a table of all possible values of
+/// the next `max_length` bits.
+struct Huffman {
+    /// The symbol and the length of its code
+    table: Vec<(u16, u8)>,
+    max_length: u32,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let max_length = lengths.iter().copied().max().unwrap_or(0) as u32;
+        let mut counts = [0u32; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        // Incomplete codes are allowed, codes with too many symbols are not
+        let mut left = 1i64;
+        for &count in counts.iter().skip(1) {
+            left = 2 * left - count as i64;
+            if left < 0 {
+                return Err(invalid_data("invalid Huffman code"));
+            }
+        }
+        counts[0] = 0;
+        let mut next_code = [0u32; 16];
+        let mut code = 0;
+        for length in 1..16 {
+            code = (code + counts[length - 1]) << 1;
+            next_code[length] = code;
+        }
+        let mut table = vec![(0, 0); 1 << max_length];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            // Codes are stored starting with their most significant bit
+            let reversed = code.reverse_bits() >> (32 - length as u32);
+            for index in (reversed as usize..table.len()).step_by(1 << length) {
+                table[index] = (symbol as u16, length);
+            }
+        }
+        Ok(Huffman { table, max_length })
+    }
+
+    fn decode<R: Read>(&self, input: &mut BitReader<R>) -> io::Result<u16> {
+        let (symbol, length) = self.table[input.peek(self.max_length)? as usize];
+        if length == 0 {
+            return Err(invalid_data("invalid Huffman code"));
+        }
+        input.consume(length as u32)?;
+        Ok(symbol)
+    }
+}
+
+fn fixed_codes() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use libflate::lz77::DefaultLz77Encoder;
+    use libflate::zlib::{EncodeOptions, Encoder};
+    use std::io::Write;
+
+    type Options = EncodeOptions<DefaultLz77Encoder>;
+
+    fn sample_data() -> Vec<u8> {
+        (0..300_000u32)
+            .map(|i| ((i.wrapping_mul(i) >> 7) ^ (i / 1000)) as u8)
+            .collect()
+    }
+
+    fn compress(data: &[u8], options: fn(Options) -> Options) -> Vec<u8> {
+        let options = options(EncodeOptions::new().block_size(16 * 1024));
+        let mut encoder = Encoder::with_options(Vec::new(), options).unwrap();
+        for chunk in data.chunks(4096) {
+            encoder.write_all(chunk).unwrap();
+        }
+        encoder.finish().into_result().unwrap()
+    }
+
+    #[test]
+    fn inflate() {
+        let data = sample_data();
+        let options: [fn(Options) -> Options; 3] = [
+            |options| options,
+            |options| options.fixed_huffman_codes(),
+            |options| options.no_compression(),
+        ];
+        for &options in options.iter() {
+            let compressed = compress(&data, options);
+            let mut inflater = Inflater::zlib(compressed.as_slice()).unwrap();
+            let mut inflated = Vec::new();
+            inflater.read_to_end(&mut inflated).unwrap();
+            assert_eq!(inflated, data);
+        }
+    }
+
+    #[test]
+    fn resume_at_checkpoints() {
+        let data = sample_data();
+        let compressed = compress(&data, |options| options);
+        let mut inflater = Inflater::zlib(compressed.as_slice()).unwrap();
+        inflater.record_checkpoints(50_000);
+        std::io::copy(&mut inflater, &mut std::io::sink()).unwrap();
+        let checkpoints = inflater.into_checkpoints();
+        assert!(checkpoints.len() >= 4);
+        for checkpoint in &checkpoints {
+            let input = &compressed[checkpoint.input_bits as usize / 8..];
+            let mut inflater = Inflater::resume(input, checkpoint).unwrap();
+            assert_eq!(inflater.position(), checkpoint.output);
+            let mut inflated = Vec::new();
+            inflater.read_to_end(&mut inflated).unwrap();
+            assert_eq!(inflated, &data[checkpoint.output as usize..]);
+        }
+    }
+
+    #[test]
+    fn invalid_streams() {
+        let compressed = compress(&sample_data(), |options| options);
+        assert!(Inflater::zlib(&compressed[1..]).is_err());
+        let mut inflater = Inflater::zlib(&compressed[..compressed.len() / 2]).unwrap();
+        assert!(inflater.read_to_end(&mut Vec::new()).is_err());
+    }
+}
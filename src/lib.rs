@@ -1,21 +1,38 @@
 #![doc(html_root_url = "https://docs.rs/matfile/0.5.0")]
 
-//! Matfile is a library for reading (and in the future writing) Matlab ".mat" files.
+//! Matfile is a library for reading and writing Matlab ".mat" files.
 //!
 //! __Please note__: This library is still alpha quality software and only implements a subset of the features supported by .mat files.
 //!
 //! ## Feature Status
 //!
-//! Matfile currently allows you to load numeric arrays from .mat files (all floating point and integer types, including complex numbers). All other types are currently ignored.
+//! Matfile currently allows you to load numeric arrays from .mat files (all floating point and integer types, including complex numbers) as well as sparse, character, cell, structure and object arrays and function handles. All other types are kept as raw values, which are written back unchanged. Numeric (including half precision), logical, character, sparse, cell, structure, object, string, categorical, datetime and duration arrays as well as tables, `containers.Map` objects and function handles can be written to .mat files.
 //!
 //! * [ ] Loading .mat files
 //!   * [x] Numeric arrays
-//!   * [ ] Cell arrays
-//!   * [ ] Structure arrays
-//!   * [ ] Object arrays
-//!   * [ ] Character arrays
-//!   * [ ] Sparse arrays
+//!   * [x] Cell arrays
+//!   * [x] Structure arrays
+//!   * [x] Object arrays (including `classdef` objects)
+//!   * [x] Character arrays
+//!   * [x] String arrays
+//!   * [x] `datetime` and `duration` arrays
+//!   * [x] Tables and timetables
+//!   * [x] Categorical arrays
+//!   * [x] `containers.Map` objects
+//!   * [x] Half precision arrays
+//!   * [x] Sparse arrays
+//!   * [x] Function handles
 //! * [ ] Writing .mat files
+//!   * [x] Numeric arrays
+//!   * [x] Character arrays
+//!   * [x] Logical arrays
+//!   * [x] Sparse arrays
+//!   * [x] Cell arrays
+//!   * [x] Structure arrays
+//!   * [x] Object arrays (including `classdef` objects)
+//!   * [x] String arrays
+//!   * [x] Tables
+//!   * [x] Categorical arrays
 //!
 //! ## Examples
 //!
@@ -49,7 +66,8 @@
 //!                 10.0
 //!             ],
 //!             imag: None
-//!         }
+//!         },
+//!         logical: false
 //!     }
 //! )
 //! ```
@@ -58,15 +76,52 @@
 //! The following crate feature flags can be enabled in your Cargo.toml:
 //! * `ndarray`
 //!   * Enable conversions between Matfile and `ndarray` array types
+//! * `chrono`
+//!   * Enable conversions of `datetime` and `duration` arrays to `chrono` types
+//! * `half`
+//!   * Enable conversions of half precision arrays to `half::f16`
+//! * `tokio`
+//!   * Enable async variants of parsing, writing and reading variables via
+//!     `MatFileIndex` for `tokio` readers and writers
 
 #[macro_use]
 extern crate enum_primitive_derive;
 
+mod append;
+mod categorical;
+mod datetime;
+mod function_handle;
+mod half_precision;
+mod index;
+mod inflate;
+mod map;
+mod mcos;
 #[cfg(feature = "ndarray")]
 pub mod ndarray;
 mod parse;
+mod sparse;
+mod stream;
+mod string_array;
+mod table;
+mod value;
+mod write;
+
+pub use categorical::CategoricalArray;
+pub use datetime::{DatetimeArray, DurationArray};
+pub use function_handle::{FunctionHandle, FunctionHandleKind};
+pub use index::{MatFileIndex, VariableInfo};
+pub use map::{Map, MapKey};
+pub use mcos::ClassdefArray;
+pub use sparse::SparseArray;
+pub use stream::{ArrayWriter, MatFileReader, MatFileWriter};
+pub use string_array::StringArray;
+pub use table::{Table, TableKind};
+pub use value::{CellArray, CharArray, ObjectArray, RawValue, StructArray, Value};
+pub use write::{CharEncoding, WriteOptions};
 
-/// MatFile is a collection of named arrays.
+use std::collections::HashMap;
+
+/// MatFile is a collection of named values.
 ///
 /// You can load a ".mat" file from disk like this:
 /// ```rust
@@ -76,12 +131,22 @@ mod parse;
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Clone, Default)]
 pub struct MatFile {
-    arrays: Vec<Array>,
+    values: Vec<Value>,
+    /// The position of the (first) value with each name
+    index: HashMap<String, usize>,
+}
+
+impl std::fmt::Debug for MatFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MatFile")
+            .field("values", &self.values)
+            .finish()
+    }
 }
 
-/// A numeric array (the only type supported at the moment).
+/// A numeric array.
 ///
 /// You can access the arrays of a MatFile either by name or by iterating
 /// through all of them:
@@ -104,6 +169,7 @@ pub struct Array {
     name: String,
     size: Vec<usize>,
     data: NumericData,
+    logical: bool,
 }
 
 /// Stores the data of a numerical array and abstracts over the actual data
@@ -155,6 +221,12 @@ pub enum NumericData {
         real: Vec<f64>,
         imag: Option<Vec<f64>>,
     },
+    /// Half precision floating point numbers, stored as their raw IEEE 754
+    /// binary16 bits. See `NumericData::half_to_f32` for a conversion.
+    Half {
+        real: Vec<u16>,
+        imag: Option<Vec<u16>>,
+    },
 }
 
 fn try_convert_number_format(
@@ -291,107 +363,159 @@ impl NumericData {
         };
         // The next step should never fail unless there is a bug in the code
         match (real, imag) {
-            (parse::NumericData::Double(real), None) => Ok(NumericData::Double {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::Double(real), None) => {
+                Ok(NumericData::Double { real, imag: None })
+            }
             (parse::NumericData::Double(real), Some(parse::NumericData::Double(imag))) => {
                 Ok(NumericData::Double {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::Single(real), None) => Ok(NumericData::Single {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::Single(real), None) => {
+                Ok(NumericData::Single { real, imag: None })
+            }
             (parse::NumericData::Single(real), Some(parse::NumericData::Single(imag))) => {
                 Ok(NumericData::Single {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::UInt64(real), None) => Ok(NumericData::UInt64 {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::UInt64(real), None) => {
+                Ok(NumericData::UInt64 { real, imag: None })
+            }
             (parse::NumericData::UInt64(real), Some(parse::NumericData::UInt64(imag))) => {
                 Ok(NumericData::UInt64 {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::Int64(real), None) => Ok(NumericData::Int64 {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::Int64(real), None) => Ok(NumericData::Int64 { real, imag: None }),
             (parse::NumericData::Int64(real), Some(parse::NumericData::Int64(imag))) => {
                 Ok(NumericData::Int64 {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::UInt32(real), None) => Ok(NumericData::UInt32 {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::UInt32(real), None) => {
+                Ok(NumericData::UInt32 { real, imag: None })
+            }
             (parse::NumericData::UInt32(real), Some(parse::NumericData::UInt32(imag))) => {
                 Ok(NumericData::UInt32 {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::Int32(real), None) => Ok(NumericData::Int32 {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::Int32(real), None) => Ok(NumericData::Int32 { real, imag: None }),
             (parse::NumericData::Int32(real), Some(parse::NumericData::Int32(imag))) => {
                 Ok(NumericData::Int32 {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::UInt16(real), None) => Ok(NumericData::UInt16 {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::UInt16(real), None) => {
+                Ok(NumericData::UInt16 { real, imag: None })
+            }
             (parse::NumericData::UInt16(real), Some(parse::NumericData::UInt16(imag))) => {
                 Ok(NumericData::UInt16 {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::Int16(real), None) => Ok(NumericData::Int16 {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::Int16(real), None) => Ok(NumericData::Int16 { real, imag: None }),
             (parse::NumericData::Int16(real), Some(parse::NumericData::Int16(imag))) => {
                 Ok(NumericData::Int16 {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::UInt8(real), None) => Ok(NumericData::UInt8 {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::UInt8(real), None) => Ok(NumericData::UInt8 { real, imag: None }),
             (parse::NumericData::UInt8(real), Some(parse::NumericData::UInt8(imag))) => {
                 Ok(NumericData::UInt8 {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            (parse::NumericData::Int8(real), None) => Ok(NumericData::Int8 {
-                real: real,
-                imag: None,
-            }),
+            (parse::NumericData::Int8(real), None) => Ok(NumericData::Int8 { real, imag: None }),
             (parse::NumericData::Int8(real), Some(parse::NumericData::Int8(imag))) => {
                 Ok(NumericData::Int8 {
-                    real: real,
+                    real,
                     imag: Some(imag),
                 })
             }
-            _ => return Err(Error::InternalError),
+            _ => Err(Error::InternalError),
+        }
+    }
+
+    /// The number of values of the real part.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            NumericData::Int8 { real, .. } => real.len(),
+            NumericData::UInt8 { real, .. } => real.len(),
+            NumericData::Int16 { real, .. } => real.len(),
+            NumericData::UInt16 { real, .. } => real.len(),
+            NumericData::Int32 { real, .. } => real.len(),
+            NumericData::UInt32 { real, .. } => real.len(),
+            NumericData::Int64 { real, .. } => real.len(),
+            NumericData::UInt64 { real, .. } => real.len(),
+            NumericData::Single { real, .. } => real.len(),
+            NumericData::Double { real, .. } => real.len(),
+            NumericData::Half { real, .. } => real.len(),
+        }
+    }
+
+    /// The number of values of the imaginary part, if there is one.
+    pub(crate) fn imag_len(&self) -> Option<usize> {
+        match self {
+            NumericData::Int8 { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::UInt8 { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::Int16 { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::UInt16 { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::Int32 { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::UInt32 { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::Int64 { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::UInt64 { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::Single { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::Double { imag, .. } => imag.as_ref().map(Vec::len),
+            NumericData::Half { imag, .. } => imag.as_ref().map(Vec::len),
+        }
+    }
+
+    /// Whether the data has an imaginary part.
+    pub(crate) fn is_complex(&self) -> bool {
+        match self {
+            NumericData::Int8 { imag, .. } => imag.is_some(),
+            NumericData::UInt8 { imag, .. } => imag.is_some(),
+            NumericData::Int16 { imag, .. } => imag.is_some(),
+            NumericData::UInt16 { imag, .. } => imag.is_some(),
+            NumericData::Int32 { imag, .. } => imag.is_some(),
+            NumericData::UInt32 { imag, .. } => imag.is_some(),
+            NumericData::Int64 { imag, .. } => imag.is_some(),
+            NumericData::UInt64 { imag, .. } => imag.is_some(),
+            NumericData::Single { imag, .. } => imag.is_some(),
+            NumericData::Double { imag, .. } => imag.is_some(),
+            NumericData::Half { imag, .. } => imag.is_some(),
+        }
+    }
+
+    /// The real part of the data converted to `f64`, as used by the decoders
+    /// of built-in classes that don't care about the exact storage type.
+    pub(crate) fn real_to_f64(&self) -> Vec<f64> {
+        match self {
+            NumericData::Int8 { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::UInt8 { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::Int16 { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::UInt16 { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::Int32 { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::UInt32 { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::Int64 { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::UInt64 { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::Single { real, .. } => real.iter().map(|&x| x as f64).collect(),
+            NumericData::Double { real, .. } => real.clone(),
+            NumericData::Half { real, .. } => real
+                .iter()
+                .map(|&bits| half_precision::f16_bits_to_f32(bits) as f64)
+                .collect(),
         }
     }
 }
@@ -401,6 +525,9 @@ pub enum Error {
     IOError(std::io::Error),
     ParseError(nom::Err<nom::error::Error<&'static [u8]>>),
     ConversionError,
+    /// A value passed to one of the functions of this crate can't be used,
+    /// the message explains why
+    InvalidArgument(String),
     InternalError,
 }
 
@@ -412,6 +539,7 @@ impl std::fmt::Display for Error {
             Error::ConversionError => {
                 write!(f, "An error occurred while converting number formats")
             }
+            Error::InvalidArgument(message) => write!(f, "Invalid argument: {}", message),
             Error::InternalError => write!(f, "An internal error occurred, this is a bug"),
         }
     }
@@ -453,8 +581,8 @@ impl Array {
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// # let file = std::fs::File::open("tests/double.mat")?;
     /// # let mat_file = matfile::MatFile::parse(file)?;
-    /// # let array = &mat_file.arrays()[0];
-    /// if let matfile::NumericData::Double { real: real, imag: _ } = array.data() {
+    /// # let array = mat_file.arrays()[0];
+    /// if let matfile::NumericData::Double { real, imag: _ } = array.data() {
     ///     println!("Real part of the data: {:?}", real);
     /// }
     /// # Ok(())
@@ -466,62 +594,337 @@ impl Array {
     pub fn data(&self) -> &NumericData {
         &self.data
     }
+
+    /// Whether this is a logical array. Matlab stores logical arrays as
+    /// `UInt8` data holding zeros and ones.
+    pub fn is_logical(&self) -> bool {
+        self.logical
+    }
+
+    /// Creates a numeric array of the given size. The data has to be stored
+    /// in column-major order and its length (as well as the length of the
+    /// imaginary part, if any) has to match the size. Like in Matlab, the
+    /// size needs at least two dimensions.
+    pub fn new(name: &str, size: Vec<usize>, data: NumericData) -> Result<Self, Error> {
+        let num_elements = check_size(&size)?;
+        if data.len() != num_elements || data.imag_len().is_some_and(|len| len != num_elements) {
+            return Err(Error::InvalidArgument(format!(
+                "an array of size {:?} needs {} elements",
+                size, num_elements
+            )));
+        }
+        Ok(Array {
+            name: name.to_owned(),
+            size,
+            data,
+            logical: false,
+        })
+    }
+
+    /// Creates a logical array of the given size from values in column-major
+    /// order.
+    pub fn new_logical(name: &str, size: Vec<usize>, values: &[bool]) -> Result<Self, Error> {
+        let data = NumericData::UInt8 {
+            real: values.iter().map(|&value| u8::from(value)).collect(),
+            imag: None,
+        };
+        let mut array = Array::new(name, size, data)?;
+        array.logical = true;
+        Ok(array)
+    }
+
+    /// Replaces the data of this array, with the same requirements as
+    /// `Array::new`. The array is no longer logical afterwards.
+    pub fn set_data(&mut self, size: Vec<usize>, data: NumericData) -> Result<(), Error> {
+        *self = Array::new(&self.name, size, data)?;
+        Ok(())
+    }
+
+    /// Changes the size of this array while keeping its data in column-major
+    /// order, like Matlab's `reshape`. The number of elements can't change.
+    pub fn reshape(&mut self, size: Vec<usize>) -> Result<(), Error> {
+        if check_size(&size)? != self.data.len() {
+            return Err(Error::InvalidArgument(format!(
+                "an array of size {:?} can't be reshaped to {:?}",
+                self.size, size
+            )));
+        }
+        self.size = size;
+        Ok(())
+    }
+}
+
+/// Checks that a name can be used for a variable or a field: it has to
+/// start with a letter, followed by letters, digits and underscores.
+pub(crate) fn check_name(name: &str) -> Result<(), Error> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "\"{}\" is not a valid Matlab identifier",
+            name
+        )))
+    }
+}
+
+/// Checks that a size can be used for an array and returns its number of
+/// elements. The format stores every dimension as a 32 bit integer.
+pub(crate) fn check_size(size: &[usize]) -> Result<usize, Error> {
+    if size.len() < 2 || size.iter().any(|&d| d > i32::MAX as usize) {
+        return Err(Error::InvalidArgument(format!(
+            "{:?} is not a valid array size",
+            size
+        )));
+    }
+    size.iter()
+        .try_fold(1usize, |product, &d| product.checked_mul(d))
+        .ok_or_else(|| Error::InvalidArgument(format!("an array of size {:?} is too large", size)))
 }
 
 impl MatFile {
+    /// Creates an empty collection of values, to which values can be added
+    /// with `insert`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a collection of values, e.g. in order to write them to a
+    /// file. Every value needs a unique name that is a valid Matlab
+    /// identifier.
+    pub fn from_values(values: Vec<Value>) -> Result<Self, Error> {
+        let mut mat_file = MatFile::new();
+        for value in values {
+            check_name(value.name())?;
+            if mat_file.index.contains_key(value.name()) {
+                return Err(Error::InvalidArgument(format!(
+                    "the name \"{}\" is used more than once",
+                    value.name()
+                )));
+            }
+            mat_file
+                .index
+                .insert(value.name().to_owned(), mat_file.values.len());
+            mat_file.values.push(value);
+        }
+        Ok(mat_file)
+    }
+
+    /// Creates a collection of values without checking their names, which
+    /// is how loaded files are represented.
+    pub(crate) fn from_values_unchecked(values: Vec<Value>) -> Self {
+        let mut mat_file = MatFile {
+            values,
+            index: HashMap::new(),
+        };
+        mat_file.reindex(0);
+        mat_file
+    }
+
+    /// Updates the positions of all values starting at `start`.
+    fn reindex(&mut self, start: usize) {
+        self.index.retain(|_, position| *position < start);
+        for (position, value) in self.values.iter().enumerate().skip(start) {
+            self.index
+                .entry(value.name().to_owned())
+                .or_insert(position);
+        }
+    }
+
     /// Tries to parse a byte sequence as a ".mat" file.
     pub fn parse<R: std::io::Read>(mut reader: R) -> Result<Self, Error> {
         let mut buf = Vec::new();
-        reader
-            .read_to_end(&mut buf)
-            .map_err(|err| Error::IOError(err))?;
-        let (_remaining, parse_result) = parse::parse_all(&buf)
+        reader.read_to_end(&mut buf).map_err(Error::IOError)?;
+        Self::parse_bytes(&buf)
+    }
+
+    /// Like `parse`, but reading asynchronously with the `tokio` runtime.
+    /// The whole file is read before it is parsed.
+    #[cfg(feature = "tokio")]
+    pub async fn parse_async<R: tokio::io::AsyncRead + Unpin>(
+        mut reader: R,
+    ) -> Result<Self, Error> {
+        use tokio::io::AsyncReadExt;
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.map_err(Error::IOError)?;
+        Self::parse_bytes(&buf)
+    }
+
+    fn parse_bytes(buf: &[u8]) -> Result<Self, Error> {
+        let (_remaining, parse_result) = parse::parse_all(buf)
             .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
-        let arrays: Result<Vec<Array>, Error> = parse_result
+        let endianness = parse_result.header.endianness();
+        // Without readable subsystem data, variables holding objects are
+        // kept as raw values while all others can still be loaded
+        let subsystem = parse_result
+            .subsystem
+            .and_then(|subsystem| mcos::Subsystem::from_data_element(subsystem, endianness).ok())
+            .unwrap_or_default();
+        let subsystem = match parse_result.subsystem_range {
+            Some(range) => subsystem.with_element(&buf[range]),
+            None => subsystem,
+        };
+        // Variables that can't be decoded are kept as they are, while
+        // invalid variables of supported types are an error
+        let values = parse_result
             .data_elements
             .into_iter()
-            .filter_map(|data_element| match data_element {
-                parse::DataElement::NumericMatrix(flags, dims, name, real, imag) => {
-                    let size = dims.into_iter().map(|d| d as usize).collect();
-                    let numeric_data = match NumericData::try_from(flags.class, real, imag) {
-                        Ok(numeric_data) => numeric_data,
-                        Err(err) => return Some(Err(err)),
-                    };
-                    Some(Ok(Array {
-                        size: size,
-                        name: name,
-                        data: numeric_data,
-                    }))
-                }
+            .map(|(data_element, range)| {
+                Ok(
+                    Value::try_from(data_element, &subsystem)?.unwrap_or_else(|| {
+                        Value::Raw(RawValue::new(&buf[range], endianness, &subsystem))
+                    }),
+                )
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(MatFile::from_values_unchecked(values))
+    }
+
+    /// List of all numeric arrays in this .mat file.
+    ///
+    /// Values of other types are not part of this list, use `values` to
+    /// access them.
+    pub fn arrays(&self) -> Vec<&Array> {
+        self.values
+            .iter()
+            .filter_map(|value| match value {
+                Value::Numeric(array) => Some(array),
                 _ => None,
             })
-            .collect();
-        let arrays = arrays?;
-        Ok(MatFile { arrays: arrays })
+            .collect()
     }
 
-    /// List of all arrays in this .mat file.
+    /// Returns a numeric array with the given name if it exists. Case
+    /// sensitive.
+    pub fn find_by_name<'me>(&'me self, name: &'_ str) -> Option<&'me Array> {
+        match self.find_value_by_name(name)? {
+            Value::Numeric(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// List of all values in this .mat file.
     ///
-    /// When parsing a .mat file all arrays of unsupported type (currently all
-    /// non-numerical and sparse arrays) will be ignored and will thus not be
-    /// part of this list.
-    pub fn arrays(&self) -> &Vec<Array> {
-        &self.arrays
+    /// When parsing a .mat file, values of unsupported types are kept as
+    /// `Value::Raw` with their name and class, so that they are written
+    /// back unchanged.
+    pub fn values(&self) -> &Vec<Value> {
+        &self.values
     }
 
-    /// Returns an array with the given name if it exists. Case sensitive.
+    /// Returns a value with the given name if it exists. Case sensitive.
+    pub fn find_value_by_name<'me>(&'me self, name: &'_ str) -> Option<&'me Value> {
+        self.position(name).map(|position| &self.values[position])
+    }
+
+    /// The position of the value with the given name in `values`.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Takes the values out of this collection.
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+
+    /// Adds a value. A value with the same name is replaced in place and
+    /// returned, otherwise the value is added at the end. The name has to
+    /// be a valid Matlab identifier.
     ///
-    /// When parsing a .mat file all arrays of unsupported type (currently all
-    /// non-numerical and sparse arrays) will be ignored and will thus not be
-    /// returned by this function.
-    pub fn find_by_name<'me>(&'me self, name: &'_ str) -> Option<&'me Array> {
-        for array in &self.arrays {
-            if array.name == name {
-                return Some(array);
+    /// ```rust
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let data = matfile::NumericData::Double {
+    ///     real: vec![1.0, 2.0, 3.0, 4.0],
+    ///     imag: None,
+    /// };
+    /// let mut mat_file = matfile::MatFile::new();
+    /// mat_file.insert(matfile::Value::Numeric(matfile::Array::new("a", vec![2, 2], data)?))?;
+    /// mat_file.rename("a", "b")?;
+    /// assert!(mat_file.find_by_name("b").is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn insert(&mut self, value: Value) -> Result<Option<Value>, Error> {
+        check_name(value.name())?;
+        match self.position(value.name()) {
+            Some(position) => Ok(Some(std::mem::replace(&mut self.values[position], value))),
+            None => {
+                self.index
+                    .insert(value.name().to_owned(), self.values.len());
+                self.values.push(value);
+                Ok(None)
             }
         }
-        None
     }
+
+    /// Removes the value with the given name and returns it.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        let position = self.position(name)?;
+        let value = self.values.remove(position);
+        self.reindex(position);
+        Some(value)
+    }
+
+    /// Renames a value. The new name has to be a valid Matlab identifier
+    /// that is not used by any other value.
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), Error> {
+        let position = self.position(name).ok_or_else(|| not_found(name))?;
+        if name == new_name {
+            return Ok(());
+        }
+        if let Value::Raw(_) = self.values[position] {
+            return Err(Error::InvalidArgument(format!(
+                "\"{}\" couldn't be decoded and can't be renamed",
+                name
+            )));
+        }
+        check_name(new_name)?;
+        if self.index.contains_key(new_name) {
+            return Err(Error::InvalidArgument(format!(
+                "the name \"{}\" is already used",
+                new_name
+            )));
+        }
+        self.values[position].set_name(new_name);
+        self.index.remove(name);
+        self.index.insert(new_name.to_owned(), position);
+        // A loaded file could have contained several values of that name
+        self.reindex(position + 1);
+        Ok(())
+    }
+
+    /// Moves the value with the given name to a new position, shifting the
+    /// values in between.
+    pub fn move_to(&mut self, name: &str, new_position: usize) -> Result<(), Error> {
+        let position = self.position(name).ok_or_else(|| not_found(name))?;
+        if new_position >= self.values.len() {
+            return Err(Error::InvalidArgument(format!(
+                "position {} is out of bounds for {} values",
+                new_position,
+                self.values.len()
+            )));
+        }
+        let value = self.values.remove(position);
+        self.values.insert(new_position, value);
+        self.reindex(position.min(new_position));
+        Ok(())
+    }
+
+    /// Reorders the values with a comparison function, keeping the order of
+    /// equal values.
+    pub fn sort_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&Value, &Value) -> std::cmp::Ordering,
+    {
+        self.values.sort_by(compare);
+        self.reindex(0);
+    }
+}
+
+fn not_found(name: &str) -> Error {
+    Error::InvalidArgument(format!("there is no value named \"{}\"", name))
 }
 
 // TODO: improve tests.
@@ -573,4 +976,1885 @@ mod tests {
         let data = include_bytes!("../tests/long_name.mat");
         let _mat_file = MatFile::parse(data.as_ref()).unwrap();
     }
+
+    #[test]
+    fn character_array() {
+        let data = include_bytes!("../tests/character.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.find_value_by_name("chr") {
+            Some(Value::Char(chr)) => assert_eq!(chr.to_string(), "Hello, world!"),
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn char_cell_struct() {
+        let data = include_bytes!("../tests/char_cell_struct.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        assert_eq!(mat_file.values().len(), 4);
+        assert!(mat_file.arrays().is_empty());
+        match mat_file.find_value_by_name("rows") {
+            Some(Value::Char(rows)) => assert_eq!(rows.rows(), vec!["abc", "def"]),
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("mixed") {
+            Some(Value::Cell(mixed)) => {
+                assert_eq!(mixed.size(), &vec![1, 2]);
+                assert!(matches!(&mixed.cells()[0], Value::Numeric(_)));
+                assert!(
+                    matches!(&mixed.cells()[1], Value::Char(text) if text.to_string() == "text")
+                );
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("config") {
+            Some(Value::Struct(config)) => {
+                assert_eq!(config.field_names(), &vec!["gain", "label"]);
+                assert!(
+                    matches!(config.field(0, "label"), Some(Value::Char(label)) if label.to_string() == "ch1")
+                );
+                assert!(config.field(0, "offset").is_none());
+                assert!(config.field(1, "gain").is_none());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn function_handles() {
+        let data = include_bytes!("../tests/function_handles.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        let handle = |name| match mat_file.find_value_by_name(name) {
+            Some(Value::FunctionHandle(handle)) => handle,
+            other => panic!("Unexpected value {:?}", other),
+        };
+        assert_eq!(handle("sine").kind(), FunctionHandleKind::Simple);
+        assert_eq!(handle("sine").function(), "sin");
+        let square = handle("square");
+        assert_eq!(square.kind(), FunctionHandleKind::Anonymous);
+        assert_eq!(square.function(), "@(x)a*x.^2");
+        assert_eq!(square.workspace().len(), 1);
+        assert_eq!(square.workspace()[0].0, "a");
+        assert!(matches!(
+            &square.workspace()[0].1,
+            Value::Numeric(Array { data: NumericData::Double { real, .. }, .. }) if real == &vec![3.0]
+        ));
+        assert_eq!(handle("helper").kind(), FunctionHandleKind::Scoped);
+        assert_eq!(handle("helper").file(), "/home/user/controller.m");
+    }
+
+    #[test]
+    fn object_array() {
+        let data = include_bytes!("../tests/object.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.find_value_by_name("p") {
+            Some(Value::Object(p)) => {
+                assert_eq!(p.class_name(), "polynom");
+                assert_eq!(p.size(), &vec![1, 2]);
+                assert_eq!(p.field_names(), &vec!["c", "var"]);
+                assert!(
+                    matches!(p.field(1, "var"), Some(Value::Char(var)) if var.to_string() == "s")
+                );
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn classdef_objects() {
+        let data = include_bytes!("../tests/classdef.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        // The subsystem data must not show up as a variable
+        assert_eq!(mat_file.values().len(), 3);
+        let sensor = match mat_file.find_value_by_name("sensor") {
+            Some(Value::Classdef(sensor)) => sensor,
+            other => panic!("Unexpected value {:?}", other),
+        };
+        assert_eq!(sensor.class_name(), "Sensor");
+        assert_eq!(sensor.properties(0).unwrap().len(), 5);
+        assert!(
+            matches!(sensor.property(0, "Mode"), Some(Value::Char(mode)) if mode.to_string() == "fast")
+        );
+        match sensor.property(0, "Calibration") {
+            Some(Value::Classdef(calibration)) => {
+                assert_eq!(calibration.class_name(), "sensors.Calibration");
+                assert!(calibration.property(0, "Offset").is_some());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("sensors") {
+            Some(Value::Classdef(sensors)) => {
+                assert_eq!(sensors.size(), &vec![2, 1]);
+                assert!(matches!(
+                    sensors.property(1, "Gain"),
+                    Some(Value::Numeric(Array { data: NumericData::Double { real, .. }, .. })) if real == &vec![3.0]
+                ));
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn string_arrays() {
+        let data = include_bytes!("../tests/strings.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.find_value_by_name("names") {
+            Some(Value::String(names)) => {
+                assert_eq!(names.size(), &vec![2, 2]);
+                assert_eq!(
+                    names.strings(),
+                    &vec![
+                        Some("alpha".to_owned()),
+                        None,
+                        Some("Grüße ✓".to_owned()),
+                        Some(String::new())
+                    ]
+                );
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn datetimes_and_durations() {
+        let data = include_bytes!("../tests/datetime.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.find_value_by_name("launch") {
+            Some(Value::Datetime(launch)) => {
+                assert_eq!(launch.size(), &vec![1, 3]);
+                assert_eq!(launch.time_zone(), "UTC");
+                assert_eq!(launch.format(), "yyyy-MM-dd HH:mm");
+                assert_eq!(launch.milliseconds()[0], 1613681700000.0);
+                assert!(launch.milliseconds()[1].is_nan());
+                assert_eq!(launch.sub_milliseconds().unwrap()[2], 0.000123);
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("birthday") {
+            Some(Value::Datetime(birthday)) => {
+                assert_eq!(birthday.time_zone(), "");
+                assert!(birthday.is_date_only());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("elapsed") {
+            Some(Value::Duration(elapsed)) => {
+                assert_eq!(elapsed.size(), &vec![3, 1]);
+                assert_eq!(elapsed.format(), "hh:mm:ss");
+                assert_eq!(elapsed.milliseconds()[..2], [1500.0, 90000.0]);
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        let stamp = DatetimeArray::from_datenums(mat_file.find_by_name("stamp").unwrap()).unwrap();
+        assert_eq!(stamp.milliseconds(), &vec![1595937600000.0, 0.0]);
+    }
+
+    #[test]
+    fn tables() {
+        let data = include_bytes!("../tests/table.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.find_value_by_name("results") {
+            Some(Value::Table(results)) => {
+                assert_eq!(results.kind(), TableKind::Table);
+                assert_eq!(results.num_rows(), 3);
+                assert_eq!(results.variable_names(), &vec!["Score", "Label", "Notes"]);
+                assert_eq!(results.row_names(), &vec!["first", "second", "third"]);
+                assert_eq!(results.dimension_names(), &vec!["Trial", "Variables"]);
+                assert_eq!(results.description(), "Trial results");
+                assert_eq!(results.variable_units(), &vec!["pts", "", ""]);
+                assert_eq!(results.variable_descriptions()[0], "Measured score");
+                match results.column("Label") {
+                    Some(Value::String(labels)) => assert_eq!(labels.strings().len(), 3),
+                    other => panic!("Unexpected column {:?}", other),
+                }
+                assert!(matches!(results.column("Notes"), Some(Value::Cell(_))));
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("readings") {
+            Some(Value::Table(readings)) => {
+                assert_eq!(readings.kind(), TableKind::Timetable);
+                assert_eq!(readings.num_rows(), 2);
+                assert_eq!(readings.variable_units(), &vec!["degC"]);
+                assert_eq!(readings.description(), "Lab readings");
+                assert!(matches!(readings.row_times(), Some(Value::Duration(_))));
+                assert!(matches!(
+                    readings.column("Temperature"),
+                    Some(Value::Numeric(_))
+                ));
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn categorical_arrays() {
+        let data = include_bytes!("../tests/categorical.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.find_value_by_name("sizes") {
+            Some(Value::Categorical(sizes)) => {
+                assert_eq!(sizes.size(), &vec![2, 3]);
+                assert_eq!(sizes.categories(), &vec!["small", "medium", "large"]);
+                assert_eq!(
+                    sizes.codes(),
+                    &vec![Some(0), Some(2), None, Some(1), Some(0), Some(2)]
+                );
+                assert_eq!(sizes.labels()[..3], [Some("small"), Some("large"), None]);
+                assert!(sizes.is_ordinal());
+                assert!(sizes.is_protected());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("paint") {
+            Some(Value::Table(paint)) => match paint.column("Color") {
+                Some(Value::Categorical(colors)) => {
+                    assert_eq!(colors.labels(), vec![Some("blue"), Some("red")]);
+                    assert!(!colors.is_ordinal());
+                }
+                other => panic!("Unexpected column {:?}", other),
+            },
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps() {
+        let data = include_bytes!("../tests/map.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.find_value_by_name("config") {
+            Some(Value::Map(config)) => {
+                assert_eq!(config.key_type(), "char");
+                assert_eq!(config.len(), 2);
+                match config.get_char("host") {
+                    Some(Value::Char(host)) => assert_eq!(host.to_string(), "example.org"),
+                    other => panic!("Unexpected entry {:?}", other),
+                }
+                assert!(matches!(config.get_char("port"), Some(Value::Numeric(_))));
+                assert!(config.get_char("user").is_none());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("lookup") {
+            Some(Value::Map(lookup)) => {
+                assert_eq!(lookup.key_type(), "double");
+                assert_eq!(lookup.value_type(), "char");
+                assert_eq!(lookup.entries()[1].0, MapKey::Numeric(2.5));
+                match lookup.get_numeric(1.0) {
+                    Some(Value::Char(one)) => assert_eq!(one.to_string(), "one"),
+                    other => panic!("Unexpected entry {:?}", other),
+                }
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn half_precision() {
+        let data = include_bytes!("../tests/half.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        let samples = mat_file.find_by_name("samples").unwrap();
+        assert_eq!(samples.size(), &vec![2, 3]);
+        let (real, imag) = samples.data().half_to_f32().unwrap();
+        assert_eq!(
+            real,
+            vec![1.0, -2.0, 0.5, 65504.0, 2f32.powi(-24), f32::INFINITY]
+        );
+        assert!(imag.is_none());
+    }
+
+    #[test]
+    fn empty_arrays() {
+        let data = include_bytes!("../tests/empty.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        assert_eq!(mat_file.values().len(), 12);
+        let wide = mat_file.find_by_name("wide").unwrap();
+        assert_eq!(wide.size(), &vec![0, 3]);
+        assert!(matches!(wide.data(), NumericData::Double { real, imag: None } if real.is_empty()));
+        let empty_complex = mat_file.find_by_name("empty_complex").unwrap();
+        assert!(empty_complex.data().is_complex());
+        match mat_file.find_value_by_name("utf8_text") {
+            Some(Value::Char(text)) => {
+                assert_eq!(text.size(), &vec![1, 0]);
+                assert_eq!(text.to_string(), "");
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("holes") {
+            Some(Value::Cell(holes)) => match &holes.cells()[0] {
+                Value::Numeric(hole) => {
+                    assert_eq!(hole.name(), "");
+                    assert_eq!(hole.size(), &vec![0, 0]);
+                }
+                other => panic!("Unexpected cell {:?}", other),
+            },
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("no_elements") {
+            Some(Value::Struct(no_elements)) => {
+                assert_eq!(no_elements.field_names(), &vec!["a", "b"]);
+                assert!(no_elements.values().is_empty());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        for name in &["no_fields", "zero_length_names"] {
+            match mat_file.find_value_by_name(name) {
+                Some(Value::Struct(no_fields)) => assert!(no_fields.field_names().is_empty()),
+                other => panic!("Unexpected value {:?}", other),
+            }
+        }
+        assert!(mat_file.find_by_name("compressed_empty").is_some());
+        assert_eq!(mat_file.find_by_name("bytes").unwrap().size(), &vec![0, 5]);
+    }
+
+    #[test]
+    fn sparse_arrays() {
+        let data = include_bytes!("../tests/sparse2.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.values().first() {
+            Some(Value::Sparse(sparse)) => {
+                assert_eq!(sparse.size(), &vec![8, 8]);
+                assert_eq!(sparse.num_nonzeros(), 8);
+                assert!(sparse.data().is_complex());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sparse_edge_cases() {
+        let data = include_bytes!("../tests/sparse_edge_cases.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        let sparse = |name| match mat_file.find_value_by_name(name) {
+            Some(Value::Sparse(sparse)) => sparse,
+            other => panic!("Unexpected value {:?}", other),
+        };
+        // nzmax is larger than the number of nonzeros
+        let padded = sparse("padded");
+        assert_eq!(padded.row_indices(), &vec![0, 2]);
+        assert_eq!(padded.column_offsets(), &vec![0, 1, 1, 2]);
+        assert!(
+            matches!(padded.data(), NumericData::Double { real, .. } if real == &vec![4.0, 5.0])
+        );
+        let complex_padded = sparse("complex_padded");
+        assert!(matches!(
+            complex_padded.data(),
+            NumericData::Double { real, imag: Some(imag) } if real == &vec![3.0] && imag == &vec![-1.0]
+        ));
+        let mask = sparse("mask");
+        assert!(mask.is_logical());
+        assert!(matches!(mask.data(), NumericData::UInt8 { real, .. } if real == &vec![1, 1]));
+        let nothing = sparse("nothing");
+        assert_eq!(nothing.size(), &vec![0, 0]);
+        assert_eq!(nothing.num_nonzeros(), 0);
+        let no_nonzeros = sparse("no_nonzeros");
+        assert_eq!(no_nonzeros.num_columns(), 3);
+        assert_eq!(no_nonzeros.column_offsets(), &vec![0, 0, 0, 0]);
+        // Values stored in a smaller integer type are converted to doubles
+        let small_type = sparse("small_type");
+        assert!(
+            matches!(small_type.data(), NumericData::Double { real, .. } if real == &vec![7.0, 9.0])
+        );
+    }
+
+    #[test]
+    fn sparse_conversions() {
+        let data = include_bytes!("../tests/sparse1.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        let sparse = match mat_file.values().first() {
+            Some(Value::Sparse(sparse)) => sparse,
+            other => panic!("Unexpected value {:?}", other),
+        };
+        assert_eq!(sparse.column_nonzeros(2).collect::<Vec<_>>(), vec![]);
+        assert_eq!(sparse.column_nonzeros(3).collect::<Vec<_>>(), vec![(2, 2)]);
+        let triplets = sparse.to_triplets();
+        assert_eq!(triplets[0], (5, 0, 2.0));
+        let dense = sparse.to_dense();
+        assert_eq!(dense.size(), &vec![8, 8]);
+        match dense.data() {
+            NumericData::Double { real, imag: None } => {
+                assert_eq!(real.iter().filter(|&&x| x != 0.0).count(), 7);
+                assert_eq!(real[5], 2.0);
+                assert_eq!(real[3 * 8 + 2], 4.0);
+                assert_eq!(real[4 * 8], 9.0);
+            }
+            other => panic!("Unexpected data {:?}", other),
+        }
+        // Rebuilding from shuffled triplets gives the same matrix
+        let mut shuffled = triplets.clone();
+        shuffled.reverse();
+        let rebuilt = SparseArray::from_triplets("rebuilt", 8, 8, &shuffled).unwrap();
+        assert_eq!(rebuilt.row_indices(), sparse.row_indices());
+        assert_eq!(rebuilt.column_offsets(), sparse.column_offsets());
+        assert_eq!(rebuilt.to_triplets(), triplets);
+    }
+
+    #[test]
+    fn sparse_from_triplets() {
+        // Duplicates are added up and zeros dropped
+        let sparse = SparseArray::from_triplets(
+            "s",
+            2,
+            3,
+            &[(1, 2, 1.0), (0, 0, 5.0), (1, 2, 2.0), (1, 1, 0.0)],
+        )
+        .unwrap();
+        assert_eq!(sparse.to_triplets(), vec![(0, 0, 5.0), (1, 2, 3.0)]);
+        assert_eq!(sparse.column_offsets(), &vec![0, 1, 1, 2]);
+        let complex = SparseArray::from_complex_triplets("c", 2, 2, &[(1, 0, 0.0, 1.0)]).unwrap();
+        assert_eq!(complex.to_complex_triplets(), vec![(1, 0, 0.0, 1.0)]);
+        let mask =
+            SparseArray::from_logical_triplets("m", 2, 2, &[(1, 1), (0, 1), (1, 1)]).unwrap();
+        assert!(mask.is_logical());
+        match mask.to_dense().data() {
+            NumericData::UInt8 { real, .. } => assert_eq!(real, &vec![0, 0, 1, 1]),
+            other => panic!("Unexpected data {:?}", other),
+        }
+        assert!(matches!(
+            SparseArray::from_triplets("s", 2, 2, &[(2, 0, 1.0)]),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    fn write_and_parse(values: Vec<Value>, options: &WriteOptions) -> MatFile {
+        let mut data = Vec::new();
+        MatFile::from_values(values)
+            .unwrap()
+            .write_with_options(&mut data, options)
+            .unwrap();
+        MatFile::parse(data.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn write_numeric_arrays() {
+        let values = vec![
+            Value::Numeric(
+                Array::new(
+                    "a",
+                    vec![2, 2],
+                    NumericData::Double {
+                        real: vec![1.0, 2.0, 3.0, 4.5],
+                        imag: Some(vec![0.0, -1.0, 0.0, 2.0]),
+                    },
+                )
+                .unwrap(),
+            ),
+            Value::Numeric(
+                Array::new(
+                    "b",
+                    vec![1, 3],
+                    NumericData::Int32 {
+                        real: vec![-1, 0, i32::MAX],
+                        imag: None,
+                    },
+                )
+                .unwrap(),
+            ),
+            Value::Numeric(
+                Array::new(
+                    "c",
+                    vec![0, 0],
+                    NumericData::UInt16 {
+                        real: vec![],
+                        imag: None,
+                    },
+                )
+                .unwrap(),
+            ),
+        ];
+        let mat_file = write_and_parse(values, &WriteOptions::new());
+        let a = mat_file.find_by_name("a").unwrap();
+        assert_eq!(a.size(), &vec![2, 2]);
+        assert!(!a.is_logical());
+        assert!(
+            matches!(a.data(), NumericData::Double { real, imag: Some(imag) }
+            if real == &vec![1.0, 2.0, 3.0, 4.5] && imag == &vec![0.0, -1.0, 0.0, 2.0])
+        );
+        assert!(matches!(mat_file.find_by_name("b").unwrap().data(),
+            NumericData::Int32 { real, imag: None } if real == &vec![-1, 0, i32::MAX]));
+        let c = mat_file.find_by_name("c").unwrap();
+        assert_eq!(c.size(), &vec![0, 0]);
+        assert!(matches!(c.data(), NumericData::UInt16 { real, .. } if real.is_empty()));
+    }
+
+    #[test]
+    fn write_char_and_logical_arrays() {
+        let values = || {
+            vec![
+                Value::Char(CharArray::from_text("greeting", "Hello, wörld! 🎉")),
+                Value::Char(CharArray::from_rows("rows", &["abc", "de"])),
+                Value::Char(CharArray::from_text("empty", "")),
+                Value::Numeric(
+                    Array::new_logical("mask", vec![2, 2], &[true, false, false, true]).unwrap(),
+                ),
+            ]
+        };
+        for options in &[
+            WriteOptions::new(),
+            WriteOptions::new().char_encoding(CharEncoding::Utf8),
+        ] {
+            let mat_file = write_and_parse(values(), options);
+            match mat_file.find_value_by_name("greeting") {
+                Some(Value::Char(text)) => assert_eq!(text.to_string(), "Hello, wörld! 🎉"),
+                other => panic!("Unexpected value {:?}", other),
+            }
+            match mat_file.find_value_by_name("rows") {
+                Some(Value::Char(rows)) => {
+                    assert_eq!(rows.size(), &vec![2, 3]);
+                    assert_eq!(rows.rows(), vec!["abc", "de "]);
+                }
+                other => panic!("Unexpected value {:?}", other),
+            }
+            match mat_file.find_value_by_name("empty") {
+                Some(Value::Char(empty)) => assert_eq!(empty.size(), &vec![0, 0]),
+                other => panic!("Unexpected value {:?}", other),
+            }
+            let mask = mat_file.find_by_name("mask").unwrap();
+            assert!(mask.is_logical());
+            assert!(
+                matches!(mask.data(), NumericData::UInt8 { real, .. } if real == &vec![1, 0, 0, 1])
+            );
+        }
+        // Surrogate pairs split across rows can't be stored as UTF-8
+        let emoji = vec![Value::Char(CharArray::from_rows("e", &["🎉", "🎉"]))];
+        let mut data = Vec::new();
+        let options = WriteOptions::new().char_encoding(CharEncoding::Utf8);
+        let result = MatFile::from_values(emoji)
+            .unwrap()
+            .write_with_options(&mut data, &options);
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn write_sparse_matrices() {
+        let real = SparseArray::from_triplets("k", 3, 4, &[(0, 0, 4.0), (2, 0, -1.0), (1, 3, 2.5)])
+            .unwrap();
+        let complex = SparseArray::from_complex_triplets("z", 2, 2, &[(1, 0, 1.0, -1.0)]).unwrap();
+        let mask = SparseArray::from_logical_triplets("m", 2, 3, &[(0, 2), (1, 1)]).unwrap();
+        let empty = SparseArray::from_triplets("e", 5, 0, &[]).unwrap();
+        let csc = SparseArray::from_csc(
+            "c",
+            3,
+            2,
+            vec![0, 2, 1],
+            vec![0, 2, 3],
+            NumericData::Double {
+                real: vec![1.0, 2.0, 3.0],
+                imag: None,
+            },
+        )
+        .unwrap();
+        let values = vec![
+            Value::Sparse(real.clone()),
+            Value::Sparse(complex.clone()),
+            Value::Sparse(mask.clone()),
+            Value::Sparse(empty),
+            Value::Sparse(csc),
+        ];
+        let mut data = Vec::new();
+        MatFile::from_values(values)
+            .unwrap()
+            .write(&mut data)
+            .unwrap();
+        let mat_file = MatFile::parse(data.as_slice()).unwrap();
+        let sparse = |name| match mat_file.find_value_by_name(name) {
+            Some(Value::Sparse(sparse)) => sparse,
+            other => panic!("Unexpected value {:?}", other),
+        };
+        assert_eq!(sparse("k").size(), &vec![3, 4]);
+        assert_eq!(sparse("k").to_triplets(), real.to_triplets());
+        assert_eq!(
+            sparse("z").to_complex_triplets(),
+            complex.to_complex_triplets()
+        );
+        assert!(sparse("m").is_logical());
+        assert_eq!(sparse("m").to_triplets(), mask.to_triplets());
+        assert_eq!(sparse("e").size(), &vec![5, 0]);
+        assert_eq!(sparse("e").num_nonzeros(), 0);
+        assert_eq!(
+            sparse("c").to_triplets(),
+            vec![(0, 0, 1.0), (2, 0, 2.0), (1, 1, 3.0)]
+        );
+        // Like Matlab, nzmax is never zero
+        let (_, parsed) = parse::parse_all(&data).unwrap();
+        let nzmax: Vec<usize> = parsed
+            .data_elements
+            .iter()
+            .map(|(element, _)| match element {
+                parse::DataElement::SparseMatrix(flags, ..) => flags.nzmax,
+                other => panic!("Unexpected element {:?}", other),
+            })
+            .collect();
+        assert_eq!(nzmax, vec![3, 1, 2, 1, 3]);
+    }
+
+    #[test]
+    fn sparse_from_csc_validation() {
+        let data = || NumericData::Double {
+            real: vec![1.0, 2.0],
+            imag: None,
+        };
+        let from_csc = |rows: Vec<usize>, offsets: Vec<usize>, data| {
+            SparseArray::from_csc("s", 3, 2, rows, offsets, data)
+        };
+        assert!(from_csc(vec![0, 2], vec![0, 1, 2], data()).is_ok());
+        // Offsets of the wrong length, not starting at zero or decreasing
+        assert!(from_csc(vec![0, 2], vec![0, 2], data()).is_err());
+        assert!(from_csc(vec![0, 2], vec![1, 1, 2], data()).is_err());
+        assert!(from_csc(vec![0, 2], vec![0, 2, 1], data()).is_err());
+        // Rows out of range or not increasing within a column
+        assert!(from_csc(vec![0, 3], vec![0, 1, 2], data()).is_err());
+        assert!(from_csc(vec![2, 0], vec![0, 2, 2], data()).is_err());
+        // Values that don't match the rows or aren't doubles or logicals
+        assert!(from_csc(vec![0], vec![0, 1, 1], data()).is_err());
+        let singles = NumericData::Single {
+            real: vec![1.0, 2.0],
+            imag: None,
+        };
+        assert!(from_csc(vec![0, 2], vec![0, 1, 2], singles).is_err());
+        let logical = NumericData::UInt8 {
+            real: vec![1, 7],
+            imag: None,
+        };
+        let mask = from_csc(vec![0, 2], vec![0, 1, 2], logical).unwrap();
+        assert!(mask.is_logical());
+        assert!(matches!(mask.data(), NumericData::UInt8 { real, .. } if real == &vec![1, 1]));
+    }
+
+    #[test]
+    fn write_cells_and_structs() {
+        let scalar = |x: f64| {
+            Value::Numeric(
+                Array::new(
+                    "",
+                    vec![1, 1],
+                    NumericData::Double {
+                        real: vec![x],
+                        imag: None,
+                    },
+                )
+                .unwrap(),
+            )
+        };
+        let long_field = "f".repeat(63);
+        let fields = StructArray::new(
+            "",
+            vec![1, 2],
+            vec!["gain".to_owned(), long_field.clone()],
+            vec![
+                scalar(1.0),
+                Value::Char(CharArray::from_text("", "first")),
+                scalar(2.0),
+                Value::Cell(CellArray::new("", vec![0, 0], vec![]).unwrap()),
+            ],
+        )
+        .unwrap();
+        let no_fields = StructArray::new("", vec![1, 1], vec![], vec![]).unwrap();
+        let nested = CellArray::new(
+            "nested",
+            vec![2, 2],
+            vec![
+                scalar(3.0),
+                Value::Struct(fields),
+                Value::Struct(no_fields),
+                Value::Sparse(SparseArray::from_triplets("", 2, 2, &[(1, 1, 5.0)]).unwrap()),
+            ],
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        MatFile::from_values(vec![Value::Cell(nested)])
+            .unwrap()
+            .write(&mut data)
+            .unwrap();
+        let mat_file = MatFile::parse(data.as_slice()).unwrap();
+        let nested = match mat_file.find_value_by_name("nested") {
+            Some(Value::Cell(nested)) => nested,
+            other => panic!("Unexpected value {:?}", other),
+        };
+        assert_eq!(nested.size(), &vec![2, 2]);
+        assert!(matches!(&nested.cells()[3], Value::Sparse(s) if s.num_nonzeros() == 1));
+        match &nested.cells()[1] {
+            Value::Struct(fields) => {
+                assert_eq!(fields.size(), &vec![1, 2]);
+                assert_eq!(
+                    fields.field_names(),
+                    &vec!["gain".to_owned(), long_field.clone()]
+                );
+                assert!(
+                    matches!(fields.field(0, &long_field), Some(Value::Char(text)) if text.to_string() == "first")
+                );
+                assert!(matches!(fields.field(1, &long_field), Some(Value::Cell(_))));
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        assert!(
+            matches!(&nested.cells()[2], Value::Struct(s) if s.field_names().is_empty() && s.size() == &vec![1, 1])
+        );
+        // Field names have to be valid, unique and at most 63 characters long
+        let field = |name: &str| {
+            StructArray::new("s", vec![1, 1], vec![name.to_owned()], vec![scalar(1.0)])
+        };
+        assert!(field(&"f".repeat(64)).is_err());
+        assert!(field("_f").is_err());
+        let twice = vec!["a".to_owned(), "a".to_owned()];
+        assert!(StructArray::new("s", vec![1, 1], twice, vec![scalar(1.0), scalar(2.0)]).is_err());
+        assert!(CellArray::new("c", vec![1, 2], vec![scalar(1.0)]).is_err());
+        let names = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        assert!(StructArray::new("s", vec![usize::MAX / 2, 1], names.clone(), vec![]).is_err());
+
+        // Dimensions which are fine on their own, but too large for the
+        // number of fields
+        let names: Vec<_> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|&name| name.to_owned())
+            .collect();
+        let fields = names.iter().map(|_| scalar(1.0)).collect();
+        let s = StructArray::new("s", vec![1, 1], names, fields).unwrap();
+        let mut data = Vec::new();
+        MatFile::from_values(vec![Value::Struct(s)])
+            .unwrap()
+            .write(&mut data)
+            .unwrap();
+        for d in data[160..168].chunks_mut(4) {
+            d.copy_from_slice(&i32::MAX.to_le_bytes());
+        }
+        let mat_file = MatFile::parse(data.as_slice()).unwrap();
+        assert!(matches!(mat_file.values()[0], Value::Raw(_)));
+    }
+
+    #[test]
+    fn rewrite_files() {
+        let files: [&[u8]; 12] = [
+            include_bytes!("../tests/char_cell_struct.mat"),
+            include_bytes!("../tests/object.mat"),
+            include_bytes!("../tests/strings.mat"),
+            include_bytes!("../tests/classdef.mat"),
+            include_bytes!("../tests/multidimensional.mat"),
+            include_bytes!("../tests/sparse2.mat"),
+            include_bytes!("../tests/categorical.mat"),
+            include_bytes!("../tests/datetime.mat"),
+            include_bytes!("../tests/map.mat"),
+            include_bytes!("../tests/half.mat"),
+            include_bytes!("../tests/function_handles.mat"),
+            include_bytes!("../tests/table.mat"),
+        ];
+        for data in files.iter() {
+            let mat_file = MatFile::parse(*data).unwrap();
+            let mut written = Vec::new();
+            mat_file.write(&mut written).unwrap();
+            let rewritten = MatFile::parse(written.as_slice()).unwrap();
+            assert_eq!(
+                format!("{:?}", rewritten.values()),
+                format!("{:?}", mat_file.values())
+            );
+        }
+    }
+
+    #[test]
+    fn write_string_arrays() {
+        let strings = vec![
+            Some("alpha".to_owned()),
+            None,
+            Some(String::new()),
+            Some("wörld 🎉".to_owned()),
+        ];
+        let names = StringArray::new("names", vec![2, 2], strings.clone()).unwrap();
+        let scalar = StringArray::new("", vec![1, 1], vec![Some("in a cell".to_owned())]).unwrap();
+        let cell = CellArray::new("cell", vec![1, 1], vec![Value::String(scalar)]).unwrap();
+        let mut data = Vec::new();
+        MatFile::from_values(vec![Value::String(names), Value::Cell(cell)])
+            .unwrap()
+            .write(&mut data)
+            .unwrap();
+        let (_, parsed) = parse::parse_all(&data).unwrap();
+        assert!(parsed.subsystem.is_some());
+        let mat_file = MatFile::parse(data.as_slice()).unwrap();
+        match mat_file.find_value_by_name("names") {
+            Some(Value::String(names)) => {
+                assert_eq!(names.size(), &vec![2, 2]);
+                assert_eq!(names.strings(), &strings);
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("cell") {
+            Some(Value::Cell(cell)) => assert!(matches!(&cell.cells()[0],
+                Value::String(s) if s.strings() == &vec![Some("in a cell".to_owned())])),
+            other => panic!("Unexpected value {:?}", other),
+        }
+        assert!(StringArray::new("s", vec![1, 2], vec![None]).is_err());
+
+        // Unreadable subsystem data only affects the values holding objects
+        let array = Array::new_logical("mask", vec![1, 2], &[true, false]).unwrap();
+        let mut mat_file = mat_file;
+        mat_file.insert(Value::Numeric(array)).unwrap();
+        let mut data = Vec::new();
+        mat_file.write(&mut data).unwrap();
+        let (_, header) = parse::parse_header(&data).unwrap();
+        let subsystem_offset = header.subsystem_offset().unwrap() as usize;
+        // The metadata is a uint8 array starting with its version
+        let version = (subsystem_offset..data.len() - 12)
+            .find(|&i| data[i..i + 4] == [2, 0, 0, 0] && data[i + 8..i + 12] == [4, 0, 0, 0])
+            .unwrap()
+            + 8;
+        data[version] = 9;
+        let mut reader = std::io::Cursor::new(&data);
+        let index = MatFileIndex::scan(&mut reader).unwrap();
+        for mat_file in [
+            MatFile::parse(data.as_slice()).unwrap(),
+            MatFile::from_values(
+                ["names", "cell", "mask"]
+                    .iter()
+                    .map(|name| index.read_value(&mut reader, name).unwrap())
+                    .collect(),
+            )
+            .unwrap(),
+        ]
+        .iter()
+        {
+            assert!(matches!(
+                mat_file.find_value_by_name("names"),
+                Some(Value::Raw(_))
+            ));
+            assert!(matches!(
+                mat_file.find_value_by_name("cell"),
+                Some(Value::Raw(_))
+            ));
+            assert!(mat_file.find_by_name("mask").unwrap().is_logical());
+        }
+    }
+
+    #[test]
+    fn write_tables() {
+        let scores = Array::new(
+            "",
+            vec![3, 1],
+            NumericData::Double {
+                real: vec![1.5, 2.0, -3.0],
+                imag: None,
+            },
+        )
+        .unwrap();
+        let labels = StringArray::new(
+            "",
+            vec![3, 1],
+            vec![Some("a".to_owned()), None, Some("c".to_owned())],
+        )
+        .unwrap();
+        let sizes = CategoricalArray::new(
+            "",
+            vec![3, 1],
+            vec!["small".to_owned(), "large".to_owned()],
+            vec![Some(1), None, Some(0)],
+        )
+        .unwrap();
+        let names = vec!["Score".to_owned(), "Label".to_owned(), "Size".to_owned()];
+        let row_names = vec!["x".to_owned(), "y".to_owned(), "z".to_owned()];
+        let table = Table::new(
+            "t",
+            names.clone(),
+            vec![
+                Value::Numeric(scores),
+                Value::String(labels),
+                Value::Categorical(sizes.clone()),
+            ],
+        )
+        .unwrap()
+        .with_row_names(row_names.clone())
+        .unwrap();
+        let mat_file = write_and_parse(vec![Value::Table(table.clone())], &WriteOptions::new());
+        match mat_file.find_value_by_name("t") {
+            Some(Value::Table(written)) => {
+                assert_eq!(written.kind(), TableKind::Table);
+                assert_eq!(written.num_rows(), 3);
+                assert_eq!(written.variable_names(), &names);
+                assert_eq!(written.row_names(), &row_names);
+                assert_eq!(written.dimension_names(), &vec!["Row", "Variables"]);
+                assert_eq!(
+                    format!("{:?}", written.columns()),
+                    format!("{:?}", table.columns())
+                );
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        let sizes = CategoricalArray::new(
+            "sizes",
+            sizes.size().clone(),
+            sizes.categories().clone(),
+            sizes.codes().clone(),
+        )
+        .unwrap();
+        let mat_file = write_and_parse(vec![Value::Categorical(sizes)], &WriteOptions::new());
+        match mat_file.find_value_by_name("sizes") {
+            Some(Value::Categorical(written)) => {
+                assert_eq!(written.labels(), vec![Some("large"), None, Some("small")]);
+                assert!(!written.is_ordinal());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        // Loaded tables and timetables are written with all of their saved
+        // properties, including the row times of timetables
+        let data = include_bytes!("../tests/table.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        for name in ["results", "readings"].iter() {
+            let table = mat_file.find_value_by_name(name).unwrap().clone();
+            let rewritten = write_and_parse(vec![table.clone()], &WriteOptions::new());
+            assert_eq!(
+                format!("{:?}", rewritten.values()),
+                format!("{:?}", [table])
+            );
+        }
+        match write_and_parse(mat_file.values().clone(), &WriteOptions::new())
+            .find_value_by_name("readings")
+        {
+            Some(Value::Table(readings)) => {
+                assert_eq!(readings.kind(), TableKind::Timetable);
+                assert!(readings.row_times().is_some());
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+
+        let column = |rows| {
+            Value::Numeric(Array::new_logical("", vec![rows, 1], &vec![true; rows]).unwrap())
+        };
+        assert!(Table::new("t", vec!["a".to_owned()], vec![]).is_err());
+        assert!(Table::new(
+            "t",
+            vec!["a".to_owned(), "b".to_owned()],
+            vec![column(1), column(2)]
+        )
+        .is_err());
+        assert!(Table::new(
+            "t",
+            vec!["a".to_owned(), "a".to_owned()],
+            vec![column(1), column(1)]
+        )
+        .is_err());
+        assert!(Table::new("t", vec!["a".to_owned()], vec![column(2)])
+            .unwrap()
+            .with_row_names(vec!["r".to_owned()])
+            .is_err());
+        assert!(CategoricalArray::new("c", vec![1, 1], vec![], vec![Some(0)]).is_err());
+    }
+
+    #[test]
+    fn append_to_files() {
+        let scalar = |name: &str, value: f64| {
+            Value::Numeric(
+                Array::new(
+                    name,
+                    vec![1, 1],
+                    NumericData::Double {
+                        real: vec![value],
+                        imag: None,
+                    },
+                )
+                .unwrap(),
+            )
+        };
+        let names = |path: &std::path::Path| -> Vec<String> {
+            let mat_file = MatFile::parse(std::fs::File::open(path).unwrap()).unwrap();
+            mat_file
+                .values()
+                .iter()
+                .map(|value| value.name().to_owned())
+                .collect()
+        };
+        let dir = std::env::temp_dir();
+
+        // Variables before the first replaced one are kept as they are
+        let path = dir.join("matfile_append_uncompressed.mat");
+        let _ = std::fs::remove_file(&path);
+        let values = vec![scalar("x", 1.0), scalar("y", 2.0), scalar("z", 3.0)];
+        MatFile::from_values(values)
+            .unwrap()
+            .append_to(&path)
+            .unwrap();
+        let before = std::fs::read(&path).unwrap();
+        MatFile::from_values(vec![scalar("y", 4.0), scalar("w", 5.0)])
+            .unwrap()
+            .append_to(&path)
+            .unwrap();
+        let after = std::fs::read(&path).unwrap();
+        assert_eq!(after.len(), before.len() + 64);
+        assert_eq!(after[..128 + 64], before[..128 + 64]);
+        assert_eq!(names(&path), vec!["x", "z", "y", "w"]);
+        let mat_file = MatFile::parse(after.as_slice()).unwrap();
+        assert_eq!(
+            mat_file.find_by_name("y").unwrap().data().real_to_f64(),
+            vec![4.0]
+        );
+
+        // Compressed variables are located without being decoded
+        let path = dir.join("matfile_append_compressed.mat");
+        std::fs::write(&path, &include_bytes!("../tests/two_arrays.mat")[..]).unwrap();
+        let original = names(&path);
+        MatFile::from_values(vec![scalar(&original[0], 6.0)])
+            .unwrap()
+            .append_to(&path)
+            .unwrap();
+        assert_eq!(names(&path), vec![original[1].clone(), original[0].clone()]);
+
+        // Files with objects keep their variables and subsystem data as they
+        // are when no objects are added, the subsystem data is moved to the
+        // end
+        let path = dir.join("matfile_append_tables.mat");
+        let before = include_bytes!("../tests/table.mat");
+        std::fs::write(&path, &before[..]).unwrap();
+        let original = MatFile::parse(before.as_ref()).unwrap();
+        let subsystem = match parse::parse_all(before).unwrap().1.subsystem_range {
+            Some(range) => range,
+            None => panic!("No subsystem data"),
+        };
+        MatFile::from_values(vec![scalar("x", 1.0)])
+            .unwrap()
+            .append_to(&path)
+            .unwrap();
+        let after = std::fs::read(&path).unwrap();
+        assert_eq!(after[..116], before[..116]);
+        assert_eq!(after[124..subsystem.start], before[124..subsystem.start]);
+        assert_eq!(
+            after[after.len() - subsystem.len()..],
+            before[subsystem.clone()]
+        );
+        let mat_file = MatFile::parse(after.as_slice()).unwrap();
+        let mut expected = original.values().clone();
+        expected.push(scalar("x", 1.0));
+        assert_eq!(
+            format!("{:?}", mat_file.values()),
+            format!("{:?}", expected)
+        );
+
+        // Files are rewritten when objects are added
+        let path = dir.join("matfile_append_objects.mat");
+        std::fs::write(&path, &include_bytes!("../tests/strings.mat")[..]).unwrap();
+        let mut expected = names(&path);
+        let label = StringArray::new("label", vec![1, 1], vec![Some("new".to_owned())]).unwrap();
+        MatFile::from_values(vec![Value::String(label)])
+            .unwrap()
+            .append_to(&path)
+            .unwrap();
+        expected.push("label".to_owned());
+        assert_eq!(names(&path), expected);
+        let path = dir.join("matfile_append_datetime.mat");
+        std::fs::write(&path, &include_bytes!("../tests/datetime.mat")[..]).unwrap();
+        let mut expected = names(&path);
+        MatFile::from_values(vec![scalar("x", 1.0)])
+            .unwrap()
+            .append_to(&path)
+            .unwrap();
+        expected.push("x".to_owned());
+        assert_eq!(names(&path), expected);
+
+        // Trailing bytes that can't be read are not truncated
+        let path = dir.join("matfile_append_trailing.mat");
+        let _ = std::fs::remove_file(&path);
+        MatFile::from_values(vec![scalar("x", 1.0)])
+            .unwrap()
+            .append_to(&path)
+            .unwrap();
+        let mut before = std::fs::read(&path).unwrap();
+        before.extend_from_slice(&[0xff; 24]);
+        std::fs::write(&path, &before).unwrap();
+        assert!(matches!(
+            MatFile::from_values(vec![scalar("y", 2.0)])
+                .unwrap()
+                .append_to(&path),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+    }
+
+    #[test]
+    fn edit_values() {
+        let array = |name: &str| {
+            Value::Numeric(
+                Array::new(
+                    name,
+                    vec![1, 2],
+                    NumericData::Int8 {
+                        real: vec![1, 2],
+                        imag: None,
+                    },
+                )
+                .unwrap(),
+            )
+        };
+        let names = |mat_file: &MatFile| -> Vec<String> {
+            mat_file
+                .values()
+                .iter()
+                .map(|value| value.name().to_owned())
+                .collect()
+        };
+        let mut mat_file = MatFile::new();
+        for name in ["a", "b", "c", "d"].iter() {
+            assert!(mat_file.insert(array(name)).unwrap().is_none());
+        }
+        assert!(mat_file.insert(array("1x")).is_err());
+        let mut replacement = Array::new_logical("b", vec![1, 1], &[true]).unwrap();
+        assert!(replacement.reshape(vec![1, 2]).is_err());
+        replacement
+            .set_data(
+                vec![2, 1],
+                NumericData::Double {
+                    real: vec![3.0, 4.0],
+                    imag: None,
+                },
+            )
+            .unwrap();
+        replacement.reshape(vec![1, 1, 2]).unwrap();
+        let previous = mat_file.insert(Value::Numeric(replacement)).unwrap();
+        assert!(matches!(previous, Some(Value::Numeric(array)) if array.size() == &vec![1, 2]));
+        assert_eq!(mat_file.find_by_name("b").unwrap().size(), &vec![1, 1, 2]);
+        assert_eq!(mat_file.arrays()[1].size(), &vec![1, 1, 2]);
+        assert_eq!(names(&mat_file), vec!["a", "b", "c", "d"]);
+
+        assert_eq!(mat_file.remove("a").unwrap().name(), "a");
+        assert!(mat_file.remove("a").is_none());
+        assert_eq!(mat_file.position("d"), Some(2));
+        mat_file.rename("c", "e").unwrap();
+        assert!(mat_file.rename("c", "f").is_err());
+        assert!(mat_file.rename("e", "b").is_err());
+        assert!(mat_file.find_value_by_name("c").is_none());
+        assert_eq!(mat_file.find_value_by_name("e").unwrap().name(), "e");
+        mat_file.move_to("d", 0).unwrap();
+        assert!(mat_file.move_to("d", 3).is_err());
+        assert_eq!(names(&mat_file), vec!["d", "b", "e"]);
+        assert_eq!(mat_file.arrays()[0].name(), "d");
+        assert_eq!(mat_file.position("e"), Some(2));
+        mat_file.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(names(&mat_file), vec!["b", "d", "e"]);
+        assert_eq!(mat_file.position("b"), Some(0));
+
+        let rewritten = write_and_parse(mat_file.clone().into_values(), &WriteOptions::new());
+        assert_eq!(format!("{:?}", rewritten), format!("{:?}", mat_file));
+    }
+
+    #[test]
+    fn keep_undecodable_values() {
+        let data = include_bytes!("../tests/undecodable.mat");
+        let mut mat_file = MatFile::parse(data.as_ref()).unwrap();
+        let raw_values: Vec<(&str, Option<&str>)> = mat_file
+            .values()
+            .iter()
+            .filter_map(|value| match value {
+                Value::Raw(raw) => Some((raw.name(), raw.class_name())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            raw_values,
+            vec![
+                ("javaobj", Some("java.lang.Double")),
+                ("mixed", Some("cell"))
+            ]
+        );
+        let before = Array::new_logical("before", vec![1, 1], &[false]).unwrap();
+        mat_file.insert(Value::Numeric(before)).unwrap();
+        let mut written = Vec::new();
+        mat_file.write(&mut written).unwrap();
+        let rewritten = MatFile::parse(written.as_slice()).unwrap();
+        assert_eq!(
+            format!("{:?}", rewritten.values()),
+            format!("{:?}", mat_file.values())
+        );
+        // Raw values keep their name, which is stored in their data
+        assert!(mat_file.rename("javaobj", "renamed").is_err());
+
+        // Supported values that are invalid aren't kept as raw values
+        let sparse = SparseArray::from_triplets("a", 1, 1, &[(0, 0, 1.0)]).unwrap();
+        let mut data = Vec::new();
+        MatFile::from_values(vec![Value::Sparse(sparse)])
+            .unwrap()
+            .write(&mut data)
+            .unwrap();
+        // Store the double value as int8 values, which aren't converted
+        let len = data.len();
+        assert_eq!(data[len - 16..len - 8], [9, 0, 0, 0, 8, 0, 0, 0]);
+        data[len - 16] = 1;
+        assert!(matches!(
+            MatFile::parse(data.as_slice()),
+            Err(Error::ConversionError)
+        ));
+        let mut values = MatFileReader::new(data.as_slice()).unwrap();
+        assert!(matches!(values.next(), Some(Err(Error::ConversionError))));
+        let mut reader = std::io::Cursor::new(&data);
+        let index = MatFileIndex::scan(&mut reader).unwrap();
+        assert!(matches!(
+            index.read_value(&mut reader, "a"),
+            Err(Error::ConversionError)
+        ));
+    }
+
+    #[test]
+    fn write_raw_object_references() {
+        let string = StringArray::new("", vec![1, 1], vec![Some("kept".to_owned())]).unwrap();
+        let text = CharArray::from_text("", "x");
+        let mixed = CellArray::new(
+            "mixed",
+            vec![1, 2],
+            vec![Value::String(string), Value::Char(text)],
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        MatFile::from_values(vec![Value::Cell(mixed)])
+            .unwrap()
+            .write(&mut data)
+            .unwrap();
+        // Turn the character array into a function handle without a
+        // description, which can't be decoded
+        let char_flags = [
+            6,
+            0,
+            0,
+            0,
+            8,
+            0,
+            0,
+            0,
+            parse::ArrayType::Char as u8,
+            0,
+            0,
+            0,
+        ];
+        let position = data
+            .windows(char_flags.len())
+            .position(|window| window == char_flags)
+            .unwrap();
+        data[position + 8] = parse::ArrayType::Function as u8;
+        // The function handle makes the cell undecodable, but its string is
+        // still stored in the subsystem data
+        let mat_file = MatFile::parse(data.as_slice()).unwrap();
+        assert!(matches!(mat_file.values()[0], Value::Raw(_)));
+        let string_of = |data: &[u8]| {
+            let (_, parsed) = parse::parse_all(data).unwrap();
+            let subsystem = mcos::Subsystem::from_data_element(
+                parsed.subsystem.unwrap(),
+                nom::number::Endianness::Little,
+            )
+            .unwrap();
+            match parsed.data_elements[0].0.clone() {
+                parse::DataElement::CellMatrix(_, _, _, cells) => {
+                    match Value::try_from(cells[0].clone(), &subsystem).unwrap() {
+                        Some(Value::String(string)) => string.strings().clone(),
+                        other => panic!("Unexpected value {:?}", other),
+                    }
+                }
+                other => panic!("Unexpected data element {:?}", other),
+            }
+        };
+        let mut written = Vec::new();
+        mat_file.write(&mut written).unwrap();
+        assert_eq!(string_of(&written), vec![Some("kept".to_owned())]);
+        let mut values = MatFileReader::new(data.as_slice()).unwrap();
+        let mut written = Vec::new();
+        MatFile::from_values(vec![values.next().unwrap().unwrap()])
+            .unwrap()
+            .write(&mut written)
+            .unwrap();
+        assert_eq!(string_of(&written), vec![Some("kept".to_owned())]);
+        let mut reader = std::io::Cursor::new(&data);
+        let index = MatFileIndex::scan(&mut reader).unwrap();
+        let mut written = Vec::new();
+        MatFile::from_values(vec![index.read_value(&mut reader, "mixed").unwrap()])
+            .unwrap()
+            .write(&mut written)
+            .unwrap();
+        assert_eq!(string_of(&written), vec![Some("kept".to_owned())]);
+
+        // The object ids of the kept subsystem data can't be mixed with new ones
+        let label = StringArray::new("label", vec![1, 1], vec![None]).unwrap();
+        let mut mat_file = mat_file;
+        mat_file.insert(Value::String(label)).unwrap();
+        assert!(matches!(
+            mat_file.write(&mut Vec::new()),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        // Raw values keep their name and compression, so they can't be nested
+        let raw = mat_file.find_value_by_name("mixed").unwrap().clone();
+        let cell = CellArray::new("cell", vec![1, 1], vec![raw]).unwrap();
+        assert!(matches!(
+            MatFile::from_values(vec![Value::Cell(cell)])
+                .unwrap()
+                .write(&mut Vec::new()),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn resolve_overflowing_references() {
+        let flags = parse::ArrayFlags {
+            complex: false,
+            global: false,
+            logical: false,
+            class: parse::ArrayType::UInt32,
+            nzmax: 0,
+        };
+        let references = [
+            vec![
+                mcos::OBJECT_REFERENCE_MARKER,
+                3,
+                u32::MAX,
+                u32::MAX,
+                u32::MAX,
+                1,
+            ],
+            vec![mcos::OBJECT_REFERENCE_MARKER, 2, u32::MAX, 1, 1],
+        ];
+        for reference in references.iter() {
+            let reference = parse::DataElement::NumericMatrix(
+                flags,
+                vec![1, reference.len() as i32],
+                String::new(),
+                parse::NumericData::UInt32(reference.clone()),
+                None,
+            );
+            let resolved = mcos::Subsystem::default().resolve(
+                "x".to_owned(),
+                "MCOS",
+                "string".to_owned(),
+                reference,
+            );
+            assert!(matches!(resolved, Ok(None)));
+        }
+    }
+
+    #[test]
+    fn write_compressed() {
+        let data = include_bytes!("../tests/char_cell_struct.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        let options = WriteOptions::new().compress(true);
+        let rewritten = write_and_parse(mat_file.values().clone(), &options);
+        assert_eq!(
+            format!("{:?}", rewritten.values()),
+            format!("{:?}", mat_file.values())
+        );
+    }
+
+    #[test]
+    fn stream_arrays() {
+        for &compress in [false, true].iter() {
+            let options = WriteOptions::new().compress(compress);
+            let mut writer =
+                MatFileWriter::with_options(std::io::Cursor::new(Vec::new()), &options).unwrap();
+            let label = StringArray::new("label", vec![1, 1], vec![Some("run".to_owned())]);
+            writer.write_value(&Value::String(label.unwrap())).unwrap();
+            let mut samples = writer.begin_array("samples", 3).unwrap();
+            for column in 0..1000 {
+                let value = column as f64;
+                samples
+                    .append_column(&NumericData::Double {
+                        real: vec![value, -value, value / 2.0],
+                        imag: None,
+                    })
+                    .unwrap();
+            }
+            assert!(samples
+                .append(&NumericData::Single {
+                    real: vec![1.0],
+                    imag: None
+                })
+                .is_err());
+            assert!(samples
+                .append_column(&NumericData::Double {
+                    real: vec![1.0],
+                    imag: None
+                })
+                .is_err());
+            samples
+                .append(&NumericData::Double {
+                    real: vec![1.0, 2.0, 3.0],
+                    imag: None,
+                })
+                .unwrap();
+            assert_eq!(samples.num_columns(), 1001);
+            samples.finish().unwrap();
+            let mut counts = writer.begin_array("counts", 2).unwrap();
+            counts
+                .append(&NumericData::UInt8 {
+                    real: vec![1, 2, 3, 4],
+                    imag: None,
+                })
+                .unwrap();
+            counts.finish().unwrap();
+            let mut empty = writer.begin_array("empty", 4).unwrap();
+            assert!(empty
+                .append(&NumericData::Double {
+                    real: vec![1.0],
+                    imag: Some(vec![1.0]),
+                })
+                .is_err());
+            empty.finish().unwrap();
+            assert!(writer.begin_array("empty", 1).is_err());
+            let data = writer.finish().unwrap().into_inner();
+
+            let mat_file = MatFile::parse(data.as_slice()).unwrap();
+            let samples = mat_file.find_by_name("samples").unwrap();
+            assert_eq!(samples.size(), &vec![3, 1001]);
+            let real = samples.data().real_to_f64();
+            assert_eq!(
+                real[3 * 999..3 * 1001],
+                [999.0, -999.0, 499.5, 1.0, 2.0, 3.0]
+            );
+            let counts = mat_file.find_by_name("counts").unwrap();
+            assert_eq!(counts.size(), &vec![2, 2]);
+            assert!(matches!(counts.data(), NumericData::UInt8 { .. }));
+            assert_eq!(mat_file.find_by_name("empty").unwrap().size(), &vec![4, 0]);
+            assert!(matches!(
+                mat_file.find_value_by_name("label"),
+                Some(Value::String(_))
+            ));
+
+            let mut writer = MatFileWriter::new(std::io::Cursor::new(Vec::new())).unwrap();
+            let mut incomplete = writer.begin_array("incomplete", 2).unwrap();
+            incomplete
+                .append(&NumericData::Int16 {
+                    real: vec![1, 2, 3],
+                    imag: None,
+                })
+                .unwrap();
+            assert!(incomplete.finish().is_err());
+        }
+    }
+
+    /// Returns at most a few bytes per read, like a slow pipe.
+    struct Trickle<R>(R);
+
+    impl<R: std::io::Read> std::io::Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(7);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn stream_values() {
+        let files = [
+            "categorical.mat",
+            "char_cell_struct.mat",
+            "classdef.mat",
+            "double_as_uint8.mat",
+            "empty.mat",
+            "function_handles.mat",
+            "map.mat",
+            "multidimensional.mat",
+            "sparse2.mat",
+            "strings.mat",
+            "table.mat",
+            "two_arrays.mat",
+            "undecodable.mat",
+        ];
+        for file in files.iter() {
+            let data = std::fs::read(format!("tests/{}", file)).unwrap();
+            let mat_file = MatFile::parse(data.as_slice()).unwrap();
+            let reader = MatFileReader::new(Trickle(data.as_slice())).unwrap();
+            let mut values = reader.collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(values.len(), mat_file.values().len());
+            // Variables holding objects come last
+            let mut expected = mat_file.into_values();
+            values.sort_by(|a, b| a.name().cmp(b.name()));
+            expected.sort_by(|a, b| a.name().cmp(b.name()));
+            for (value, expected) in values.iter().zip(&expected) {
+                match (value, expected) {
+                    // Compressed raw values are kept inflated
+                    (Value::Raw(value), Value::Raw(expected)) => {
+                        assert_eq!(value.name(), expected.name());
+                        assert_eq!(value.class_name(), expected.class_name());
+                    }
+                    _ => assert_eq!(format!("{:?}", value), format!("{:?}", expected)),
+                }
+            }
+        }
+
+        let data = include_bytes!("../tests/two_arrays.mat");
+        let mut reader = MatFileReader::new(&data[..data.len() - 10]).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+        assert!(MatFileReader::new(&data[..100]).is_err());
+    }
+
+    #[test]
+    fn read_values() {
+        let files = [
+            "categorical.mat",
+            "char_cell_struct.mat",
+            "classdef.mat",
+            "double_as_uint8.mat",
+            "map.mat",
+            "sparse2.mat",
+            "strings.mat",
+            "table.mat",
+            "two_arrays.mat",
+            "undecodable.mat",
+        ];
+        for file in files.iter() {
+            let data = std::fs::read(format!("tests/{}", file)).unwrap();
+            let mat_file = MatFile::parse(data.as_slice()).unwrap();
+            let mut reader = std::io::Cursor::new(&data);
+            let index = MatFileIndex::scan(&mut reader).unwrap();
+            assert!(!index.variables().is_empty());
+            for variable in index.variables() {
+                let value = index.read_value(&mut reader, variable.name()).unwrap();
+                let expected = mat_file.find_value_by_name(variable.name()).unwrap();
+                assert_eq!(format!("{:?}", value), format!("{:?}", expected));
+            }
+        }
+
+        let data = include_bytes!("../tests/two_arrays.mat");
+        let mut reader = std::io::Cursor::new(&data[..]);
+        let index = MatFileIndex::scan(&mut reader).unwrap();
+        assert!(index.read_value(&mut reader, "missing").is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_io() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for file in ["strings.mat", "table.mat", "two_arrays.mat"].iter() {
+            let data = std::fs::read(format!("tests/{}", file)).unwrap();
+            let mat_file = MatFile::parse(data.as_slice()).unwrap();
+            runtime.block_on(async {
+                let parsed = MatFile::parse_async(data.as_slice()).await.unwrap();
+                assert_eq!(format!("{:?}", parsed), format!("{:?}", mat_file));
+                let mut reader = std::io::Cursor::new(&data);
+                let index = MatFileIndex::scan_async(&mut reader).await.unwrap();
+                assert_eq!(index.variables().len(), mat_file.values().len());
+                for variable in index.variables() {
+                    let value = index
+                        .read_value_async(&mut reader, variable.name())
+                        .await
+                        .unwrap();
+                    let expected = mat_file.find_value_by_name(variable.name()).unwrap();
+                    assert_eq!(format!("{:?}", value), format!("{:?}", expected));
+                }
+            });
+        }
+
+        // Large enough for checkpoints when compressed
+        let real = (0..200_000).map(|x| (x % 1000) as f64).collect();
+        let array = Array::new(
+            "a",
+            vec![400, 500],
+            NumericData::Double { real, imag: None },
+        );
+        let label = CharArray::from_text("label", "samples");
+        let mat_file =
+            MatFile::from_values(vec![Value::Numeric(array.unwrap()), Value::Char(label)]).unwrap();
+        for &compress in [false, true].iter() {
+            let options = WriteOptions::new().compress(compress);
+            let mut expected = Vec::new();
+            mat_file
+                .write_with_options(&mut expected, &options)
+                .unwrap();
+            runtime.block_on(async {
+                let mut data = Vec::new();
+                mat_file
+                    .write_with_options_async(&mut data, &options)
+                    .await
+                    .unwrap();
+                assert_eq!(data, expected);
+
+                let mut reader = std::io::Cursor::new(&data);
+                let index = MatFileIndex::scan_with_checkpoints_async(&mut reader, 100_000)
+                    .await
+                    .unwrap();
+                reader.set_position(0);
+                let expected = MatFileIndex::scan_with_checkpoints(&mut reader, 100_000).unwrap();
+                assert_eq!(format!("{:?}", index), format!("{:?}", expected));
+                assert_eq!(
+                    index.find_by_name("a").unwrap().num_checkpoints() > 0,
+                    compress
+                );
+                let value = index.read_value_async(&mut reader, "a").await.unwrap();
+                let expected = mat_file.find_value_by_name("a").unwrap();
+                assert_eq!(format!("{:?}", value), format!("{:?}", expected));
+                assert!(MatFileIndex::scan_with_checkpoints_async(&mut reader, 0)
+                    .await
+                    .is_err());
+            });
+        }
+    }
+
+    /// Replaces the compressed data elements of a file by their contents,
+    /// keeping the storage types chosen by Matlab.
+    fn decompress(data: &[u8]) -> Vec<u8> {
+        use std::io::Read;
+        let (_remaining, (_header, locations)) = parse::locate_data_elements(data).unwrap();
+        let mut decompressed = data[..128].to_vec();
+        for location in locations {
+            let element = &data[location.start..location.end];
+            if element[..4] == [15, 0, 0, 0] {
+                libflate::zlib::Decoder::new(&element[8..])
+                    .unwrap()
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+                decompressed.resize(decompressed.len() + write::padding(decompressed.len()), 0);
+            } else {
+                decompressed.extend_from_slice(element);
+            }
+        }
+        decompressed
+    }
+
+    #[test]
+    fn read_regions() {
+        let files = [
+            "double.mat",
+            "double_as_int16.mat",
+            "double_as_uint8.mat",
+            "multidimensional.mat",
+            "single_complex.mat",
+            "two_arrays.mat",
+        ];
+        for file in files.iter() {
+            let compressed = std::fs::read(format!("tests/{}", file)).unwrap();
+            let mat_file = MatFile::parse(compressed.as_slice()).unwrap();
+            for data in [decompress(&compressed), compressed].iter() {
+                let mut reader = std::io::Cursor::new(data);
+                let index = MatFileIndex::scan(&mut reader).unwrap();
+                assert_eq!(index.variables().len(), mat_file.values().len());
+                for array in mat_file.arrays() {
+                    let ranges: Vec<_> = array.size().iter().map(|&d| 0..d).collect();
+                    let region = index
+                        .read_region(&mut reader, array.name(), &ranges)
+                        .unwrap();
+                    assert_eq!(format!("{:?}", region), format!("{:?}", array));
+                }
+            }
+        }
+
+        let real: Vec<i16> = (0..60).collect();
+        let imag = real.iter().map(|x| -x).collect();
+        let array = Array::new(
+            "a",
+            vec![4, 5, 3],
+            NumericData::Int16 {
+                real,
+                imag: Some(imag),
+            },
+        )
+        .unwrap();
+        let name = CharArray::from_text("name", "a");
+        let mut data = Vec::new();
+        MatFile::from_values(vec![Value::Char(name), Value::Numeric(array)])
+            .unwrap()
+            .write(&mut data)
+            .unwrap();
+        let mut reader = std::io::Cursor::new(&data);
+        let index = MatFileIndex::scan(&mut reader).unwrap();
+        let info = index.find_by_name("a").unwrap();
+        assert_eq!(info.class_name(), "int16");
+        assert_eq!(info.size(), Some(&[4, 5, 3][..]));
+        assert!(info.is_complex() && !info.is_compressed());
+        let region = index
+            .read_region(&mut reader, "a", &[1..3, 2..5, 1..3])
+            .unwrap();
+        assert_eq!(region.size(), &vec![2, 3, 2]);
+        let mut expected = Vec::new();
+        for k in 1..3 {
+            for j in 2..5 {
+                for i in 1..3 {
+                    expected.push(i + 4 * j + 20 * k);
+                }
+            }
+        }
+        match region.data() {
+            NumericData::Int16 {
+                real,
+                imag: Some(imag),
+            } => {
+                assert_eq!(real, &expected);
+                assert_eq!(imag, &expected.iter().map(|x| -x).collect::<Vec<_>>());
+            }
+            data => panic!("unexpected data {:?}", data),
+        }
+        let empty = index.read_region(&mut reader, "a", &[1..1, 0..5, 0..3]);
+        assert_eq!(empty.unwrap().size(), &vec![0, 5, 3]);
+        assert!(index.read_region(&mut reader, "a", &[0..4, 0..5]).is_err());
+        assert!(index
+            .read_region(&mut reader, "a", &[0..4, 0..6, 0..3])
+            .is_err());
+        assert!(index
+            .read_region(&mut reader, "name", &[0..1, 0..1])
+            .is_err());
+        assert!(index.read_region(&mut reader, "b", &[0..1, 0..1]).is_err());
+    }
+
+    #[test]
+    fn parse_corrupt_index() {
+        use std::io::{Read, Write};
+        let data = NumericData::Double {
+            real: (0..8).map(f64::from).collect(),
+            imag: None,
+        };
+        let array = Array::new("a", vec![2, 2, 2], data).unwrap();
+        let mut data = Vec::new();
+        MatFile::from_values(vec![Value::Numeric(array)])
+            .unwrap()
+            .write(&mut data)
+            .unwrap();
+        let mut saved = Vec::new();
+        let index = MatFileIndex::scan(&mut std::io::Cursor::new(&data)).unwrap();
+        index.write(&mut saved).unwrap();
+        let mut body = Vec::new();
+        libflate::zlib::Decoder::new(&saved[12..])
+            .unwrap()
+            .read_to_end(&mut body)
+            .unwrap();
+        let parse_patched = |offset: usize, bytes: &[u8]| {
+            let mut patched = body.clone();
+            patched[offset..offset + bytes.len()].copy_from_slice(bytes);
+            let mut encoder = libflate::zlib::Encoder::new(saved[..12].to_vec()).unwrap();
+            encoder.write_all(&patched).unwrap();
+            let saved = encoder.finish().into_result().unwrap();
+            MatFileIndex::parse(saved.as_slice())
+        };
+        // The variable starts after the endianness, the missing subsystem
+        // and the number of variables
+        let end = (data.len() as u64).to_le_bytes();
+        assert_eq!(body[14..22], end);
+        assert!(parse_patched(14, &end).is_ok());
+        // The data position is past the end of the variable
+        assert!(parse_patched(22, &(data.len() as u64 + 1).to_le_bytes()).is_err());
+        // The number of elements overflows
+        assert_eq!(
+            body[38..54],
+            [3, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0]
+        );
+        let dimension = i32::MAX.to_le_bytes();
+        assert!(parse_patched(42, &[dimension, dimension, dimension].concat()).is_err());
+        // The real part is outside of the variable
+        assert_eq!(body[82..90], 56u64.to_le_bytes());
+        assert!(parse_patched(82, &64u64.to_le_bytes()).is_err());
+        assert!(parse_patched(82, &(u64::MAX - 8).to_le_bytes()).is_err());
+    }
+
+    #[test]
+    fn read_compressed_regions() {
+        let value_at = |i: usize, j: usize| ((i * 7919 + j * 104_729) % 1000) as f64 / 8.0;
+        let mut real = Vec::new();
+        for j in 0..500 {
+            for i in 0..400 {
+                real.push(value_at(i, j));
+            }
+        }
+        let array = Array::new(
+            "a",
+            vec![400, 500],
+            NumericData::Double { real, imag: None },
+        );
+        let label = CharArray::from_text("label", "samples");
+        let mut data = Vec::new();
+        MatFile::from_values(vec![Value::Char(label), Value::Numeric(array.unwrap())])
+            .unwrap()
+            .write_with_options(&mut data, &WriteOptions::new().compress(true))
+            .unwrap();
+
+        let mut reader = std::io::Cursor::new(&data);
+        let index = MatFileIndex::scan_with_checkpoints(&mut reader, 100_000).unwrap();
+        assert!(index.find_by_name("a").unwrap().num_checkpoints() > 2);
+        assert_eq!(index.find_by_name("label").unwrap().num_checkpoints(), 0);
+        let mut saved = Vec::new();
+        index.write(&mut saved).unwrap();
+        reader.set_position(0);
+        let indices = [
+            MatFileIndex::scan(&mut reader).unwrap(),
+            MatFileIndex::parse(saved.as_slice()).unwrap(),
+            index,
+        ];
+        for index in indices.iter() {
+            assert_eq!(index.variables()[0].class_name(), "char");
+            let regions = [[390..400, 450..500], [5..6, 100..400], [0..400, 0..500]];
+            for ranges in regions.iter() {
+                let region = index.read_region(&mut reader, "a", ranges).unwrap();
+                let mut expected = Vec::new();
+                for j in ranges[1].clone() {
+                    for i in ranges[0].clone() {
+                        expected.push(value_at(i, j));
+                    }
+                }
+                assert_eq!(region.data().real_to_f64(), expected);
+            }
+        }
+
+        let mut writer = MatFileWriter::with_options(
+            std::io::Cursor::new(Vec::new()),
+            &WriteOptions::new().compress(true),
+        )
+        .unwrap();
+        let mut samples = writer.begin_array("samples", 2).unwrap();
+        for column in 0..10_000 {
+            let value = column as f64;
+            samples
+                .append_column(&NumericData::Double {
+                    real: vec![value, -value],
+                    imag: None,
+                })
+                .unwrap();
+        }
+        samples.finish().unwrap();
+        let mut reader = writer.finish().unwrap();
+        reader.set_position(0);
+        let index = MatFileIndex::scan_with_checkpoints(&mut reader, 10_000).unwrap();
+        let region = index
+            .read_region(&mut reader, "samples", &[1..2, 9000..9002])
+            .unwrap();
+        assert_eq!(region.data().real_to_f64(), vec![-9000.0, -9001.0]);
+
+        assert!(MatFileIndex::scan_with_checkpoints(&mut reader, 0).is_err());
+        assert!(MatFileIndex::parse(&b"MATINDEX\x02\0\0\0"[..]).is_err());
+        assert!(MatFileIndex::parse(&saved[..saved.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn write_validation() {
+        let data = NumericData::Double {
+            real: vec![1.0, 2.0],
+            imag: None,
+        };
+        assert!(Array::new("x", vec![3], data.clone()).is_err());
+        assert!(Array::new("x", vec![1, 3], data.clone()).is_err());
+        let x = Value::Numeric(Array::new("x", vec![1, 2], data).unwrap());
+        assert!(MatFile::from_values(vec![x.clone(), x.clone()]).is_err());
+        let unnamed = Value::Char(CharArray::from_text("1abc", "text"));
+        assert!(MatFile::from_values(vec![x, unnamed]).is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn datetimes_to_chrono() {
+        let data = include_bytes!("../tests/datetime.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        match mat_file.find_value_by_name("launch") {
+            Some(Value::Datetime(launch)) => {
+                let times = launch.to_chrono();
+                assert_eq!(times[0].unwrap().to_rfc3339(), "2021-02-18T20:55:00+00:00");
+                assert_eq!(times[1], None);
+                assert_eq!(times[2].unwrap().timestamp_nanos_opt(), Some(1_000_000_123));
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match mat_file.find_value_by_name("elapsed") {
+            Some(Value::Duration(elapsed)) => {
+                let durations = elapsed.to_chrono();
+                assert_eq!(durations[0], Some(chrono::Duration::milliseconds(1500)));
+                assert_eq!(durations[2], None);
+            }
+            other => panic!("Unexpected value {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "half")]
+    #[test]
+    fn half_precision_to_f16() {
+        let data = include_bytes!("../tests/half.mat");
+        let mat_file = MatFile::parse(data.as_ref()).unwrap();
+        let samples = mat_file.find_by_name("samples").unwrap();
+        let (real, _) = samples.data().half_to_f16().unwrap();
+        assert_eq!(real[1], half::f16::from_f32(-2.0));
+        assert_eq!(real[3], half::f16::MAX);
+    }
 }
@@ -0,0 +1,194 @@
+use crate::{CellArray, CharArray, ClassdefArray, Error, StructArray, Value};
+
+/// A key of a `containers.Map`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapKey {
+    /// A key of a map with `char` key type
+    Char(String),
+    /// A key of a map with a numeric key type. Matlab supports integer key
+    /// types too, all of which can be represented exactly as `f64`.
+    Numeric(f64),
+}
+
+/// A Matlab `containers.Map`.
+///
+/// The entries are kept in the order they are stored in the file, which is
+/// sorted by key.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/map.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::Map(config)) = mat_file.find_value_by_name("config") {
+///     if let Some(matfile::Value::Char(host)) = config.get_char("host") {
+///         println!("Host: {}", host);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Map {
+    pub(crate) name: String,
+    key_type: String,
+    value_type: String,
+    entries: Vec<(MapKey, Value)>,
+    /// The properties of the object this was loaded from, which are written
+    /// instead of encoding the fields again
+    saved_properties: Option<Vec<(String, Value)>>,
+}
+
+impl std::fmt::Debug for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Map")
+            .field("name", &self.name)
+            .field("key_type", &self.key_type)
+            .field("value_type", &self.value_type)
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl Map {
+    /// The name of this map.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The Matlab type of the keys, `char` or a numeric type like `double`
+    /// or `int32`.
+    pub fn key_type(&self) -> &str {
+        &self.key_type
+    }
+
+    /// The Matlab type of the values, `any` unless all values have the same
+    /// type (like `char` or `double`).
+    pub fn value_type(&self) -> &str {
+        &self.value_type
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The keys and values of all entries.
+    pub fn entries(&self) -> &Vec<(MapKey, Value)> {
+        &self.entries
+    }
+
+    /// Returns the value stored under `key`, if there is one.
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the value stored under the character key `key`.
+    pub fn get_char(&self, key: &str) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find_map(|(entry_key, value)| match entry_key {
+                MapKey::Char(entry_key) if entry_key == key => Some(value),
+                _ => None,
+            })
+    }
+
+    /// Returns the value stored under the numeric key `key`.
+    pub fn get_numeric(&self, key: f64) -> Option<&Value> {
+        self.get(&MapKey::Numeric(key))
+    }
+
+    /// Decodes a `containers.Map` object. Its only property `serialization`
+    /// is a structure with the fields `keys` and `values`, both cell arrays,
+    /// as well as `keyType` and `valueType`.
+    pub(crate) fn from_classdef(array: &ClassdefArray) -> Option<Self> {
+        if array.size().iter().product::<usize>() != 1 {
+            return None;
+        }
+        let serialization = match array.property(0, "serialization")? {
+            Value::Struct(serialization) => serialization,
+            _ => return None,
+        };
+        let text_field = |field_name| match serialization.field(0, field_name) {
+            Some(Value::Char(text)) => Some(text.to_string()),
+            _ => None,
+        };
+        let cells = |field_name| match serialization.field(0, field_name) {
+            Some(Value::Cell(cell)) => Some(cell.cells()),
+            _ => None,
+        };
+        let keys = cells("keys")?;
+        let values = cells("values")?;
+        if keys.len() != values.len() {
+            return None;
+        }
+        let entries = keys
+            .iter()
+            .zip(values)
+            .map(|(key, value)| {
+                let key = match key {
+                    Value::Char(key) => MapKey::Char(key.to_string()),
+                    Value::Numeric(key) => MapKey::Numeric(*key.data().real_to_f64().first()?),
+                    _ => return None,
+                };
+                Some((key, value.clone()))
+            })
+            .collect::<Option<_>>()?;
+        Some(Map {
+            name: array.name().to_owned(),
+            key_type: text_field("keyType").unwrap_or_else(|| "char".to_owned()),
+            value_type: text_field("valueType").unwrap_or_else(|| "any".to_owned()),
+            entries,
+            saved_properties: array.properties(0).cloned(),
+        })
+    }
+
+    /// Encodes the map as the properties of a `containers.Map` object, see
+    /// `from_classdef`. Numeric keys are stored as doubles, except in
+    /// loaded maps, which keep their saved properties.
+    pub(crate) fn to_properties(&self) -> Result<Vec<(String, Value)>, Error> {
+        if let Some(saved_properties) = &self.saved_properties {
+            return Ok(saved_properties.clone());
+        }
+        let size = vec![1, self.entries.len()];
+        let keys = self
+            .entries
+            .iter()
+            .map(|(key, _)| match key {
+                MapKey::Char(key) => Value::Char(CharArray::from_text("", key)),
+                MapKey::Numeric(key) => Value::from_f64(*key),
+            })
+            .collect();
+        let values = self
+            .entries
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect();
+        let serialization = StructArray::new(
+            "",
+            vec![1, 1],
+            ["keys", "values", "uniformity", "keyType", "valueType"]
+                .iter()
+                .map(|&field_name| field_name.to_owned())
+                .collect(),
+            vec![
+                Value::Cell(CellArray::new("", size.clone(), keys)?),
+                Value::Cell(CellArray::new("", size, values)?),
+                Value::from_bool(self.value_type != "any"),
+                Value::Char(CharArray::from_text("", &self.key_type)),
+                Value::Char(CharArray::from_text("", &self.value_type)),
+            ],
+        )?;
+        Ok(vec![(
+            "serialization".to_owned(),
+            Value::Struct(serialization),
+        )])
+    }
+}
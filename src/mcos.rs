@@ -0,0 +1,716 @@
+//! Matlab stores objects of classes defined with `classdef` (as well as many
+//! built-in types like `string` or `table`) in the subsystem data of a file,
+//! which is managed by the Matlab Class Object System ("MCOS"). The variables
+//! themselves only hold references into it.
+//!
+//! The subsystem data holds a single object of the internal `FileWrapper__`
+//! class, which is a cell array. Its first cell contains metadata describing
+//! all classes and objects, while the property values are stored in the
+//! remaining cells.
+
+use crate::parse;
+use crate::{Array, CharArray, Error, Value};
+use nom::bytes::complete::take;
+use nom::combinator::complete;
+use nom::multi::{count, many0};
+use nom::number::complete::u32;
+use nom::sequence::tuple;
+use nom::{error_position, IResult};
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// The first element of a uint32 array referencing objects in the subsystem
+pub(crate) const OBJECT_REFERENCE_MARKER: u32 = 0xDD00_0000;
+
+/// An array of objects of a class defined with `classdef`.
+///
+/// Each object has its own list of properties. Properties that still have
+/// their default value are usually not saved by Matlab.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/classdef.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::Classdef(sensor)) = mat_file.find_value_by_name("sensor") {
+///     println!("Object of class {}", sensor.class_name());
+///     println!("Gain: {:?}", sensor.property(0, "Gain"));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClassdefArray {
+    pub(crate) name: String,
+    class_name: String,
+    size: Vec<usize>,
+    objects: Vec<Vec<(String, Value)>>,
+}
+
+impl ClassdefArray {
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of the class of the objects in this array, including the
+    /// package name if there is one (e.g. `containers.Map`).
+    pub fn class_name(&self) -> &str {
+        &self.class_name
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.size.len()
+    }
+
+    /// The saved properties of all objects.
+    pub(crate) fn objects(&self) -> &Vec<Vec<(String, Value)>> {
+        &self.objects
+    }
+
+    /// The saved properties of the object at (column-major) position `index`.
+    pub fn properties(&self, index: usize) -> Option<&Vec<(String, Value)>> {
+        self.objects.get(index)
+    }
+
+    /// Returns the value of the property `property_name` of the object at
+    /// (column-major) position `index`, if both exist.
+    pub fn property(&self, index: usize, property_name: &str) -> Option<&Value> {
+        self.objects
+            .get(index)?
+            .iter()
+            .find(|(name, _)| name == property_name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the text of a character array property.
+    pub(crate) fn text_property(&self, index: usize, property_name: &str) -> Option<String> {
+        match self.property(index, property_name)? {
+            Value::Char(text) => Some(text.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Returns a numeric property, which is how Matlab stores logicals too.
+    pub(crate) fn numeric_property(&self, index: usize, property_name: &str) -> Option<&Array> {
+        match self.property(index, property_name)? {
+            Value::Numeric(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a scalar logical property is set. Missing properties
+    /// count as not set.
+    pub(crate) fn flag_property(&self, index: usize, property_name: &str) -> bool {
+        self.numeric_property(index, property_name)
+            .map(|flag| flag.data().real_to_f64().first() == Some(&1.0))
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PropertyValue {
+    /// Index into the list of names
+    Name(u32),
+    /// Index into the cells holding the property values
+    Cell(u32),
+    /// The value itself, which Matlab uses for logical properties. It is
+    /// loaded as a logical scalar.
+    Literal(u32),
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: u32,
+    value: PropertyValue,
+}
+
+#[derive(Clone, Debug)]
+struct Object {
+    class_id: u32,
+    properties: Vec<Property>,
+}
+
+#[derive(Clone, Debug)]
+struct Metadata {
+    names: Vec<String>,
+    class_names: Vec<String>,
+    objects: Vec<Object>,
+}
+
+/// The decoded subsystem data, used to resolve object references.
+#[derive(Debug, Default)]
+pub(crate) struct Subsystem {
+    names: Vec<String>,
+    objects: Vec<Object>,
+    values: Vec<parse::DataElement>,
+    /// The objects currently being decoded, to guard against objects
+    /// (indirectly) referencing themselves
+    decoding: RefCell<Vec<u32>>,
+    /// The subsystem data element of the file (including its tag), which is
+    /// kept by raw values referencing objects
+    element: Option<Arc<[u8]>>,
+}
+
+fn failure(i: &[u8]) -> nom::Err<nom::error::Error<&[u8]>> {
+    nom::Err::Failure(error_position!(i, nom::error::ErrorKind::Tag))
+}
+
+fn invalid_subsystem() -> Error {
+    Error::ParseError(failure(&[]))
+}
+
+fn parse_names(num_names: usize) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<String>> {
+    move |i: &[u8]| {
+        // The names are null terminated and stored back to back
+        let mut names = Vec::with_capacity(num_names);
+        let mut rest = i;
+        for _ in 0..num_names {
+            let length = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| failure(rest))?;
+            let name = std::str::from_utf8(&rest[..length]).map_err(|_err| failure(rest))?;
+            names.push(name.to_owned());
+            rest = &rest[length + 1..];
+        }
+        Ok((rest, names))
+    }
+}
+
+fn parse_property_block(
+    endianness: nom::number::Endianness,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<Property>> {
+    move |i: &[u8]| {
+        let (i, num_properties) = u32(endianness)(i)?;
+        let (i, properties) = count(
+            tuple((u32(endianness), u32(endianness), u32(endianness))),
+            num_properties as usize,
+        )(i)?;
+        // Each block is padded to a multiple of 8 bytes
+        let (i, _) = take(if num_properties % 2 == 0 { 4usize } else { 0 })(i)?;
+        let properties = properties
+            .into_iter()
+            .map(|(name, kind, value)| {
+                let value = match kind {
+                    0 => PropertyValue::Name(value),
+                    1 => PropertyValue::Cell(value),
+                    2 => PropertyValue::Literal(value),
+                    _ => return Err(failure(i)),
+                };
+                Ok(Property { name, value })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((i, properties))
+    }
+}
+
+fn parse_metadata(
+    endianness: nom::number::Endianness,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Metadata> {
+    move |metadata: &[u8]| {
+        let (i, version) = u32(endianness)(metadata)?;
+        if !(2..=4).contains(&version) {
+            return Err(failure(metadata));
+        }
+        let (i, num_names) = u32(endianness)(i)?;
+        // The metadata is split into regions, whose offsets follow
+        let (i, offsets) = count(u32(endianness), 8)(i)?;
+        let region = |index: usize| {
+            metadata
+                .get(offsets[index] as usize..offsets[index + 1] as usize)
+                .ok_or_else(|| failure(i))
+        };
+        let (_, names) = parse_names(num_names as usize)(i)?;
+        let name = |index: u32| match index {
+            0 => Some(""),
+            _ => names.get(index as usize - 1).map(|name| name.as_str()),
+        };
+        // Region 1: the classes as (package name, class name, 0, 0) tuples
+        let classes = region(0)?;
+        let (_, classes) = count(
+            tuple((
+                u32(endianness),
+                u32(endianness),
+                u32(endianness),
+                u32(endianness),
+            )),
+            classes.len() / 16,
+        )(classes)?;
+        let class_names = classes
+            .into_iter()
+            .map(
+                |(package_name, class_name, _, _)| match (name(package_name), name(class_name)) {
+                    (Some(""), Some(class_name)) => Ok(class_name.to_owned()),
+                    (Some(package_name), Some(class_name)) => {
+                        Ok(format!("{}.{}", package_name, class_name))
+                    }
+                    _ => Err(failure(i)),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        // Region 2: properties of objects which were saved by a custom
+        // `saveobj` method
+        let (_, saveobj_properties) =
+            many0(complete(parse_property_block(endianness)))(region(1)?)?;
+        // Region 3: the objects as (class id, 0, 0, saveobj properties,
+        // properties, dependency id) tuples
+        let objects = region(2)?;
+        let (_, objects) = count(count(u32(endianness), 6), objects.len() / 24)(objects)?;
+        // Region 4: properties of all other objects
+        let (_, properties) = many0(complete(parse_property_block(endianness)))(region(3)?)?;
+        let objects = objects
+            .into_iter()
+            .map(|object| {
+                let properties = match (object[3], object[4]) {
+                    (0, 0) => Some(Vec::new()),
+                    (0, index) => properties.get(index as usize).cloned(),
+                    (index, _) => saveobj_properties.get(index as usize).cloned(),
+                };
+                Ok(Object {
+                    class_id: object[0],
+                    properties: properties.ok_or_else(|| failure(i))?,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((
+            &metadata[metadata.len()..],
+            Metadata {
+                names,
+                class_names,
+                objects,
+            },
+        ))
+    }
+}
+
+/// Property values which are objects themselves are stored as plain uint32
+/// arrays. Turn these into opaque data elements so they can be decoded like
+/// any other object reference.
+fn mark_object_references(
+    data_element: parse::DataElement,
+    class_names: &[String],
+) -> parse::DataElement {
+    use parse::DataElement;
+    let mark_all = |data_elements: Vec<DataElement>| {
+        data_elements
+            .into_iter()
+            .map(|data_element| mark_object_references(data_element, class_names))
+            .collect()
+    };
+    match data_element {
+        DataElement::NumericMatrix(flags, dims, name, parse::NumericData::UInt32(data), None)
+            if data.first() == Some(&OBJECT_REFERENCE_MARKER) =>
+        {
+            let class_name = data
+                .last()
+                .and_then(|&class_id| class_names.get(class_id as usize))
+                .cloned()
+                .unwrap_or_default();
+            DataElement::Opaque(
+                flags,
+                name.clone(),
+                "MCOS".to_owned(),
+                class_name,
+                Box::new(DataElement::NumericMatrix(
+                    flags,
+                    dims,
+                    name,
+                    parse::NumericData::UInt32(data),
+                    None,
+                )),
+            )
+        }
+        DataElement::CellMatrix(flags, dims, name, cells) => {
+            DataElement::CellMatrix(flags, dims, name, mark_all(cells))
+        }
+        DataElement::StructureMatrix(flags, dims, name, field_names, values) => {
+            DataElement::StructureMatrix(flags, dims, name, field_names, mark_all(values))
+        }
+        DataElement::ObjectMatrix(flags, dims, name, class_name, field_names, values) => {
+            DataElement::ObjectMatrix(flags, dims, name, class_name, field_names, mark_all(values))
+        }
+        data_element => data_element,
+    }
+}
+
+/// Whether a data element holds references to objects in the subsystem
+/// data, possibly nested in cells, fields or function handles.
+pub(crate) fn references_objects(data_element: &parse::DataElement) -> bool {
+    use parse::DataElement;
+    match data_element {
+        DataElement::Opaque(_, _, type_system, _, _) => type_system == "MCOS",
+        DataElement::CellMatrix(_, _, _, values)
+        | DataElement::StructureMatrix(_, _, _, _, values)
+        | DataElement::ObjectMatrix(_, _, _, _, _, values) => values.iter().any(references_objects),
+        DataElement::FunctionHandle(_, _, _, description) => references_objects(description),
+        _ => false,
+    }
+}
+
+impl Subsystem {
+    /// Decodes the subsystem data, which is a struct with the `FileWrapper__`
+    /// object stored in its `MCOS` field.
+    pub(crate) fn from_data_element(
+        data_element: parse::DataElement,
+        endianness: nom::number::Endianness,
+    ) -> Result<Self, Error> {
+        let cells = match data_element {
+            parse::DataElement::StructureMatrix(_, _, _, field_names, values) => field_names
+                .iter()
+                .zip(values)
+                .find(|(field_name, _)| *field_name == "MCOS")
+                .and_then(|(_, value)| match value {
+                    parse::DataElement::Opaque(_, _, type_system, class_name, data)
+                        if type_system == "MCOS" && class_name == "FileWrapper__" =>
+                    {
+                        match *data {
+                            parse::DataElement::CellMatrix(_, _, _, cells) => Some(cells),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }),
+            _ => None,
+        };
+        // Files without classdef objects might still have subsystem data
+        let mut cells = match cells {
+            Some(cells) => cells.into_iter(),
+            None => return Ok(Subsystem::default()),
+        };
+        let metadata = match cells.next() {
+            Some(parse::DataElement::NumericMatrix(
+                _,
+                _,
+                _,
+                parse::NumericData::UInt8(metadata),
+                None,
+            )) => metadata,
+            _ => return Err(invalid_subsystem()),
+        };
+        let (_, metadata) = parse_metadata(endianness)(&metadata)
+            .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
+        // The second cell is unused, the property values start with the third
+        let values = cells
+            .skip(1)
+            .map(|value| mark_object_references(value, &metadata.class_names))
+            .collect();
+        Ok(Subsystem {
+            names: metadata.names,
+            objects: metadata.objects,
+            values,
+            decoding: RefCell::new(Vec::new()),
+            element: None,
+        })
+    }
+
+    /// Keeps the subsystem data element (including its tag) this subsystem
+    /// was read from, which also works for unreadable subsystem data.
+    pub(crate) fn with_element(mut self, element: &[u8]) -> Self {
+        self.element = Some(element.into());
+        self
+    }
+
+    /// The subsystem data element of the file, if there is one.
+    pub(crate) fn element(&self) -> Option<&Arc<[u8]>> {
+        self.element.as_ref()
+    }
+
+    /// Resolves a reference to an array of objects.
+    ///
+    /// The reference is a uint32 array made up of the marker, the number of
+    /// dimensions, the dimensions, the ids of all objects and the class id.
+    pub(crate) fn resolve(
+        &self,
+        name: String,
+        type_system: &str,
+        class_name: String,
+        reference: parse::DataElement,
+    ) -> Result<Option<Value>, Error> {
+        let reference = match reference {
+            parse::DataElement::NumericMatrix(
+                _,
+                _,
+                _,
+                parse::NumericData::UInt32(reference),
+                None,
+            ) if type_system == "MCOS" => reference,
+            _ => return Ok(None),
+        };
+        let ndims = match reference.get(..2) {
+            Some(&[OBJECT_REFERENCE_MARKER, ndims]) => ndims as usize,
+            _ => return Ok(None),
+        };
+        let size: Vec<usize> = match reference.get(2..2 + ndims) {
+            Some(size) => size.iter().map(|&d| d as usize).collect(),
+            None => return Ok(None),
+        };
+        // The sizes are read from the file and might overflow
+        let num_objects = match size.iter().try_fold(1usize, |n, &d| n.checked_mul(d)) {
+            Some(num_objects) => num_objects,
+            None => return Ok(None),
+        };
+        let end = match (2 + ndims).checked_add(num_objects) {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+        let object_ids = match reference.get(2 + ndims..end) {
+            Some(object_ids) if reference.len() == end + 1 => object_ids,
+            _ => return Ok(None),
+        };
+        let mut objects = Vec::with_capacity(num_objects);
+        for &object_id in object_ids {
+            match self.properties(object_id)? {
+                Some(properties) => objects.push(properties),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(Value::from_classdef(ClassdefArray {
+            name,
+            class_name,
+            size,
+            objects,
+        })))
+    }
+
+    /// Decodes the properties of an object. Properties of unsupported type
+    /// are skipped.
+    fn properties(&self, object_id: u32) -> Result<Option<Vec<(String, Value)>>, Error> {
+        let object = match self.objects.get(object_id as usize) {
+            Some(object) if object.class_id > 0 => object,
+            _ => return Ok(None),
+        };
+        if self.decoding.borrow().contains(&object_id) {
+            return Ok(None);
+        }
+        self.decoding.borrow_mut().push(object_id);
+        let properties = object
+            .properties
+            .iter()
+            .map(|property| self.property(property))
+            .collect::<Result<Vec<_>, _>>();
+        self.decoding.borrow_mut().pop();
+        Ok(Some(properties?.into_iter().flatten().collect()))
+    }
+
+    fn property(&self, property: &Property) -> Result<Option<(String, Value)>, Error> {
+        let name = match self.name(property.name) {
+            Some(name) => name.to_owned(),
+            None => return Ok(None),
+        };
+        let value = match property.value {
+            PropertyValue::Name(index) => self
+                .name(index)
+                .map(|text| Value::Char(CharArray::from_text("", text))),
+            PropertyValue::Cell(index) => match self.values.get(index as usize) {
+                Some(value) => Value::try_from(value.clone(), self)?,
+                None => None,
+            },
+            PropertyValue::Literal(value) => Some(Value::Numeric(crate::Array {
+                name: String::new(),
+                size: vec![1, 1],
+                data: crate::NumericData::UInt8 {
+                    real: vec![(value != 0) as u8],
+                    imag: None,
+                },
+                logical: true,
+            })),
+        };
+        Ok(value.map(|value| (name, value)))
+    }
+
+    fn name(&self, index: u32) -> Option<&str> {
+        match index {
+            0 => None,
+            _ => self.names.get(index as usize - 1).map(|name| name.as_str()),
+        }
+    }
+}
+
+/// Collects the classdef objects of a file being written, in order to store
+/// them in its subsystem data. The property values end up in the cells of
+/// the `FileWrapper__` object, all other information in the metadata.
+#[derive(Debug, Default)]
+pub(crate) struct SubsystemWriter {
+    names: Vec<String>,
+    /// (package name, class name) indices into the names
+    classes: Vec<(u32, u32)>,
+    /// The class id and the properties as (name index, cell index) pairs
+    objects: Vec<(u32, Vec<(u32, u32)>)>,
+    /// The property values which haven't been taken yet
+    values: Vec<Value>,
+    num_values: u32,
+    /// The subsystem data element that raw values referencing objects were
+    /// read with, which has to be written instead of the collected objects
+    original: Option<Arc<[u8]>>,
+}
+
+impl SubsystemWriter {
+    /// Whether no objects have been added.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Keeps the subsystem data element referenced by a raw value. Raw
+    /// values read from different subsystem data can't be combined.
+    pub(crate) fn keep_original(&mut self, element: &Arc<[u8]>) -> Result<(), Error> {
+        match &self.original {
+            Some(original) if original != element => Err(Error::InvalidArgument(
+                "raw values referencing objects of different files can't be written together"
+                    .to_owned(),
+            )),
+            _ => {
+                self.original = Some(element.clone());
+                Ok(())
+            }
+        }
+    }
+
+    /// Takes the subsystem data element kept by `keep_original`.
+    pub(crate) fn take_original(&mut self) -> Option<Arc<[u8]>> {
+        self.original.take()
+    }
+
+    /// Adds an array of objects of the same class, given the properties of
+    /// each of them, and returns the uint32 array referencing it.
+    pub(crate) fn add_objects(
+        &mut self,
+        class_name: &str,
+        size: &[usize],
+        objects: Vec<Vec<(String, Value)>>,
+    ) -> Vec<u32> {
+        let class_id = self.class_id(class_name);
+        let mut reference = vec![OBJECT_REFERENCE_MARKER, size.len() as u32];
+        reference.extend(size.iter().map(|&d| d as u32));
+        for properties in objects {
+            let properties = properties
+                .into_iter()
+                .map(|(name, value)| {
+                    self.values.push(value);
+                    self.num_values += 1;
+                    (self.name_index(&name), self.num_values - 1)
+                })
+                .collect();
+            self.objects.push((class_id, properties));
+            // Object ids start at one
+            reference.push(self.objects.len() as u32);
+        }
+        reference.push(class_id);
+        reference
+    }
+
+    /// Takes the property values added since the last call, in the order
+    /// they have to be stored in the cells following the metadata. Writing
+    /// them may add further objects (nested in the property values), so
+    /// this has to be repeated until no values are left.
+    pub(crate) fn take_values(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.values)
+    }
+
+    /// The number of classes, which determines the size of the cell of
+    /// default values stored at the end of the subsystem data.
+    pub(crate) fn num_classes(&self) -> usize {
+        self.classes.len()
+    }
+
+    /// Serializes the metadata in the (version 4) format understood by
+    /// `parse_metadata`.
+    pub(crate) fn metadata(&self) -> Vec<u8> {
+        fn extend(buf: &mut Vec<u8>, values: &[u32]) {
+            for value in values {
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        fn pad(buf: &mut Vec<u8>) {
+            buf.resize((buf.len() + 7) / 8 * 8, 0);
+        }
+        let mut names = Vec::new();
+        for name in &self.names {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        pad(&mut names);
+        // Every region starts with an unused entry
+        let mut classes = vec![0; 16];
+        for &(package_name, class_name) in &self.classes {
+            extend(&mut classes, &[package_name, class_name, 0, 0]);
+        }
+        let saveobj_properties = vec![0; 8];
+        let mut objects = vec![0; 24];
+        let mut properties = vec![0; 8];
+        for (index, (class_id, object_properties)) in self.objects.iter().enumerate() {
+            let id = index as u32 + 1;
+            extend(&mut objects, &[*class_id, 0, 0, 0, id, id]);
+            extend(&mut properties, &[object_properties.len() as u32]);
+            for &(name, cell) in object_properties {
+                extend(&mut properties, &[name, 1, cell]);
+            }
+            pad(&mut properties);
+        }
+        let dynamic_properties = vec![0; 8];
+        let mut offsets = vec![40 + names.len() as u32];
+        for region in &[
+            &classes,
+            &saveobj_properties,
+            &objects,
+            &properties,
+            &dynamic_properties,
+        ] {
+            offsets.push(offsets.last().unwrap() + region.len() as u32);
+        }
+        let end = *offsets.last().unwrap();
+        offsets.extend(&[end, end]);
+        let mut metadata = Vec::new();
+        extend(&mut metadata, &[4, self.names.len() as u32]);
+        extend(&mut metadata, &offsets);
+        for region in &[
+            names,
+            classes,
+            saveobj_properties,
+            objects,
+            properties,
+            dynamic_properties,
+        ] {
+            metadata.extend_from_slice(region);
+        }
+        metadata
+    }
+
+    fn name_index(&mut self, name: &str) -> u32 {
+        let index = match self.names.iter().position(|other| other == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_owned());
+                self.names.len() - 1
+            }
+        };
+        // Index zero stands for the empty name
+        index as u32 + 1
+    }
+
+    fn class_id(&mut self, class_name: &str) -> u32 {
+        let (package_name, class_name) = match class_name.rfind('.') {
+            Some(dot) => (&class_name[..dot], &class_name[dot + 1..]),
+            None => ("", class_name),
+        };
+        let package_name = match package_name {
+            "" => 0,
+            package_name => self.name_index(package_name),
+        };
+        let class = (package_name, self.name_index(class_name));
+        let index = match self.classes.iter().position(|&other| other == class) {
+            Some(index) => index,
+            None => {
+                self.classes.push(class);
+                self.classes.len() - 1
+            }
+        };
+        // Class ids start at one
+        index as u32 + 1
+    }
+}
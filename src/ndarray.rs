@@ -20,7 +20,7 @@
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! #     let data = include_bytes!("../tests/multidimensional.mat");
 //! #     let mat_file = matfile::MatFile::parse(data.as_ref()).unwrap();
-//! #     let mf_arr = &mat_file.arrays()[0];
+//! #     let mf_arr = mat_file.arrays()[0];
 //! #     use ndarr as ndarray;
 //! #     use std::convert::TryInto;
 //! let nd_arr: ndarray::ArrayD<f64> = mf_arr.try_into()?;
@@ -36,7 +36,7 @@
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! #     let data = include_bytes!("../tests/single_complex.mat");
 //! #     let mat_file = matfile::MatFile::parse(data.as_ref()).unwrap();
-//! #     let mf_arr = &mat_file.arrays()[0];
+//! #     let mf_arr = mat_file.arrays()[0];
 //! #     use ndarr as ndarray;
 //! #     use num_complex;
 //! #     use std::convert::TryInto;
@@ -4,7 +4,7 @@ use nom::bytes::complete::tag;
 use nom::bytes::complete::take;
 use nom::character::complete::char;
 use nom::combinator::{complete, cond, map, map_res, not, opt, peek, value};
-use nom::multi::{count, length_value, many0};
+use nom::multi::{count, length_value};
 use nom::number::complete::f32;
 use nom::number::complete::f64;
 use nom::number::complete::i16;
@@ -18,17 +18,39 @@ use nom::number::complete::u8;
 use nom::sequence::pair;
 use nom::{error_position, IResult};
 use num_traits::FromPrimitive;
+use std::convert::TryFrom;
 use std::io::Read;
+use std::ops::Range;
 
 // https://www.mathworks.com/help/pdf_doc/matlab/matfile_format.pdf
 // https://www.mathworks.com/help/matlab/import_export/mat-file-versions.html
 
 #[derive(Clone, Debug)]
 pub struct Header {
+    #[allow(dead_code)]
     text: String,
+    subsystem_offset: Option<u64>,
     is_little_endian: bool,
 }
 
+impl Header {
+    pub fn subsystem_offset(&self) -> Option<u64> {
+        self.subsystem_offset
+    }
+
+    pub fn is_little_endian(&self) -> bool {
+        self.is_little_endian
+    }
+
+    pub fn endianness(&self) -> nom::number::Endianness {
+        if self.is_little_endian {
+            nom::number::Endianness::Little
+        } else {
+            nom::number::Endianness::Big
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum NumericData {
     Int8(Vec<i8>),
@@ -59,6 +81,38 @@ impl NumericData {
         }
     }
 
+    fn truncate(&mut self, len: usize) {
+        match self {
+            NumericData::Single(vec) => vec.truncate(len),
+            NumericData::Double(vec) => vec.truncate(len),
+            NumericData::Int8(vec) => vec.truncate(len),
+            NumericData::UInt8(vec) => vec.truncate(len),
+            NumericData::Int16(vec) => vec.truncate(len),
+            NumericData::UInt16(vec) => vec.truncate(len),
+            NumericData::Int32(vec) => vec.truncate(len),
+            NumericData::UInt32(vec) => vec.truncate(len),
+            NumericData::Int64(vec) => vec.truncate(len),
+            NumericData::UInt64(vec) => vec.truncate(len),
+        }
+    }
+
+    /// Whether each value is nonzero, which is all that matters for logical
+    /// arrays
+    pub fn nonzero(&self) -> Vec<bool> {
+        match self {
+            NumericData::Single(vec) => vec.iter().map(|&x| x != 0.0).collect(),
+            NumericData::Double(vec) => vec.iter().map(|&x| x != 0.0).collect(),
+            NumericData::Int8(vec) => vec.iter().map(|&x| x != 0).collect(),
+            NumericData::UInt8(vec) => vec.iter().map(|&x| x != 0).collect(),
+            NumericData::Int16(vec) => vec.iter().map(|&x| x != 0).collect(),
+            NumericData::UInt16(vec) => vec.iter().map(|&x| x != 0).collect(),
+            NumericData::Int32(vec) => vec.iter().map(|&x| x != 0).collect(),
+            NumericData::UInt32(vec) => vec.iter().map(|&x| x != 0).collect(),
+            NumericData::Int64(vec) => vec.iter().map(|&x| x != 0).collect(),
+            NumericData::UInt64(vec) => vec.iter().map(|&x| x != 0).collect(),
+        }
+    }
+
     fn data_type(&self) -> DataType {
         match self {
             NumericData::Single(_) => DataType::Single,
@@ -93,10 +147,25 @@ pub enum DataElement {
         NumericData,
         Option<NumericData>,
     ),
-    // CharacterMatrix,
-    // Cell Matrix,
-    // Structure Matrix,
-    // Object Matrix,
+    CharacterMatrix(ArrayFlags, Dimensions, String, Vec<u16>),
+    CellMatrix(ArrayFlags, Dimensions, String, Vec<DataElement>),
+    StructureMatrix(
+        ArrayFlags,
+        Dimensions,
+        String,
+        Vec<String>,
+        Vec<DataElement>,
+    ),
+    ObjectMatrix(
+        ArrayFlags,
+        Dimensions,
+        String,
+        String,
+        Vec<String>,
+        Vec<DataElement>,
+    ),
+    FunctionHandle(ArrayFlags, Dimensions, String, Box<DataElement>),
+    Opaque(ArrayFlags, String, String, String, Box<DataElement>),
     Unsupported,
 }
 
@@ -118,7 +187,7 @@ pub fn parse_header(i: &[u8]) -> IResult<&[u8], Header> {
     // Header text field
     let (i, text) = take(116usize)(i)?;
     // Header subsystem data offset field
-    let (i, _ssdo) = take(8usize)(i)?;
+    let (i, ssdo) = take(8usize)(i)?;
     // Header flag fields
     // Assume little endian for now
     let (i, mut version) = u16(nom::number::Endianness::Little)(i)?;
@@ -135,11 +204,25 @@ pub fn parse_header(i: &[u8]) -> IResult<&[u8], Header> {
             nom::error::ErrorKind::Tag
         )));
     }
+    // Files without subsystem data have either all zeros or all spaces in
+    // the offset field
+    let subsystem_offset = if ssdo.iter().all(|&b| b == 0 || b == b' ') {
+        None
+    } else {
+        let mut ssdo_bytes = [0; 8];
+        ssdo_bytes.copy_from_slice(ssdo);
+        Some(if is_little_endian {
+            u64::from_le_bytes(ssdo_bytes)
+        } else {
+            u64::from_be_bytes(ssdo_bytes)
+        })
+    };
     Ok((
         i,
         Header {
-            text: std::str::from_utf8(text).unwrap_or(&"").to_owned(),
-            is_little_endian: is_little_endian,
+            text: std::str::from_utf8(text).unwrap_or("").to_owned(),
+            subsystem_offset,
+            is_little_endian,
         },
     ))
 }
@@ -148,7 +231,7 @@ fn constant<T: Clone>(v: T) -> impl Fn(&[u8]) -> IResult<&[u8], T> {
     move |i: &[u8]| Ok((i, v.clone()))
 }
 
-fn parse_next_data_element(
+pub fn parse_next_data_element(
     endianness: nom::number::Endianness,
 ) -> impl Fn(&[u8]) -> IResult<&[u8], DataElement> {
     move |i: &[u8]| {
@@ -156,13 +239,7 @@ fn parse_next_data_element(
         let next_parser: Box<dyn Fn(_) -> _> = match data_element_tag.data_type {
             DataType::Matrix => Box::new(parse_matrix_data_element(endianness)),
             DataType::Compressed => Box::new(parse_compressed_data_element(endianness)),
-            _ => {
-                println!(
-                    "Unsupported variable type: {:?} (must be Matrix or Compressed)",
-                    data_element_tag.data_type
-                );
-                Box::new(parse_unsupported_data_element(endianness))
-            }
+            _ => Box::new(parse_unsupported_data_element(endianness)),
         };
         let (i, data_element) =
             length_value(constant(data_element_tag.data_byte_size), next_parser)(i)?;
@@ -191,9 +268,11 @@ fn ceil_to_multiple(x: u32, multiple: u32) -> u32 {
 #[derive(Clone, Copy, Debug)]
 pub struct ArrayFlags {
     pub complex: bool,
+    #[allow(dead_code)]
     pub global: bool,
     pub logical: bool,
     pub class: ArrayType,
+    #[allow(dead_code)]
     pub nzmax: usize,
 }
 
@@ -216,17 +295,17 @@ pub enum DataType {
     Utf32 = 18,
 }
 
-// impl DataType {
-//     fn byte_size(&self) -> Option<usize> {
-//         match self {
-//             DataType::Int8 | DataType::UInt8 | DataType::Utf8 => Some(1),
-//             DataType::Int16 | DataType::UInt16 | DataType::Utf16 => Some(2),
-//             DataType::Int32 | DataType::UInt32 | DataType::Single | DataType::Utf32 => Some(4),
-//             DataType::Int64 | DataType::UInt64 | DataType::Double => Some(8),
-//             _ => None,
-//         }
-//     }
-// }
+impl DataType {
+    pub fn byte_size(&self) -> Option<usize> {
+        match self {
+            DataType::Int8 | DataType::UInt8 | DataType::Utf8 => Some(1),
+            DataType::Int16 | DataType::UInt16 | DataType::Utf16 => Some(2),
+            DataType::Int32 | DataType::UInt32 | DataType::Single | DataType::Utf32 => Some(4),
+            DataType::Int64 | DataType::UInt64 | DataType::Double => Some(8),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Copy, Primitive)]
 pub enum ArrayType {
@@ -245,9 +324,34 @@ pub enum ArrayType {
     UInt32 = 13,
     Int64 = 14,
     UInt64 = 15,
+    Function = 16,
+    Opaque = 17,
 }
 
 impl ArrayType {
+    /// The name of the Matlab class of arrays of this type. Objects store the
+    /// name of their class separately.
+    pub fn matlab_class(self, logical: bool) -> &'static str {
+        match self {
+            _ if logical => "logical",
+            ArrayType::Cell => "cell",
+            ArrayType::Struct => "struct",
+            ArrayType::Object | ArrayType::Opaque => "object",
+            ArrayType::Char => "char",
+            ArrayType::Sparse | ArrayType::Double => "double",
+            ArrayType::Single => "single",
+            ArrayType::Int8 => "int8",
+            ArrayType::UInt8 => "uint8",
+            ArrayType::Int16 => "int16",
+            ArrayType::UInt16 => "uint16",
+            ArrayType::Int32 => "int32",
+            ArrayType::UInt32 => "uint32",
+            ArrayType::Int64 => "int64",
+            ArrayType::UInt64 => "uint64",
+            ArrayType::Function => "function_handle",
+        }
+    }
+
     // fn is_numeric(&self) -> bool {
     //     match self {
     //         ArrayType::Cell
@@ -259,7 +363,7 @@ impl ArrayType {
     //     }
     // }
 
-    fn numeric_data_type(&self) -> Option<DataType> {
+    pub fn numeric_data_type(&self) -> Option<DataType> {
         match self {
             ArrayType::Double => Some(DataType::Double),
             ArrayType::Single => Some(DataType::Single),
@@ -267,7 +371,7 @@ impl ArrayType {
             ArrayType::UInt8 => Some(DataType::UInt8),
             ArrayType::Int16 => Some(DataType::Int16),
             ArrayType::UInt16 => Some(DataType::UInt16),
-            ArrayType::Int32 => Some(DataType::UInt32),
+            ArrayType::Int32 => Some(DataType::Int32),
             ArrayType::UInt32 => Some(DataType::UInt32),
             ArrayType::Int64 => Some(DataType::Int64),
             ArrayType::UInt64 => Some(DataType::UInt64),
@@ -278,6 +382,12 @@ impl ArrayType {
 
 pub type Dimensions = Vec<i32>;
 
+/// The number of elements of an array. Only valid for dimensions checked by
+/// `parse_dimensions_array_subelement`.
+fn num_elements(dimensions: &Dimensions) -> usize {
+    dimensions.iter().map(|&d| d as usize).product()
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct DataElementTag {
     data_type: DataType,
@@ -285,7 +395,35 @@ pub struct DataElementTag {
     padding_byte_size: u32,
 }
 
-fn parse_data_element_tag(
+impl DataElementTag {
+    pub fn new(data_type: DataType, data_byte_size: u32, padding_byte_size: u32) -> Self {
+        DataElementTag {
+            data_type,
+            data_byte_size,
+            padding_byte_size,
+        }
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn data_byte_size(&self) -> usize {
+        self.data_byte_size as usize
+    }
+
+    /// The number of padding bytes following the data. Compressed data
+    /// elements are never padded.
+    pub fn padding_byte_size(&self) -> usize {
+        if self.data_type == DataType::Compressed {
+            0
+        } else {
+            self.padding_byte_size as usize
+        }
+    }
+}
+
+pub fn parse_data_element_tag(
     endianness: nom::number::Endianness,
 ) -> impl Fn(&[u8]) -> IResult<&[u8], DataElementTag> {
     move |i: &[u8]| {
@@ -322,7 +460,7 @@ fn parse_data_element_tag(
                     },
                 ))?,
                 data_byte_size: byte_size,
-                padding_byte_size: padding_byte_size,
+                padding_byte_size,
             },
         ))
     }
@@ -333,7 +471,8 @@ fn parse_array_name_subelement(
 ) -> impl Fn(&[u8]) -> IResult<&[u8], String> {
     move |i: &[u8]| {
         let (i, data_element_tag) = parse_data_element_tag(endianness)(i)?;
-        if !(data_element_tag.data_type == DataType::Int8 && data_element_tag.data_byte_size > 0) {
+        // Arrays nested in cells and structs have an empty name
+        if data_element_tag.data_type != DataType::Int8 {
             return Err(nom::Err::Failure(error_position!(
                 i,
                 // TODO
@@ -372,6 +511,17 @@ fn parse_dimensions_array_subelement(
             i32(endianness),
             (data_element_tag.data_byte_size / 4) as usize,
         )(i)?;
+        // Dimensions can be zero for empty arrays, but never negative
+        let num_elements = dimensions.iter().try_fold(1usize, |product, &d| {
+            product.checked_mul(usize::try_from(d).ok()?)
+        });
+        if num_elements.is_none() {
+            return Err(nom::Err::Failure(error_position!(
+                i,
+                // TODO
+                nom::error::ErrorKind::Tag
+            )));
+        }
         let (i, _) = take(data_element_tag.padding_byte_size)(i)?;
         Ok((i, dimensions))
     }
@@ -414,75 +564,88 @@ fn parse_matrix_data_element(
     endianness: nom::number::Endianness,
 ) -> impl Fn(&[u8]) -> IResult<&[u8], DataElement> {
     move |i: &[u8]| {
+        // Matlab writes matrix elements without any subelements for empty
+        // values, e.g. for unassigned fields of structure arrays
+        if i.is_empty() {
+            return Ok((i, empty_matrix()));
+        }
         let (i, flags) = parse_array_flags_subelement(endianness)(i)?;
         match flags.class {
-            ArrayType::Cell | ArrayType::Struct | ArrayType::Object | ArrayType::Char => {
-                parse_unsupported_data_element(endianness)(i)
-            }
+            ArrayType::Char => parse_character_matrix_subelements(endianness, flags)(i),
+            ArrayType::Cell => parse_cell_matrix_subelements(endianness, flags)(i),
+            ArrayType::Struct => parse_structure_matrix_subelements(endianness, flags)(i),
+            ArrayType::Function => parse_function_handle_subelements(endianness, flags)(i),
+            ArrayType::Opaque => parse_opaque_subelements(endianness, flags)(i),
+            ArrayType::Object => parse_object_matrix_subelements(endianness, flags)(i),
             ArrayType::Sparse => parse_sparse_matrix_subelements(endianness, flags)(i),
             _ => parse_numeric_matrix_subelements(endianness, flags)(i),
         }
     }
 }
 
+/// The unnamed 0x0 double array `[]`
+fn empty_matrix() -> DataElement {
+    DataElement::NumericMatrix(
+        ArrayFlags {
+            complex: false,
+            global: false,
+            logical: false,
+            class: ArrayType::Double,
+            nzmax: 0,
+        },
+        vec![0, 0],
+        String::new(),
+        NumericData::Double(Vec::new()),
+        None,
+    )
+}
+
 fn numeric_data_types_are_compatible(array_type: DataType, subelement_type: DataType) -> bool {
     match array_type {
-        DataType::Int8 => match subelement_type {
-            DataType::Int8 => true,
-            _ => false,
-        },
-        DataType::UInt8 => match subelement_type {
-            DataType::UInt8 => true,
-            _ => false,
-        },
-        DataType::Int16 => match subelement_type {
-            DataType::UInt8 | DataType::Int16 => true,
-            _ => false,
-        },
-        DataType::UInt16 => match subelement_type {
-            DataType::UInt8 | DataType::UInt16 => true,
-            _ => false,
-        },
-        DataType::Int32 => match subelement_type {
-            DataType::UInt8 | DataType::Int16 | DataType::UInt16 | DataType::Int32 => true,
-            _ => false,
-        },
-        DataType::UInt32 => match subelement_type {
-            DataType::UInt8 | DataType::Int16 | DataType::UInt16 | DataType::UInt32 => true,
-            _ => false,
-        },
-        DataType::Int64 => match subelement_type {
+        DataType::Int8 => matches!(subelement_type, DataType::Int8),
+        DataType::UInt8 => matches!(subelement_type, DataType::UInt8),
+        DataType::Int16 => matches!(subelement_type, DataType::UInt8 | DataType::Int16),
+        DataType::UInt16 => matches!(subelement_type, DataType::UInt8 | DataType::UInt16),
+        DataType::Int32 => matches!(
+            subelement_type,
+            DataType::UInt8 | DataType::Int16 | DataType::UInt16 | DataType::Int32
+        ),
+        DataType::UInt32 => matches!(
+            subelement_type,
+            DataType::UInt8 | DataType::Int16 | DataType::UInt16 | DataType::UInt32
+        ),
+        DataType::Int64 => matches!(
+            subelement_type,
             DataType::UInt8
-            | DataType::Int16
-            | DataType::UInt16
-            | DataType::Int32
-            | DataType::Int64 => true,
-            _ => false,
-        },
-        DataType::UInt64 => match subelement_type {
+                | DataType::Int16
+                | DataType::UInt16
+                | DataType::Int32
+                | DataType::Int64
+        ),
+        DataType::UInt64 => matches!(
+            subelement_type,
             DataType::UInt8
-            | DataType::Int16
-            | DataType::UInt16
-            | DataType::Int32
-            | DataType::UInt64 => true,
-            _ => false,
-        },
-        DataType::Single => match subelement_type {
+                | DataType::Int16
+                | DataType::UInt16
+                | DataType::Int32
+                | DataType::UInt64
+        ),
+        DataType::Single => matches!(
+            subelement_type,
             DataType::UInt8
-            | DataType::Int16
-            | DataType::UInt16
-            | DataType::Int32
-            | DataType::Single => true,
-            _ => false,
-        },
-        DataType::Double => match subelement_type {
+                | DataType::Int16
+                | DataType::UInt16
+                | DataType::Int32
+                | DataType::Single
+        ),
+        DataType::Double => matches!(
+            subelement_type,
             DataType::UInt8
-            | DataType::Int16
-            | DataType::UInt16
-            | DataType::Int32
-            | DataType::Double => true,
-            _ => false,
-        },
+                | DataType::Int16
+                | DataType::UInt16
+                | DataType::Int32
+                | DataType::Double
+        ),
         _ => false,
     }
 }
@@ -492,71 +655,35 @@ fn parse_numeric_subelement(
 ) -> impl Fn(&[u8]) -> IResult<&[u8], NumericData> {
     move |i: &[u8]| {
         let (i, data_element_tag) = parse_data_element_tag(endianness)(i)?;
-        let (i, numeric_data) = match data_element_tag.data_type {
-            DataType::Int8 => map(
-                count(i8, data_element_tag.data_byte_size as usize),
-                NumericData::Int8,
-            )(i)?,
-            DataType::UInt8 => map(
-                count(u8, data_element_tag.data_byte_size as usize),
-                NumericData::UInt8,
-            )(i)?,
-            DataType::Int16 => map(
-                count(
-                    i16(endianness),
-                    data_element_tag.data_byte_size as usize / 2,
-                ),
-                NumericData::Int16,
-            )(i)?,
-            DataType::UInt16 => map(
-                count(
-                    u16(endianness),
-                    data_element_tag.data_byte_size as usize / 2,
-                ),
-                NumericData::UInt16,
-            )(i)?,
-            DataType::Int32 => map(
-                count(
-                    i32(endianness),
-                    data_element_tag.data_byte_size as usize / 4,
-                ),
-                NumericData::Int32,
-            )(i)?,
-            DataType::UInt32 => map(
-                count(
-                    u32(endianness),
-                    data_element_tag.data_byte_size as usize / 4,
-                ),
-                NumericData::UInt32,
-            )(i)?,
-            DataType::Int64 => map(
-                count(
-                    i64(endianness),
-                    data_element_tag.data_byte_size as usize / 8,
-                ),
-                NumericData::Int64,
-            )(i)?,
-            DataType::UInt64 => map(
-                count(
-                    u64(endianness),
-                    data_element_tag.data_byte_size as usize / 8,
-                ),
-                NumericData::UInt64,
-            )(i)?,
-            DataType::Single => map(
-                count(
-                    f32(endianness),
-                    data_element_tag.data_byte_size as usize / 4,
-                ),
-                NumericData::Single,
-            )(i)?,
-            DataType::Double => map(
-                count(
-                    f64(endianness),
-                    data_element_tag.data_byte_size as usize / 8,
-                ),
-                NumericData::Double,
-            )(i)?,
+        let (i, numeric_data) = parse_numeric_values(
+            endianness,
+            data_element_tag.data_type,
+            data_element_tag.data_byte_size as usize,
+        )(i)?;
+        // Padding bytes
+        let (i, _) = take(data_element_tag.padding_byte_size)(i)?;
+        Ok((i, numeric_data))
+    }
+}
+
+/// Reads `num_bytes` bytes of numbers stored as the given data type.
+pub fn parse_numeric_values(
+    endianness: nom::number::Endianness,
+    data_type: DataType,
+    num_bytes: usize,
+) -> impl Fn(&[u8]) -> IResult<&[u8], NumericData> {
+    move |i: &[u8]| {
+        let (i, numeric_data) = match data_type {
+            DataType::Int8 => map(count(i8, num_bytes), NumericData::Int8)(i)?,
+            DataType::UInt8 => map(count(u8, num_bytes), NumericData::UInt8)(i)?,
+            DataType::Int16 => map(count(i16(endianness), num_bytes / 2), NumericData::Int16)(i)?,
+            DataType::UInt16 => map(count(u16(endianness), num_bytes / 2), NumericData::UInt16)(i)?,
+            DataType::Int32 => map(count(i32(endianness), num_bytes / 4), NumericData::Int32)(i)?,
+            DataType::UInt32 => map(count(u32(endianness), num_bytes / 4), NumericData::UInt32)(i)?,
+            DataType::Int64 => map(count(i64(endianness), num_bytes / 8), NumericData::Int64)(i)?,
+            DataType::UInt64 => map(count(u64(endianness), num_bytes / 8), NumericData::UInt64)(i)?,
+            DataType::Single => map(count(f32(endianness), num_bytes / 4), NumericData::Single)(i)?,
+            DataType::Double => map(count(f64(endianness), num_bytes / 8), NumericData::Double)(i)?,
             DataType::Compressed
             | DataType::Matrix
             | DataType::Utf8
@@ -569,8 +696,6 @@ fn parse_numeric_subelement(
                 )));
             }
         };
-        // Padding bytes
-        let (i, _) = take(data_element_tag.padding_byte_size)(i)?;
         Ok((i, numeric_data))
     }
 }
@@ -581,16 +706,14 @@ fn parse_compressed_data_element(
     move |i: &[u8]| {
         let mut buf = Vec::new();
         Decoder::new(i)
-            .map_err(|err| {
-                eprintln!("{:?}", err);
+            .map_err(|_err| {
                 nom::Err::Failure(nom::error::Error {
                     input: i,
                     code: nom::error::ErrorKind::Tag,
                 }) // TODO
             })?
             .read_to_end(&mut buf)
-            .map_err(|err| {
-                eprintln!("{:?}", err);
+            .map_err(|_err| {
                 nom::Err::Failure(nom::error::Error {
                     input: i,
                     code: nom::error::ErrorKind::Tag,
@@ -602,6 +725,7 @@ fn parse_compressed_data_element(
     }
 }
 
+pub type Fields = (Vec<String>, Vec<DataElement>);
 pub type RowIndex = Vec<usize>;
 pub type ColumnShift = Vec<usize>;
 
@@ -614,9 +738,9 @@ fn parse_numeric_matrix_subelements(
         let (i, name) = parse_array_name_subelement(endianness)(i)?;
         let (i, real_part) = parse_numeric_subelement(endianness)(i)?;
         // Check that size and type of the real part are correct
-        let num_required_elements = dimensions.iter().product::<i32>();
+        let num_required_elements = num_elements(&dimensions);
         let array_data_type = flags.class.numeric_data_type().unwrap();
-        if !(real_part.len() == num_required_elements as usize
+        if !(real_part.len() == num_required_elements
             && numeric_data_types_are_compatible(array_data_type, real_part.data_type()))
         {
             return Err(nom::Err::Failure(error_position!(
@@ -628,7 +752,7 @@ fn parse_numeric_matrix_subelements(
         let (i, imag_part) = cond(flags.complex, parse_numeric_subelement(endianness))(i)?;
         // Check that size and type of imaginary part are correct if present
         if let Some(imag_part) = &imag_part {
-            if !(imag_part.len() == num_required_elements as usize
+            if !(imag_part.len() == num_required_elements
                 && numeric_data_types_are_compatible(array_data_type, imag_part.data_type()))
             {
                 return Err(nom::Err::Failure(error_position!(
@@ -653,27 +777,37 @@ fn parse_sparse_matrix_subelements(
         // Figure out the type of array
         let (i, dimensions) = parse_dimensions_array_subelement(endianness)(i)?;
         let (i, name) = parse_array_name_subelement(endianness)(i)?;
-        let (i, row_index) = parse_row_index_array_subelement(endianness)(i)?;
+        let (i, mut row_index) = parse_row_index_array_subelement(endianness)(i)?;
         let (i, column_index) = parse_column_index_array_subelement(endianness)(i)?;
-        let (i, real_part) = parse_numeric_subelement(endianness)(i)?;
-        // Check that size of the real part is correct (can't check for type in sparse matrices)
-        if !(real_part.len() == flags.nzmax) {
-            return Err(nom::Err::Failure(error_position!(
-                i,
-                // TODO
-                nom::error::ErrorKind::Tag
-            )));
-        }
-        let (i, imag_part) = cond(flags.complex, parse_numeric_subelement(endianness))(i)?;
-        // Check that size of the imaginary part is correct if present (can't check for type in sparse matrices)
-        if let Some(imag_part) = &imag_part {
-            if !(imag_part.len() == flags.nzmax as usize) {
+        let (i, mut real_part) = parse_numeric_subelement(endianness)(i)?;
+        let (i, mut imag_part) = cond(flags.complex, parse_numeric_subelement(endianness))(i)?;
+        // The column index holds the offset of the first nonzero of every
+        // column plus the total number of nonzeros. The index arrays and the
+        // values may be longer (up to nzmax), in which case the remaining
+        // entries are meaningless and get dropped. The type of the values
+        // can't be checked since it depends on the data (and is arbitrary
+        // for logical matrices).
+        let num_nonzeros = match sparse_num_nonzeros(&dimensions, &row_index, &column_index) {
+            Some(num_nonzeros)
+                if real_part.len() >= num_nonzeros
+                    && imag_part
+                        .as_ref()
+                        .map_or(true, |imag_part| imag_part.len() >= num_nonzeros) =>
+            {
+                num_nonzeros
+            }
+            _ => {
                 return Err(nom::Err::Failure(error_position!(
                     i,
                     // TODO
                     nom::error::ErrorKind::Tag
                 )));
             }
+        };
+        row_index.truncate(num_nonzeros);
+        real_part.truncate(num_nonzeros);
+        if let Some(imag_part) = &mut imag_part {
+            imag_part.truncate(num_nonzeros);
         }
         Ok((
             i,
@@ -681,8 +815,8 @@ fn parse_sparse_matrix_subelements(
                 flags,
                 dimensions,
                 name,
-                row_index.iter().map(|&i| i as usize).collect(),
-                column_index.iter().map(|&i| i as usize).collect(),
+                row_index,
+                column_index,
                 real_part,
                 imag_part,
             ),
@@ -690,12 +824,253 @@ fn parse_sparse_matrix_subelements(
     }
 }
 
+/// Checks the index arrays of a sparse matrix and returns its number of
+/// nonzeros.
+fn sparse_num_nonzeros(
+    dimensions: &Dimensions,
+    row_index: &RowIndex,
+    column_shift: &ColumnShift,
+) -> Option<usize> {
+    let (num_rows, num_columns) = match dimensions.as_slice() {
+        &[num_rows, num_columns] => (num_rows as usize, num_columns as usize),
+        _ => return None,
+    };
+    if column_shift.len() != num_columns + 1
+        || column_shift[0] != 0
+        || column_shift.windows(2).any(|w| w[0] > w[1])
+    {
+        return None;
+    }
+    let num_nonzeros = column_shift[num_columns];
+    let row_index = row_index.get(..num_nonzeros)?;
+    if row_index.iter().any(|&row| row >= num_rows) {
+        return None;
+    }
+    Some(num_nonzeros)
+}
+
+fn parse_character_subelement(
+    endianness: nom::number::Endianness,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<u16>> {
+    move |i: &[u8]| {
+        let (i, data_element_tag) = parse_data_element_tag(endianness)(i)?;
+        let (i, bytes) = take(data_element_tag.data_byte_size)(i)?;
+        // Characters are stored as UTF-16 code units, but the file may use
+        // any of the Unicode encodings (or plain bytes) to store them
+        let (_, characters) = match data_element_tag.data_type {
+            DataType::Utf8 => map_res(complete(take(bytes.len())), |b| {
+                std::str::from_utf8(b).map(|s| s.encode_utf16().collect())
+            })(bytes)?,
+            DataType::Utf16 | DataType::UInt16 | DataType::Int16 => {
+                count(u16(endianness), bytes.len() / 2)(bytes)?
+            }
+            DataType::Utf32 | DataType::UInt32 | DataType::Int32 => map_res(
+                count(u32(endianness), bytes.len() / 4),
+                |code_points: Vec<u32>| {
+                    code_points
+                        .into_iter()
+                        .map(|c| std::char::from_u32(c).ok_or(()))
+                        .collect::<Result<String, ()>>()
+                        .map(|s| s.encode_utf16().collect())
+                },
+            )(bytes)?,
+            DataType::UInt8 | DataType::Int8 => (
+                &bytes[bytes.len()..],
+                bytes.iter().map(|&b| b as u16).collect(),
+            ),
+            _ => {
+                return Err(nom::Err::Failure(error_position!(
+                    i,
+                    // TODO
+                    nom::error::ErrorKind::Tag
+                )));
+            }
+        };
+        // Padding bytes
+        let (i, _) = take(data_element_tag.padding_byte_size)(i)?;
+        Ok((i, characters))
+    }
+}
+
+fn parse_character_matrix_subelements(
+    endianness: nom::number::Endianness,
+    flags: ArrayFlags,
+) -> impl Fn(&[u8]) -> IResult<&[u8], DataElement> {
+    move |i: &[u8]| {
+        let (i, dimensions) = parse_dimensions_array_subelement(endianness)(i)?;
+        let (i, name) = parse_array_name_subelement(endianness)(i)?;
+        let (i, characters) = parse_character_subelement(endianness)(i)?;
+        if characters.len() != num_elements(&dimensions) {
+            return Err(nom::Err::Failure(error_position!(
+                i,
+                // TODO
+                nom::error::ErrorKind::Tag
+            )));
+        }
+        Ok((
+            i,
+            DataElement::CharacterMatrix(flags, dimensions, name, characters),
+        ))
+    }
+}
+
+fn parse_cell_matrix_subelements(
+    endianness: nom::number::Endianness,
+    flags: ArrayFlags,
+) -> impl Fn(&[u8]) -> IResult<&[u8], DataElement> {
+    move |i: &[u8]| {
+        let (i, dimensions) = parse_dimensions_array_subelement(endianness)(i)?;
+        let (i, name) = parse_array_name_subelement(endianness)(i)?;
+        // Each cell is stored as a separate matrix data element
+        let num_cells = num_elements(&dimensions);
+        let (i, cells) = count(parse_next_data_element(endianness), num_cells)(i)?;
+        Ok((i, DataElement::CellMatrix(flags, dimensions, name, cells)))
+    }
+}
+
+fn parse_field_names_subelements(
+    endianness: nom::number::Endianness,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<String>> {
+    move |i: &[u8]| {
+        // All field names are stored in a single subelement, each one padded
+        // with null bytes to the same length
+        let (i, field_name_length) = parse_numeric_subelement(endianness)(i)?;
+        let field_name_length = match field_name_length {
+            NumericData::Int32(ref length) if length.len() == 1 && length[0] >= 0 => {
+                length[0] as usize
+            }
+            _ => {
+                return Err(nom::Err::Failure(error_position!(
+                    i,
+                    // TODO
+                    nom::error::ErrorKind::Tag
+                )));
+            }
+        };
+        let (i, data_element_tag) = parse_data_element_tag(endianness)(i)?;
+        // Structures without any fields may have a field name length of zero
+        let data_byte_size = data_element_tag.data_byte_size as usize;
+        if !(data_element_tag.data_type == DataType::Int8
+            && (data_byte_size == 0
+                || (field_name_length != 0 && data_byte_size % field_name_length == 0)))
+        {
+            return Err(nom::Err::Failure(error_position!(
+                i,
+                // TODO
+                nom::error::ErrorKind::Tag
+            )));
+        }
+        let (i, field_names) = map_res(take(data_element_tag.data_byte_size), |b: &[u8]| {
+            b.chunks(field_name_length.max(1))
+                .map(|name| {
+                    let length = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                    std::str::from_utf8(&name[..length]).map(|s| s.to_owned())
+                })
+                .collect::<Result<Vec<String>, _>>()
+        })(i)?;
+        // Padding bytes
+        let (i, _) = take(data_element_tag.padding_byte_size)(i)?;
+        Ok((i, field_names))
+    }
+}
+
+fn parse_structure_matrix_subelements(
+    endianness: nom::number::Endianness,
+    flags: ArrayFlags,
+) -> impl Fn(&[u8]) -> IResult<&[u8], DataElement> {
+    move |i: &[u8]| {
+        let (i, dimensions) = parse_dimensions_array_subelement(endianness)(i)?;
+        let (i, name) = parse_array_name_subelement(endianness)(i)?;
+        let (i, (field_names, fields)) =
+            parse_fields_subelements(endianness, num_elements(&dimensions))(i)?;
+        Ok((
+            i,
+            DataElement::StructureMatrix(flags, dimensions, name, field_names, fields),
+        ))
+    }
+}
+
+fn parse_object_matrix_subelements(
+    endianness: nom::number::Endianness,
+    flags: ArrayFlags,
+) -> impl Fn(&[u8]) -> IResult<&[u8], DataElement> {
+    move |i: &[u8]| {
+        let (i, dimensions) = parse_dimensions_array_subelement(endianness)(i)?;
+        let (i, name) = parse_array_name_subelement(endianness)(i)?;
+        // Objects are stored just like structs, with an additional class name
+        let (i, class_name) = parse_array_name_subelement(endianness)(i)?;
+        let (i, (field_names, fields)) =
+            parse_fields_subelements(endianness, num_elements(&dimensions))(i)?;
+        Ok((
+            i,
+            DataElement::ObjectMatrix(flags, dimensions, name, class_name, field_names, fields),
+        ))
+    }
+}
+
+fn parse_fields_subelements(
+    endianness: nom::number::Endianness,
+    num_elements: usize,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Fields> {
+    move |i: &[u8]| {
+        let (i, field_names) = parse_field_names_subelements(endianness)(i)?;
+        // The fields of the first struct come first, followed by the fields
+        // of the second struct etc.
+        let num_fields = match num_elements.checked_mul(field_names.len()) {
+            Some(num_fields) => num_fields,
+            None => {
+                return Err(nom::Err::Failure(error_position!(
+                    i,
+                    nom::error::ErrorKind::TooLarge
+                )))
+            }
+        };
+        let (i, fields) = count(parse_next_data_element(endianness), num_fields)(i)?;
+        Ok((i, (field_names, fields)))
+    }
+}
+
+fn parse_function_handle_subelements(
+    endianness: nom::number::Endianness,
+    flags: ArrayFlags,
+) -> impl Fn(&[u8]) -> IResult<&[u8], DataElement> {
+    move |i: &[u8]| {
+        let (i, dimensions) = parse_dimensions_array_subelement(endianness)(i)?;
+        let (i, name) = parse_array_name_subelement(endianness)(i)?;
+        // The function handle's description is stored in a nested struct
+        let (i, description) = parse_next_data_element(endianness)(i)?;
+        Ok((
+            i,
+            DataElement::FunctionHandle(flags, dimensions, name, Box::new(description)),
+        ))
+    }
+}
+
+fn parse_opaque_subelements(
+    endianness: nom::number::Endianness,
+    flags: ArrayFlags,
+) -> impl Fn(&[u8]) -> IResult<&[u8], DataElement> {
+    move |i: &[u8]| {
+        // Opaque values have no dimensions. Their name is followed by the
+        // name of the type system (usually "MCOS") and the name of the class.
+        let (i, name) = parse_array_name_subelement(endianness)(i)?;
+        let (i, type_system) = parse_array_name_subelement(endianness)(i)?;
+        let (i, class_name) = parse_array_name_subelement(endianness)(i)?;
+        let (i, data) = parse_next_data_element(endianness)(i)?;
+        Ok((
+            i,
+            DataElement::Opaque(flags, name, type_system, class_name, Box::new(data)),
+        ))
+    }
+}
+
 fn parse_row_index_array_subelement(
     endianness: nom::number::Endianness,
 ) -> impl Fn(&[u8]) -> IResult<&[u8], RowIndex> {
     move |i: &[u8]| {
         let (i, data_element_tag) = parse_data_element_tag(endianness)(i)?;
-        if !(data_element_tag.data_type == DataType::Int32 && data_element_tag.data_byte_size > 0) {
+        // Empty sparse matrices have zero-length index arrays
+        if data_element_tag.data_type != DataType::Int32 {
             return Err(nom::Err::Failure(error_position!(
                 i,
                 // TODO
@@ -707,7 +1082,18 @@ fn parse_row_index_array_subelement(
             (data_element_tag.data_byte_size / 4) as usize,
         )(i)?;
         let (i, _) = take(data_element_tag.padding_byte_size)(i)?;
-        Ok((i, row_index.iter().map(|&i| i as usize).collect()))
+        let row_index = row_index
+            .into_iter()
+            .map(usize::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|_err| {
+                nom::Err::Failure(error_position!(
+                    i,
+                    // TODO
+                    nom::error::ErrorKind::Tag
+                ))
+            })?;
+        Ok((i, row_index))
     }
 }
 
@@ -716,7 +1102,8 @@ fn parse_column_index_array_subelement(
 ) -> impl Fn(&[u8]) -> IResult<&[u8], ColumnShift> {
     move |i: &[u8]| {
         let (i, data_element_tag) = parse_data_element_tag(endianness)(i)?;
-        if !(data_element_tag.data_type == DataType::Int32 && data_element_tag.data_byte_size > 0) {
+        // Empty sparse matrices have zero-length index arrays
+        if data_element_tag.data_type != DataType::Int32 {
             return Err(nom::Err::Failure(error_position!(
                 i,
                 // TODO
@@ -728,12 +1115,23 @@ fn parse_column_index_array_subelement(
             (data_element_tag.data_byte_size / 4) as usize,
         )(i)?;
         let (i, _) = take(data_element_tag.padding_byte_size)(i)?;
-        Ok((i, column_index.iter().map(|&i| i as usize).collect()))
+        let column_index = column_index
+            .into_iter()
+            .map(usize::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|_err| {
+                nom::Err::Failure(error_position!(
+                    i,
+                    // TODO
+                    nom::error::ErrorKind::Tag
+                ))
+            })?;
+        Ok((i, column_index))
     }
 }
 
-pub fn replace_err_slice<'old, 'new>(
-    err: nom::Err<nom::error::Error<&'old [u8]>>,
+pub fn replace_err_slice<'new>(
+    err: nom::Err<nom::error::Error<&[u8]>>,
     new_slice: &'new [u8],
 ) -> nom::Err<nom::error::Error<&'new [u8]>> {
     match err {
@@ -758,26 +1156,247 @@ fn parse_unsupported_data_element(
 #[derive(Debug)]
 pub struct ParseResult {
     pub header: Header,
-    pub data_elements: Vec<DataElement>,
+    /// The variables along with the position of their data elements in the
+    /// file. Elements that can't be parsed are `DataElement::Unsupported`.
+    pub data_elements: Vec<(DataElement, Range<usize>)>,
+    pub subsystem: Option<DataElement>,
+    /// The position of the subsystem data element, even if it can't be
+    /// parsed
+    pub subsystem_range: Option<Range<usize>>,
 }
 
-pub fn parse_all(i: &[u8]) -> IResult<&[u8], ParseResult> {
-    let (i, header) = parse_header(i)?;
-    let endianness = if header.is_little_endian {
+/// The subsystem data is stored as the bytes of an unnamed uint8 array. These
+/// bytes start with a shortened version of the file header followed by a
+/// struct holding the actual data.
+fn parse_subsystem_data(i: &[u8]) -> IResult<&[u8], DataElement> {
+    let (i, _version) = take(2usize)(i)?;
+    let (i, is_little_endian) = alt((value(true, tag("IM")), value(false, tag("MI"))))(i)?;
+    let (i, _padding) = take(4usize)(i)?;
+    let endianness = if is_little_endian {
         nom::number::Endianness::Little
     } else {
         nom::number::Endianness::Big
     };
-    let (i, data_elements) = many0(complete(parse_next_data_element(endianness)))(i)?;
+    parse_next_data_element(endianness)(i)
+}
+
+/// Reads the subsystem data, which is stored as the bytes of a uint8 array
+/// in the data element the header points to.
+pub fn parse_subsystem_element(
+    endianness: nom::number::Endianness,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Option<DataElement>> {
+    move |i: &[u8]| {
+        let (i, data_element) = parse_next_data_element(endianness)(i)?;
+        match data_element {
+            DataElement::NumericMatrix(_, _, _, NumericData::UInt8(bytes), None) => {
+                let (_, subsystem) =
+                    parse_subsystem_data(&bytes).map_err(|err| replace_err_slice(err, i))?;
+                Ok((i, Some(subsystem)))
+            }
+            _ => Ok((i, None)),
+        }
+    }
+}
+
+/// Parses all data elements of a file. Each element is split off by its tag
+/// before being parsed, so that an element which can't be parsed doesn't
+/// stop the parser: it is kept as `DataElement::Unsupported`. Bytes following
+/// the last complete element are ignored.
+pub fn parse_all(i: &[u8]) -> IResult<&[u8], ParseResult> {
+    let file_size = i.len();
+    let (mut i, header) = parse_header(i)?;
+    let endianness = header.endianness();
+    let mut data_elements = Vec::new();
+    let mut subsystem = None;
+    let mut subsystem_range = None;
+    while let Ok((remaining, _)) = complete(parse_raw_data_element(endianness))(i) {
+        let range = file_size - i.len()..file_size - remaining.len();
+        let element = &i[..range.len()];
+        // The subsystem data element is not a variable. Variables following
+        // it are unusual, but possible.
+        if header.subsystem_offset == Some(range.start as u64) {
+            subsystem = parse_subsystem_element(endianness)(element)
+                .ok()
+                .and_then(|(_, subsystem)| subsystem);
+            subsystem_range = Some(range);
+        } else {
+            let data_element = parse_next_data_element(endianness)(element)
+                .map_or(DataElement::Unsupported, |(_, data_element)| data_element);
+            data_elements.push((data_element, range));
+        }
+        i = remaining;
+    }
     Ok((
         i,
         ParseResult {
-            header: header,
-            data_elements: data_elements,
+            header,
+            data_elements,
+            subsystem,
+            subsystem_range,
         },
     ))
 }
 
+/// The position of a top-level data element in a file and the name of the
+/// variable it holds, if that can be read.
+#[derive(Clone, Debug)]
+pub struct ElementLocation {
+    pub start: usize,
+    pub end: usize,
+    pub name: Option<String>,
+}
+
+/// Locates the data elements of a file without decoding them. Like
+/// `parse_next_data_element` only the tags are needed to skip from one
+/// element to the next, but the name of each variable is read as well.
+/// Bytes following the last element that can be read are returned as the
+/// remaining input.
+pub fn locate_data_elements(i: &[u8]) -> IResult<&[u8], (Header, Vec<ElementLocation>)> {
+    let file_size = i.len();
+    let (mut i, header) = parse_header(i)?;
+    let endianness = header.endianness();
+    let mut locations = Vec::new();
+    while let Ok((remaining, (data_type, data))) = complete(parse_raw_data_element(endianness))(i) {
+        locations.push(ElementLocation {
+            start: file_size - i.len(),
+            end: file_size - remaining.len(),
+            name: read_matrix_header(endianness, data_type, data).map(|header| header.name),
+        });
+        i = remaining;
+    }
+    Ok((i, (header, locations)))
+}
+
+/// Reads the name and the Matlab class of the variable held by a complete
+/// data element (including its tag), as far as they can be read.
+pub fn identify_data_element(
+    endianness: nom::number::Endianness,
+    element: &[u8],
+) -> Option<(String, String)> {
+    let (_, (data_type, data)) = parse_raw_data_element(endianness)(element).ok()?;
+    read_matrix_header(endianness, data_type, data).map(|header| (header.name, header.class_name))
+}
+
+/// Splits off the next data element without decoding it, returning its data
+/// type and its (possibly compressed) data.
+fn parse_raw_data_element(
+    endianness: nom::number::Endianness,
+) -> impl Fn(&[u8]) -> IResult<&[u8], (DataType, &[u8])> {
+    move |i: &[u8]| {
+        let (i, data_element_tag) = parse_data_element_tag(endianness)(i)?;
+        let (i, data) = take(data_element_tag.data_byte_size)(i)?;
+        // Compressed elements aren't padded, see `parse_next_data_element`
+        let num_padding_bytes = if data_element_tag.data_type == DataType::Compressed {
+            0
+        } else {
+            data_element_tag.padding_byte_size
+        };
+        let (i, _) = opt(complete(take(num_padding_bytes)))(i)?;
+        Ok((i, (data_element_tag.data_type, data)))
+    }
+}
+
+/// Reads the header of a matrix data element from the data of an element
+/// of the given type, inflating compressed elements as far as needed.
+/// Offsets in the header are relative to the start of the data.
+pub fn read_matrix_header<R: Read>(
+    endianness: nom::number::Endianness,
+    data_type: DataType,
+    data: R,
+) -> Option<MatrixHeader> {
+    match data_type {
+        DataType::Matrix => read_prefix(data, parse_matrix_header(endianness)),
+        DataType::Compressed => {
+            let (data_element_tag, tag_len, mut header) =
+                read_prefix(Decoder::new(data).ok()?, |i: &[u8]| {
+                    let (remaining, data_element_tag) = parse_data_element_tag(endianness)(i)?;
+                    let tag_len = i.len() - remaining.len();
+                    let (remaining, header) = parse_matrix_header(endianness)(remaining)?;
+                    Ok((remaining, (data_element_tag, tag_len, header)))
+                })?;
+            if let Some((_, offset)) = &mut header.real_part {
+                *offset += tag_len;
+            }
+            Some(header).filter(|_| data_element_tag.data_type == DataType::Matrix)
+        }
+        _ => None,
+    }
+}
+
+/// Reads just enough of the data to apply the parser.
+fn read_prefix<R: Read, O>(
+    mut reader: R,
+    mut parser: impl FnMut(&[u8]) -> IResult<&[u8], O>,
+) -> Option<O> {
+    let mut buf = Vec::new();
+    loop {
+        match parser(&buf) {
+            Ok((_, output)) => return Some(output),
+            Err(nom::Err::Error(_)) => (),
+            Err(_) => return None,
+        }
+        let num_bytes = buf.len();
+        (&mut reader).take(256).read_to_end(&mut buf).ok()?;
+        if buf.len() == num_bytes {
+            return None;
+        }
+    }
+}
+
+/// The subelements at the start of a matrix data element that describe the
+/// array, without any of its data.
+#[derive(Clone, Debug)]
+pub struct MatrixHeader {
+    pub flags: ArrayFlags,
+    /// Opaque values have no dimensions
+    pub dimensions: Option<Dimensions>,
+    pub name: String,
+    pub class_name: String,
+    /// For numeric arrays, the tag of the real part and the offset of its
+    /// data from the start of the data of the element, which for compressed
+    /// elements is the inflated data
+    pub real_part: Option<(DataElementTag, usize)>,
+}
+
+/// Reads the array flags, the dimensions (for anything but opaque values)
+/// and the name of a matrix data element, along with the class name of
+/// objects and the tag of the real part of numeric arrays.
+pub fn parse_matrix_header(
+    endianness: nom::number::Endianness,
+) -> impl Fn(&[u8]) -> IResult<&[u8], MatrixHeader> {
+    move |data: &[u8]| {
+        let (i, flags) = parse_array_flags_subelement(endianness)(data)?;
+        let (i, dimensions) = cond(
+            flags.class != ArrayType::Opaque,
+            parse_dimensions_array_subelement(endianness),
+        )(i)?;
+        let (i, name) = parse_array_name_subelement(endianness)(i)?;
+        let (i, class_name) = match flags.class {
+            ArrayType::Object => parse_array_name_subelement(endianness)(i)?,
+            ArrayType::Opaque => {
+                let (i, _type_system) = parse_array_name_subelement(endianness)(i)?;
+                parse_array_name_subelement(endianness)(i)?
+            }
+            class => (i, class.matlab_class(flags.logical).to_owned()),
+        };
+        let (i, real_part) = cond(
+            flags.class.numeric_data_type().is_some(),
+            parse_data_element_tag(endianness),
+        )(i)?;
+        let real_part = real_part.map(|tag| (tag, data.len() - i.len()));
+        Ok((
+            i,
+            MatrixHeader {
+                flags,
+                dimensions,
+                name,
+                class_name,
+                real_part,
+            },
+        ))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -787,7 +1406,7 @@ mod test {
         let data = include_bytes!("../tests/sparse1.mat");
 
         let (_, parsed_data) = parse_all(data).unwrap();
-        let parsed_matrix_data = parsed_data.data_elements[0].clone();
+        let parsed_matrix_data = parsed_data.data_elements[0].0.clone();
         if let DataElement::SparseMatrix(_flags, dim, _name, irows, icols, real_vals, imag_vals) =
             parsed_matrix_data
         {
@@ -809,7 +1428,7 @@ mod test {
         let data = include_bytes!("../tests/sparse2.mat");
 
         let (_, parsed_data) = parse_all(data).unwrap();
-        let parsed_matrix_data = parsed_data.data_elements[0].clone();
+        let parsed_matrix_data = parsed_data.data_elements[0].0.clone();
         if let DataElement::SparseMatrix(_flags, dim, _name, irows, icols, real_vals, imag_vals) =
             parsed_matrix_data
         {
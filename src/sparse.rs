@@ -0,0 +1,400 @@
+use crate::parse;
+use crate::{Array, Error, NumericData};
+
+/// A sparse matrix.
+///
+/// Matlab stores sparse matrices in compressed sparse column (CSC) format:
+/// the nonzero values are stored column after column, together with their
+/// row. For every column the offset of its first nonzero is stored, plus a
+/// final entry holding the total number of nonzeros, so that the nonzeros of
+/// column `j` are those in the range `column_offsets[j]..column_offsets[j + 1]`.
+///
+/// Matlab only supports sparse matrices of doubles (real or complex) and
+/// logicals. The values of logical matrices are stored as `UInt8` data
+/// holding zeros and ones, just like dense logical arrays.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/sparse1.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::Sparse(sparse)) = mat_file.values().first() {
+///     println!(
+///         "{}x{} matrix with {} nonzeros",
+///         sparse.num_rows(),
+///         sparse.num_columns(),
+///         sparse.num_nonzeros()
+///     );
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SparseArray {
+    pub(crate) name: String,
+    size: Vec<usize>,
+    row_indices: Vec<usize>,
+    column_offsets: Vec<usize>,
+    data: NumericData,
+    logical: bool,
+}
+
+impl SparseArray {
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of this array. Sparse arrays are always two-dimensional.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of rows.
+    pub fn num_rows(&self) -> usize {
+        self.size[0]
+    }
+
+    /// The number of columns.
+    pub fn num_columns(&self) -> usize {
+        self.size[1]
+    }
+
+    /// The number of stored values.
+    pub fn num_nonzeros(&self) -> usize {
+        self.row_indices.len()
+    }
+
+    /// The (zero-based) row of every stored value.
+    pub fn row_indices(&self) -> &Vec<usize> {
+        &self.row_indices
+    }
+
+    /// The offset of the first stored value of every column, followed by
+    /// the number of stored values. Always has one more entry than there
+    /// are columns.
+    pub fn column_offsets(&self) -> &Vec<usize> {
+        &self.column_offsets
+    }
+
+    /// The stored values. `Double` data for numeric matrices, `UInt8` data
+    /// for logical ones.
+    pub fn data(&self) -> &NumericData {
+        &self.data
+    }
+
+    /// Whether this is a logical matrix.
+    pub fn is_logical(&self) -> bool {
+        self.logical
+    }
+
+    /// Creates a real sparse matrix from `(row, column, value)` triplets,
+    /// like Matlab's `sparse(i, j, v, m, n)`: values with the same row and
+    /// column are added up and zeros are not stored.
+    pub fn from_triplets(
+        name: &str,
+        num_rows: usize,
+        num_columns: usize,
+        triplets: &[(usize, usize, f64)],
+    ) -> Result<Self, Error> {
+        let (row_indices, column_offsets, values) =
+            compress(num_rows, num_columns, triplets, |a, b| a + b, |&v| v == 0.0)?;
+        Ok(SparseArray {
+            name: name.to_owned(),
+            size: vec![num_rows, num_columns],
+            row_indices,
+            column_offsets,
+            data: NumericData::Double {
+                real: values,
+                imag: None,
+            },
+            logical: false,
+        })
+    }
+
+    /// Creates a complex sparse matrix from `(row, column, real, imag)`
+    /// tuples, see `from_triplets`.
+    pub fn from_complex_triplets(
+        name: &str,
+        num_rows: usize,
+        num_columns: usize,
+        triplets: &[(usize, usize, f64, f64)],
+    ) -> Result<Self, Error> {
+        let triplets: Vec<_> = triplets
+            .iter()
+            .map(|&(row, column, real, imag)| (row, column, (real, imag)))
+            .collect();
+        let (row_indices, column_offsets, values) = compress(
+            num_rows,
+            num_columns,
+            &triplets,
+            |a, b| (a.0 + b.0, a.1 + b.1),
+            |&v| v == (0.0, 0.0),
+        )?;
+        let (real, imag) = values.into_iter().unzip();
+        Ok(SparseArray {
+            name: name.to_owned(),
+            size: vec![num_rows, num_columns],
+            row_indices,
+            column_offsets,
+            data: NumericData::Double {
+                real,
+                imag: Some(imag),
+            },
+            logical: false,
+        })
+    }
+
+    /// Creates a logical sparse matrix which is true at the given
+    /// `(row, column)` positions.
+    pub fn from_logical_triplets(
+        name: &str,
+        num_rows: usize,
+        num_columns: usize,
+        positions: &[(usize, usize)],
+    ) -> Result<Self, Error> {
+        let triplets: Vec<_> = positions
+            .iter()
+            .map(|&(row, column)| (row, column, 1u8))
+            .collect();
+        let (row_indices, column_offsets, values) =
+            compress(num_rows, num_columns, &triplets, |a, _| a, |_| false)?;
+        Ok(SparseArray {
+            name: name.to_owned(),
+            size: vec![num_rows, num_columns],
+            row_indices,
+            column_offsets,
+            data: NumericData::UInt8 {
+                real: values,
+                imag: None,
+            },
+            logical: true,
+        })
+    }
+
+    /// Creates a sparse matrix from its compressed sparse column
+    /// representation, see the type level documentation. The data has to be
+    /// `Double` (real or complex) for numeric matrices or `UInt8` for logical
+    /// ones, with one value per row index. Within every column the row
+    /// indices have to be strictly increasing.
+    pub fn from_csc(
+        name: &str,
+        num_rows: usize,
+        num_columns: usize,
+        row_indices: Vec<usize>,
+        column_offsets: Vec<usize>,
+        data: NumericData,
+    ) -> Result<Self, Error> {
+        check_matrix_size(num_rows, num_columns)?;
+        let invalid = |message: &str| Err(Error::InvalidArgument(message.to_owned()));
+        let num_nonzeros = row_indices.len();
+        if column_offsets.len() != num_columns + 1
+            || column_offsets[0] != 0
+            || column_offsets[num_columns] != num_nonzeros
+            || column_offsets.windows(2).any(|w| w[0] > w[1])
+        {
+            return invalid("the column offsets don't match the number of columns and nonzeros");
+        }
+        if num_nonzeros > i32::MAX as usize {
+            return invalid("too many nonzeros");
+        }
+        for offsets in column_offsets.windows(2) {
+            let rows = &row_indices[offsets[0]..offsets[1]];
+            if rows.windows(2).any(|w| w[0] >= w[1]) || rows.last().is_some_and(|&r| r >= num_rows)
+            {
+                return invalid(
+                    "the row indices have to be increasing and smaller than the number of rows",
+                );
+            }
+        }
+        if data.len() != num_nonzeros || data.imag_len().is_some_and(|len| len != num_nonzeros) {
+            return invalid("the number of values doesn't match the number of row indices");
+        }
+        let (data, logical) = match data {
+            NumericData::Double { .. } => (data, false),
+            NumericData::UInt8 { real, imag: None } => {
+                let real = real.into_iter().map(|v| u8::from(v != 0)).collect();
+                (NumericData::UInt8 { real, imag: None }, true)
+            }
+            _ => return invalid("sparse matrices hold either doubles or logicals"),
+        };
+        Ok(SparseArray {
+            name: name.to_owned(),
+            size: vec![num_rows, num_columns],
+            row_indices,
+            column_offsets,
+            data,
+            logical,
+        })
+    }
+
+    /// Iterates over the nonzeros of the given column, yielding their row
+    /// and their index into `row_indices` and the values. Yields nothing if
+    /// the column doesn't exist.
+    pub fn column_nonzeros(&self, column: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let range = match self.column_offsets.get(column..column + 2) {
+            Some(offsets) => offsets[0]..offsets[1],
+            None => 0..0,
+        };
+        range.map(move |index| (self.row_indices[index], index))
+    }
+
+    /// The nonzeros as `(row, column, value)` triplets in column-major
+    /// order. Only holds the real part of complex matrices, the values of
+    /// logical matrices are 1.
+    pub fn to_triplets(&self) -> Vec<(usize, usize, f64)> {
+        let real = self.data.real_to_f64();
+        self.positions()
+            .map(|(row, column, index)| (row, column, real[index]))
+            .collect()
+    }
+
+    /// The nonzeros as `(row, column, real, imag)` tuples in column-major
+    /// order. The imaginary part is zero for real matrices.
+    pub fn to_complex_triplets(&self) -> Vec<(usize, usize, f64, f64)> {
+        let real = self.data.real_to_f64();
+        let imag = match &self.data {
+            NumericData::Double {
+                imag: Some(imag), ..
+            } => Some(imag),
+            _ => None,
+        };
+        self.positions()
+            .map(|(row, column, index)| {
+                let imag = imag.map_or(0.0, |imag| imag[index]);
+                (row, column, real[index], imag)
+            })
+            .collect()
+    }
+
+    /// Converts this matrix into a dense array of the same name, holding
+    /// doubles or (for logical matrices) zeros and ones stored as `UInt8`.
+    pub fn to_dense(&self) -> Array {
+        let num_rows = self.num_rows();
+        let len = num_rows * self.num_columns();
+        let scatter = |values: &Vec<f64>| {
+            let mut dense = vec![0.0; len];
+            for (row, column, index) in self.positions() {
+                dense[column * num_rows + row] = values[index];
+            }
+            dense
+        };
+        let data = match &self.data {
+            NumericData::Double { real, imag } => NumericData::Double {
+                real: scatter(real),
+                imag: imag.as_ref().map(scatter),
+            },
+            _ => {
+                let mut dense = vec![0u8; len];
+                for (row, column, _) in self.positions() {
+                    dense[column * num_rows + row] = 1;
+                }
+                NumericData::UInt8 {
+                    real: dense,
+                    imag: None,
+                }
+            }
+        };
+        Array {
+            name: self.name.clone(),
+            size: self.size.clone(),
+            data,
+            logical: self.logical,
+        }
+    }
+
+    /// Iterates over the `(row, column, index)` of all nonzeros.
+    fn positions(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        (0..self.num_columns()).flat_map(move |column| {
+            self.column_nonzeros(column)
+                .map(move |(row, index)| (row, column, index))
+        })
+    }
+
+    /// Converts the parsed matrix, whose index arrays have already been
+    /// validated by the parser. Numeric values are normalized to doubles
+    /// (Matlab may store them using a smaller type), logical values to
+    /// zeros and ones.
+    pub(crate) fn try_from(
+        flags: parse::ArrayFlags,
+        dims: parse::Dimensions,
+        name: String,
+        row_indices: parse::RowIndex,
+        column_offsets: parse::ColumnShift,
+        real: parse::NumericData,
+        imag: Option<parse::NumericData>,
+    ) -> Result<Self, Error> {
+        let data = if flags.logical {
+            let values = real.nonzero().into_iter().map(u8::from).collect();
+            NumericData::UInt8 {
+                real: values,
+                imag: None,
+            }
+        } else {
+            NumericData::try_from(parse::ArrayType::Double, real, imag)?
+        };
+        Ok(SparseArray {
+            name,
+            size: dims.into_iter().map(|d| d as usize).collect(),
+            row_indices,
+            column_offsets,
+            data,
+            logical: flags.logical,
+        })
+    }
+}
+
+/// Row indices, column offsets and values of a matrix in compressed sparse
+/// column format
+type Compressed<T> = (Vec<usize>, Vec<usize>, Vec<T>);
+
+/// Sorts triplets into compressed sparse column format, combining values at
+/// the same position and dropping zeros.
+fn compress<T: Copy>(
+    num_rows: usize,
+    num_columns: usize,
+    triplets: &[(usize, usize, T)],
+    combine: impl Fn(T, T) -> T,
+    is_zero: impl Fn(&T) -> bool,
+) -> Result<Compressed<T>, Error> {
+    check_matrix_size(num_rows, num_columns)?;
+    if let Some(&(row, column, _)) = triplets
+        .iter()
+        .find(|&&(row, column, _)| row >= num_rows || column >= num_columns)
+    {
+        return Err(Error::InvalidArgument(format!(
+            "position ({}, {}) is outside of a {}x{} matrix",
+            row, column, num_rows, num_columns
+        )));
+    }
+    let mut sorted = triplets.to_vec();
+    sorted.sort_by_key(|&(row, column, _)| (column, row));
+    let mut entries: Vec<(usize, usize, T)> = Vec::with_capacity(sorted.len());
+    for (row, column, value) in sorted {
+        match entries.last_mut() {
+            Some(last) if last.0 == row && last.1 == column => last.2 = combine(last.2, value),
+            _ => entries.push((row, column, value)),
+        }
+    }
+    entries.retain(|(_, _, value)| !is_zero(value));
+    let mut column_offsets = vec![0; num_columns + 1];
+    for &(_, column, _) in &entries {
+        column_offsets[column + 1] += 1;
+    }
+    for column in 0..num_columns {
+        column_offsets[column + 1] += column_offsets[column];
+    }
+    let row_indices = entries.iter().map(|&(row, _, _)| row).collect();
+    let values = entries.into_iter().map(|(_, _, value)| value).collect();
+    Ok((row_indices, column_offsets, values))
+}
+
+/// The format stores sizes and indices as 32 bit integers.
+fn check_matrix_size(num_rows: usize, num_columns: usize) -> Result<(), Error> {
+    if num_rows > i32::MAX as usize || num_columns > i32::MAX as usize {
+        return Err(Error::InvalidArgument(format!(
+            "a {}x{} matrix is too large",
+            num_rows, num_columns
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,550 @@
+use crate::mcos::{Subsystem, SubsystemWriter};
+use crate::parse::{self, ArrayType, DataType};
+use crate::write::{
+    byte_size, encode_subsystem, encode_value, extend_real_bytes, header, numeric_class,
+    numeric_matrix_header, padding, DEFLATE_BLOCK_SIZE,
+};
+use crate::{check_name, Error, NumericData, RawValue, Value, WriteOptions};
+use libflate::{deflate, zlib};
+use std::collections::{HashSet, VecDeque};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Writes a ".mat" file one variable at a time.
+///
+/// Besides complete values, numeric matrices with a fixed number of rows can
+/// be written incrementally with `begin_array`, without ever holding all of
+/// their data in memory. Their size is filled in once they are finished,
+/// which is why the writer needs to be able to seek. Since data is written
+/// in small pieces, wrapping files in a `std::io::BufWriter` is recommended.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use matfile::{MatFileWriter, NumericData, WriteOptions};
+///
+/// let file = std::fs::File::create(std::env::temp_dir().join("samples.mat"))?;
+/// let options = WriteOptions::new().compress(true);
+/// let mut writer = MatFileWriter::with_options(std::io::BufWriter::new(file), &options)?;
+/// let mut samples = writer.begin_array("samples", 2)?;
+/// for t in 0..1000 {
+///     let t = t as f64;
+///     samples.append_column(&NumericData::Double {
+///         real: vec![t.sin(), t.cos()],
+///         imag: None,
+///     })?;
+/// }
+/// samples.finish()?;
+/// writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MatFileWriter<W: Write + Seek> {
+    writer: W,
+    options: WriteOptions,
+    subsystem: SubsystemWriter,
+    names: HashSet<String>,
+    /// The position of the file header
+    start: u64,
+}
+
+impl<W: Write + Seek> MatFileWriter<W> {
+    /// Starts a file at the current position of the writer using the
+    /// default options.
+    pub fn new(writer: W) -> Result<Self, Error> {
+        Self::with_options(writer, &WriteOptions::default())
+    }
+
+    /// Starts a file at the current position of the writer.
+    pub fn with_options(mut writer: W, options: &WriteOptions) -> Result<Self, Error> {
+        let start = writer.stream_position().map_err(Error::IOError)?;
+        writer.write_all(&header(None)).map_err(Error::IOError)?;
+        Ok(MatFileWriter {
+            writer,
+            options: options.clone(),
+            subsystem: SubsystemWriter::default(),
+            names: HashSet::new(),
+            start,
+        })
+    }
+
+    /// Writes a complete value. Like with `MatFile::from_values`, every
+    /// variable needs a unique name that is a valid Matlab identifier.
+    pub fn write_value(&mut self, value: &Value) -> Result<(), Error> {
+        self.add_name(value.name())?;
+        let element = encode_value(value, &self.options, &mut self.subsystem)?;
+        self.writer.write_all(&element).map_err(Error::IOError)
+    }
+
+    /// Starts a real numeric matrix with the given number of rows, whose
+    /// data is appended with the returned `ArrayWriter`. Nothing else can be
+    /// written to the file before it is finished.
+    pub fn begin_array(
+        &mut self,
+        name: &str,
+        num_rows: usize,
+    ) -> Result<ArrayWriter<'_, W>, Error> {
+        if num_rows == 0 {
+            return Err(Error::InvalidArgument(
+                "a matrix written incrementally needs at least one row".to_owned(),
+            ));
+        }
+        self.add_name(name)?;
+        ArrayWriter::new(&mut self.writer, name, num_rows, self.options.compress)
+    }
+
+    /// Writes the subsystem data holding the objects of all variables (if
+    /// there are any) and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        let subsystem = std::mem::take(&mut self.subsystem);
+        if let Some(subsystem) = encode_subsystem(&self.options, subsystem)? {
+            let position = self.writer.stream_position().map_err(Error::IOError)?;
+            self.writer.write_all(&subsystem).map_err(Error::IOError)?;
+            let end = self.writer.stream_position().map_err(Error::IOError)?;
+            let header = header(Some(position - self.start));
+            self.patch(self.start, &header, end)?;
+        }
+        self.writer.flush().map_err(Error::IOError)?;
+        Ok(self.writer)
+    }
+
+    fn add_name(&mut self, name: &str) -> Result<(), Error> {
+        check_name(name)?;
+        if !self.names.insert(name.to_owned()) {
+            return Err(Error::InvalidArgument(format!(
+                "the name \"{}\" is used more than once",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    fn patch(&mut self, position: u64, bytes: &[u8], end: u64) -> Result<(), Error> {
+        patch(&mut self.writer, position, bytes, end)
+    }
+}
+
+/// Appends the data of a numeric matrix to a file, see
+/// `MatFileWriter::begin_array`.
+///
+/// The data is given in column-major order, either column by column or in
+/// arbitrarily sized pieces, as long as all pieces are of the same type and
+/// all columns are complete once the matrix is finished. Complex data can't
+/// be written incrementally since the imaginary part is stored after the
+/// real part.
+///
+/// For compressed files the data is deflated as it is appended. The header
+/// of the matrix, which isn't known until the end, is placed in front of it
+/// as an uncompressed (stored) block of fixed size, which is filled in
+/// afterwards.
+pub struct ArrayWriter<'a, W: Write + Seek> {
+    sink: Sink<'a, W>,
+    /// The position of the data element tag
+    start: u64,
+    name: String,
+    num_rows: usize,
+    /// The class and data type given by the first piece of data
+    class: Option<(ArrayType, DataType)>,
+    num_elements: usize,
+    data_len: usize,
+    /// The checksum of the uncompressed data, for compressed files
+    checksum: Adler32,
+}
+
+enum Sink<'a, W: Write> {
+    Uncompressed(&'a mut W),
+    Compressed(deflate::Encoder<&'a mut W>),
+}
+
+/// The zlib stream header: deflate with the default window size and
+/// compression level.
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x9c];
+
+impl<'a, W: Write + Seek> ArrayWriter<'a, W> {
+    fn new(writer: &'a mut W, name: &str, num_rows: usize, compress: bool) -> Result<Self, Error> {
+        let start = writer.stream_position().map_err(Error::IOError)?;
+        let header =
+            numeric_matrix_header(ArrayType::Double, &[num_rows, 0], name, DataType::Double, 0)?;
+        let sink = if compress {
+            // The compressed data element starts with a stored block holding
+            // the matrix header
+            let mut prefix = Vec::with_capacity(header.len() + 15);
+            prefix.extend_from_slice(&(DataType::Compressed as u32).to_le_bytes());
+            prefix.extend_from_slice(&[0; 4]);
+            prefix.extend_from_slice(&ZLIB_HEADER);
+            prefix.push(0);
+            prefix.extend_from_slice(&(header.len() as u16).to_le_bytes());
+            prefix.extend_from_slice(&(!(header.len() as u16)).to_le_bytes());
+            prefix.extend_from_slice(&header);
+            writer.write_all(&prefix).map_err(Error::IOError)?;
+            let options = deflate::EncodeOptions::new().block_size(DEFLATE_BLOCK_SIZE);
+            Sink::Compressed(deflate::Encoder::with_options(writer, options))
+        } else {
+            writer.write_all(&header).map_err(Error::IOError)?;
+            Sink::Uncompressed(writer)
+        };
+        Ok(ArrayWriter {
+            sink,
+            start,
+            name: name.to_owned(),
+            num_rows,
+            class: None,
+            num_elements: 0,
+            data_len: 0,
+            checksum: Adler32::new(),
+        })
+    }
+
+    /// The number of rows of the matrix.
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// The number of complete columns appended so far.
+    pub fn num_columns(&self) -> usize {
+        self.num_elements / self.num_rows
+    }
+
+    /// Appends elements in column-major order.
+    pub fn append(&mut self, data: &NumericData) -> Result<(), Error> {
+        if data.is_complex() {
+            return Err(Error::InvalidArgument(
+                "complex data can't be written incrementally".to_owned(),
+            ));
+        }
+        let class = numeric_class(data)?;
+        let mut bytes = Vec::new();
+        let data_type = extend_real_bytes(&mut bytes, data);
+        match self.class {
+            Some(previous) if previous != (class, data_type) => {
+                return Err(Error::InvalidArgument(format!(
+                    "{:?} data can't be appended to a {:?} matrix",
+                    class, previous.0
+                )))
+            }
+            _ => self.class = Some((class, data_type)),
+        }
+        // The size of the whole matrix, including its header of at most 128
+        // bytes, has to fit into the tag
+        let data_len = self.data_len + bytes.len();
+        byte_size(data_len + padding(data_len) + 128)?;
+        self.write(&bytes)?;
+        self.num_elements += data.len();
+        self.data_len = data_len;
+        Ok(())
+    }
+
+    /// Appends a column, which needs as many elements as the matrix has rows.
+    pub fn append_column(&mut self, column: &NumericData) -> Result<(), Error> {
+        if column.len() != self.num_rows {
+            return Err(Error::InvalidArgument(format!(
+                "a column of the matrix needs {} elements",
+                self.num_rows
+            )));
+        }
+        if self.num_elements % self.num_rows != 0 {
+            return Err(Error::InvalidArgument(
+                "the last column of the matrix isn't complete".to_owned(),
+            ));
+        }
+        self.append(column)
+    }
+
+    /// Fills in the size of the matrix. Unless this succeeds, which requires
+    /// all columns to be complete, the file is left in an invalid state.
+    pub fn finish(mut self) -> Result<(), Error> {
+        if self.num_elements % self.num_rows != 0 {
+            return Err(Error::InvalidArgument(
+                "the last column of the matrix isn't complete".to_owned(),
+            ));
+        }
+        self.write(&vec![0; padding(self.data_len)])?;
+        let (class, data_type) = self.class.unwrap_or((ArrayType::Double, DataType::Double));
+        let size = [self.num_rows, self.num_columns()];
+        let header = numeric_matrix_header(class, &size, &self.name, data_type, self.data_len)?;
+        match self.sink {
+            Sink::Uncompressed(writer) => {
+                let end = writer.stream_position().map_err(Error::IOError)?;
+                patch(writer, self.start, &header, end)
+            }
+            Sink::Compressed(encoder) => {
+                let writer = encoder.finish().into_result().map_err(Error::IOError)?;
+                // The checksum covers the header followed by the data
+                let data_len = self.data_len + padding(self.data_len);
+                let checksum =
+                    Adler32::combine(Adler32::of(&header), self.checksum.value(), data_len as u64);
+                writer
+                    .write_all(&checksum.to_be_bytes())
+                    .map_err(Error::IOError)?;
+                let end = writer.stream_position().map_err(Error::IOError)?;
+                let len = byte_size((end - self.start - 8) as usize)?;
+                patch(writer, self.start + 4, &len.to_le_bytes(), end)?;
+                let header_position = self.start + 8 + ZLIB_HEADER.len() as u64 + 5;
+                patch(writer, header_position, &header, end)
+            }
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        match &mut self.sink {
+            Sink::Uncompressed(writer) => writer.write_all(bytes),
+            Sink::Compressed(encoder) => {
+                self.checksum.update(bytes);
+                encoder.write_all(bytes)
+            }
+        }
+        .map_err(Error::IOError)
+    }
+}
+
+/// Reads the variables of a ".mat" file one at a time from a reader that
+/// doesn't need to be able to seek, like a pipe or the body of an HTTP
+/// response. Compressed variables are inflated as they are read, and no
+/// data is kept once a variable has been returned.
+///
+/// Objects like `string` arrays or tables are stored in the subsystem data
+/// at the end of the file. Variables holding them are kept back in their
+/// encoded form and returned once the subsystem data has been read, all
+/// other variables are returned in the order in which they are stored.
+/// Like with `MatFile::parse`, variables of unsupported types are returned
+/// as raw values.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let file = std::fs::File::open("tests/two_arrays.mat")?;
+/// for value in matfile::MatFileReader::new(file)? {
+///     if let matfile::Value::Numeric(array) = value? {
+///         println!("{}: {:?}", array.name(), array.size());
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct MatFileReader<R: Read> {
+    reader: R,
+    endianness: nom::number::Endianness,
+    subsystem_offset: Option<u64>,
+    /// The position of the next data element in the file
+    position: u64,
+    /// Available once the subsystem data has been read, or right away for
+    /// files without any
+    subsystem: Option<Subsystem>,
+    /// Encoded variables waiting for the subsystem data
+    deferred: VecDeque<Vec<u8>>,
+    finished: bool,
+}
+
+impl<R: Read> MatFileReader<R> {
+    /// Reads the header of a file starting at the current position of the
+    /// reader.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut buf = [0; 128];
+        reader.read_exact(&mut buf).map_err(Error::IOError)?;
+        let (_, header) = parse::parse_header(&buf)
+            .map_err(|err| Error::ParseError(parse::replace_err_slice(err, &[])))?;
+        Ok(MatFileReader {
+            reader,
+            endianness: header.endianness(),
+            subsystem_offset: header.subsystem_offset(),
+            position: 128,
+            subsystem: match header.subsystem_offset() {
+                Some(_) => None,
+                None => Some(Subsystem::default()),
+            },
+            deferred: VecDeque::new(),
+            finished: false,
+        })
+    }
+
+    /// Reads the next data element along with its position, inflating it if
+    /// it is compressed. Returns `None` at the end of the file.
+    fn read_element(&mut self) -> Result<Option<(u64, Vec<u8>)>, Error> {
+        let mut tag = Vec::with_capacity(8);
+        self.read(8, &mut tag)?;
+        // Like `MatFile::parse`, ignore trailing bytes that aren't a data
+        // element
+        let (remaining, data_element_tag) =
+            match parse::parse_data_element_tag(self.endianness)(&tag) {
+                Ok(result) => result,
+                Err(_) => {
+                    self.finished = true;
+                    return Ok(None);
+                }
+            };
+        let start = self.position;
+        let tag_len = tag.len() - remaining.len();
+        let data_len = data_element_tag.data_byte_size();
+        let padding_len = data_element_tag.padding_byte_size();
+        self.position += (tag_len + data_len + padding_len) as u64;
+        if data_element_tag.data_type() != DataType::Compressed {
+            let mut element = tag.clone();
+            let num_bytes = (tag_len + data_len).saturating_sub(tag.len());
+            self.read(num_bytes, &mut element)?;
+            if element.len() < tag.len() + num_bytes {
+                self.finished = true;
+                return Err(Error::IOError(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+            // The padding of the last element might be missing
+            let num_bytes = (tag_len + data_len + padding_len).saturating_sub(element.len());
+            self.read(num_bytes, &mut element)?;
+            return Ok(Some((start, element)));
+        }
+        let mut data = remaining.chain(self.reader.by_ref()).take(data_len as u64);
+        let mut element = Vec::new();
+        let inflated =
+            zlib::Decoder::new(&mut data).and_then(|mut decoder| decoder.read_to_end(&mut element));
+        // Skip whatever wasn't needed to get to the next element
+        if let Err(err) = std::io::copy(&mut data, &mut std::io::sink()) {
+            self.finished = true;
+            return Err(Error::IOError(err));
+        }
+        if data.limit() > 0 {
+            self.finished = true;
+            return Err(Error::IOError(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        // A variable that can't be inflated doesn't affect the others
+        inflated.map_err(Error::IOError)?;
+        Ok(Some((start, element)))
+    }
+
+    /// Appends up to `num_bytes` bytes to the buffer.
+    fn read(&mut self, num_bytes: usize, buf: &mut Vec<u8>) -> Result<(), Error> {
+        let result = self.reader.by_ref().take(num_bytes as u64).read_to_end(buf);
+        if let Err(err) = result {
+            self.finished = true;
+            return Err(Error::IOError(err));
+        }
+        Ok(())
+    }
+
+    /// Decodes an element, returning `None` if it is of an unsupported type.
+    fn decode(&self, element: &[u8], subsystem: &Subsystem) -> Result<Option<Value>, Error> {
+        match parse::parse_next_data_element(self.endianness)(element) {
+            Ok((_, data_element)) => Value::try_from(data_element, subsystem),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl<R: Read> Iterator for MatFileReader<R> {
+    type Item = Result<Value, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(subsystem) = &self.subsystem {
+                if let Some(element) = self.deferred.pop_front() {
+                    let value = self.decode(&element, subsystem).map(|value| {
+                        value.unwrap_or_else(|| {
+                            Value::Raw(RawValue::new(&element, self.endianness, subsystem))
+                        })
+                    });
+                    return Some(value);
+                }
+            }
+            if self.finished {
+                return None;
+            }
+            let (start, element) = match self.read_element() {
+                Ok(Some(element)) => element,
+                // Objects can't be decoded without the subsystem data
+                Ok(None) => {
+                    self.subsystem.get_or_insert_with(Subsystem::default);
+                    continue;
+                }
+                Err(err) => return Some(Err(err)),
+            };
+            if self.subsystem_offset == Some(start) {
+                let subsystem = match parse::parse_subsystem_element(self.endianness)(&element) {
+                    Ok((_, Some(subsystem))) => {
+                        Subsystem::from_data_element(subsystem, self.endianness)
+                    }
+                    Ok((_, None)) => Ok(Subsystem::default()),
+                    Err(err) => Err(Error::ParseError(parse::replace_err_slice(err, &[]))),
+                };
+                match subsystem {
+                    Ok(subsystem) => self.subsystem = Some(subsystem.with_element(&element)),
+                    Err(err) => {
+                        self.subsystem = Some(Subsystem::default().with_element(&element));
+                        return Some(Err(err));
+                    }
+                }
+                continue;
+            }
+            let default = Subsystem::default();
+            let subsystem = self.subsystem.as_ref().unwrap_or(&default);
+            match self.decode(&element, subsystem) {
+                Ok(Some(value)) => return Some(Ok(value)),
+                _ if self.subsystem.is_none() => self.deferred.push_back(element),
+                Ok(None) => {
+                    let raw = RawValue::new(&element, self.endianness, subsystem);
+                    return Some(Ok(Value::Raw(raw)));
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Overwrites bytes at the given position and returns to `end`.
+fn patch<W: Write + Seek>(
+    writer: &mut W,
+    position: u64,
+    bytes: &[u8],
+    end: u64,
+) -> Result<(), Error> {
+    writer
+        .seek(SeekFrom::Start(position))
+        .map_err(Error::IOError)?;
+    writer.write_all(bytes).map_err(Error::IOError)?;
+    writer.seek(SeekFrom::Start(end)).map_err(Error::IOError)?;
+    Ok(())
+}
+
+/// The Adler-32 checksum of zlib streams.
+struct Adler32 {
+    a: u64,
+    b: u64,
+}
+
+impl Adler32 {
+    const MODULUS: u64 = 65521;
+
+    fn new() -> Self {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    fn of(bytes: &[u8]) -> u32 {
+        let mut checksum = Adler32::new();
+        checksum.update(bytes);
+        checksum.value()
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        // The sums can't overflow within a chunk of this size
+        for chunk in bytes.chunks(1 << 16) {
+            for &byte in chunk {
+                self.a += u64::from(byte);
+                self.b += self.a;
+            }
+            self.a %= Self::MODULUS;
+            self.b %= Self::MODULUS;
+        }
+    }
+
+    fn value(&self) -> u32 {
+        (self.b << 16 | self.a) as u32
+    }
+
+    /// The checksum of two concatenated byte sequences, given the checksums
+    /// of both and the length of the second one (like zlib's
+    /// `adler32_combine`).
+    fn combine(first: u32, second: u32, second_len: u64) -> u32 {
+        let modulus = Self::MODULUS;
+        let remainder = second_len % modulus;
+        let a1 = u64::from(first & 0xffff);
+        let b1 = u64::from(first >> 16);
+        let a2 = u64::from(second & 0xffff);
+        let b2 = u64::from(second >> 16);
+        let a = (a1 + a2 + modulus - 1) % modulus;
+        let b = (remainder * a1 % modulus + b1 + b2 + modulus - remainder) % modulus;
+        (b << 16 | a) as u32
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{check_size, Array, ClassdefArray, Error, NumericData, Value};
+
+/// The character count Matlab uses to mark missing strings
+const MISSING_STRING: u64 = u64::MAX;
+
+/// An array of Matlab strings (as created with double quotes or the `string`
+/// function), stored in column-major order.
+///
+/// Unlike character arrays, the strings of a string array can have different
+/// lengths. Missing strings (`string(missing)` in Matlab) are `None`.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/strings.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::String(names)) = mat_file.find_value_by_name("names") {
+///     for name in names.strings().iter().flatten() {
+///         println!("{}", name);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct StringArray {
+    pub(crate) name: String,
+    size: Vec<usize>,
+    strings: Vec<Option<String>>,
+}
+
+impl StringArray {
+    /// Creates a string array of the given size from strings in
+    /// column-major order, `None` for missing strings.
+    pub fn new(name: &str, size: Vec<usize>, strings: Vec<Option<String>>) -> Result<Self, Error> {
+        let num_elements = check_size(&size)?;
+        if strings.len() != num_elements {
+            return Err(Error::InvalidArgument(format!(
+                "a string array of size {:?} needs {} strings",
+                size, num_elements
+            )));
+        }
+        Ok(StringArray {
+            name: name.to_owned(),
+            size,
+            strings,
+        })
+    }
+
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.size.len()
+    }
+
+    /// The strings in column-major order, `None` for missing strings.
+    pub fn strings(&self) -> &Vec<Option<String>> {
+        &self.strings
+    }
+
+    /// Decodes a `string` object. Its only property `any` is a uint64 array
+    /// made up of a version number, the number of dimensions, the dimensions,
+    /// the number of characters of every string and finally the UTF-16 code
+    /// units of all strings, four of them packed into each uint64.
+    pub(crate) fn from_classdef(array: &ClassdefArray) -> Option<Self> {
+        if array.size().iter().product::<usize>() != 1 {
+            return None;
+        }
+        let data = match array.property(0, "any")? {
+            Value::Numeric(Array {
+                data: NumericData::UInt64 { real, imag: None },
+                ..
+            }) => real,
+            _ => return None,
+        };
+        let (&version, data) = data.split_first()?;
+        let (&ndims, data) = data.split_first()?;
+        if version != 1 || data.len() < ndims as usize {
+            return None;
+        }
+        let (size, data) = data.split_at(ndims as usize);
+        let size: Vec<usize> = size.iter().map(|&d| d as usize).collect();
+        let num_strings = size.iter().try_fold(1usize, |n, &d| n.checked_mul(d))?;
+        if data.len() < num_strings {
+            return None;
+        }
+        let (lengths, data) = data.split_at(num_strings);
+        // The code units are stored in little endian order, which is the only
+        // byte order Matlab writes nowadays
+        let code_units: Vec<u16> = data
+            .iter()
+            .flat_map(|&word| (0..4).map(move |k| (word >> (16 * k)) as u16))
+            .collect();
+        let mut position = 0usize;
+        let mut strings = Vec::with_capacity(num_strings);
+        for &length in lengths {
+            if length == MISSING_STRING {
+                strings.push(None);
+                continue;
+            }
+            let end = position.checked_add(length as usize)?;
+            strings.push(Some(String::from_utf16_lossy(
+                code_units.get(position..end)?,
+            )));
+            position = end;
+        }
+        Some(StringArray {
+            name: array.name().to_owned(),
+            size,
+            strings,
+        })
+    }
+
+    /// Encodes the strings as the properties of a `string` object, see
+    /// `from_classdef`.
+    pub(crate) fn to_properties(&self) -> Vec<(String, Value)> {
+        let mut data = vec![1, self.size.len() as u64];
+        data.extend(self.size.iter().map(|&d| d as u64));
+        let mut code_units = Vec::new();
+        for string in &self.strings {
+            match string {
+                Some(string) => {
+                    let start = code_units.len();
+                    code_units.extend(string.encode_utf16());
+                    data.push((code_units.len() - start) as u64);
+                }
+                None => data.push(MISSING_STRING),
+            }
+        }
+        data.extend(code_units.chunks(4).map(|units| {
+            units
+                .iter()
+                .enumerate()
+                .fold(0u64, |word, (k, &unit)| word | (unit as u64) << (16 * k))
+        }));
+        let any = Array {
+            name: String::new(),
+            size: vec![1, data.len()],
+            data: NumericData::UInt64 {
+                real: data,
+                imag: None,
+            },
+            logical: false,
+        };
+        vec![("any".to_owned(), Value::Numeric(any))]
+    }
+}
@@ -0,0 +1,394 @@
+use crate::{Array, CellArray, CharArray, ClassdefArray, Error, NumericData, StructArray, Value};
+
+/// Whether a table is a plain `table` or a `timetable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableKind {
+    /// A `table`, whose rows can optionally be named
+    Table,
+    /// A `timetable`, whose rows are labeled with a `datetime` or `duration`
+    Timetable,
+}
+
+/// A Matlab `table` or `timetable`.
+///
+/// Tables are stored column by column. Each column (a "variable" in Matlab
+/// terms) can be of a different type, e.g. a numeric array, a string array, a
+/// `categorical` array or a cell array, and has as many rows as the table.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/table.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::Table(results)) = mat_file.find_value_by_name("results") {
+///     for (name, column) in results.variable_names().iter().zip(results.columns()) {
+///         println!("{}: {:?}", name, column);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Table {
+    pub(crate) name: String,
+    kind: TableKind,
+    num_rows: usize,
+    variable_names: Vec<String>,
+    columns: Vec<Value>,
+    row_names: Vec<String>,
+    row_times: Option<Box<Value>>,
+    dimension_names: Vec<String>,
+    description: String,
+    variable_units: Vec<String>,
+    variable_descriptions: Vec<String>,
+    /// The properties of the object this was loaded from, which are written
+    /// instead of encoding the fields again
+    saved_properties: Option<Vec<(String, Value)>>,
+}
+
+impl std::fmt::Debug for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Table")
+            .field("name", &self.name)
+            .field("kind", &self.kind)
+            .field("num_rows", &self.num_rows)
+            .field("variable_names", &self.variable_names)
+            .field("columns", &self.columns)
+            .field("row_names", &self.row_names)
+            .field("row_times", &self.row_times)
+            .field("dimension_names", &self.dimension_names)
+            .field("description", &self.description)
+            .field("variable_units", &self.variable_units)
+            .field("variable_descriptions", &self.variable_descriptions)
+            .finish()
+    }
+}
+
+impl Table {
+    /// Creates a `table` from its columns and their (unique, non-empty)
+    /// variable names. All columns need the same number of rows, i.e. the
+    /// same first dimension. The names of the column values are ignored.
+    pub fn new(
+        name: &str,
+        variable_names: Vec<String>,
+        columns: Vec<Value>,
+    ) -> Result<Self, Error> {
+        if variable_names.len() != columns.len() {
+            return Err(Error::InvalidArgument(format!(
+                "{} variable names were given for {} columns",
+                variable_names.len(),
+                columns.len()
+            )));
+        }
+        for (i, variable_name) in variable_names.iter().enumerate() {
+            if variable_name.is_empty() || variable_names[..i].contains(variable_name) {
+                return Err(Error::InvalidArgument(format!(
+                    "invalid or duplicate variable name \"{}\"",
+                    variable_name
+                )));
+            }
+        }
+        let mut num_rows = None;
+        for (variable_name, column) in variable_names.iter().zip(&columns) {
+            let rows = num_rows_of(column).ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "column \"{}\" can't be stored in a table",
+                    variable_name
+                ))
+            })?;
+            if *num_rows.get_or_insert(rows) != rows {
+                return Err(Error::InvalidArgument(format!(
+                    "column \"{}\" has {} rows instead of {}",
+                    variable_name,
+                    rows,
+                    num_rows.unwrap_or(0)
+                )));
+            }
+        }
+        Ok(Table {
+            name: name.to_owned(),
+            kind: TableKind::Table,
+            num_rows: num_rows.unwrap_or(0),
+            variable_names,
+            columns,
+            row_names: Vec::new(),
+            row_times: None,
+            dimension_names: vec!["Row".to_owned(), "Variables".to_owned()],
+            description: String::new(),
+            variable_units: Vec::new(),
+            variable_descriptions: Vec::new(),
+            saved_properties: None,
+        })
+    }
+
+    /// Names the rows of a `table`, one name per row.
+    pub fn with_row_names(mut self, row_names: Vec<String>) -> Result<Self, Error> {
+        if self.kind != TableKind::Table || row_names.len() != self.num_rows {
+            return Err(Error::InvalidArgument(format!(
+                "a table with {} rows needs as many row names",
+                self.num_rows
+            )));
+        }
+        self.row_names = row_names;
+        self.saved_properties = None;
+        Ok(self)
+    }
+
+    /// The name of this table.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether this is a `table` or a `timetable`.
+    pub fn kind(&self) -> TableKind {
+        self.kind
+    }
+
+    /// The number of rows.
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// The number of columns (variables).
+    pub fn num_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// The names of the columns.
+    pub fn variable_names(&self) -> &Vec<String> {
+        &self.variable_names
+    }
+
+    /// The values of the columns, in the order of `variable_names`.
+    pub fn columns(&self) -> &Vec<Value> {
+        &self.columns
+    }
+
+    /// Returns the column named `variable_name`, if there is one.
+    pub fn column(&self, variable_name: &str) -> Option<&Value> {
+        let index = self
+            .variable_names
+            .iter()
+            .position(|name| name == variable_name)?;
+        self.columns.get(index)
+    }
+
+    /// The names of the rows. Empty if the rows are not named, which is
+    /// always the case for timetables.
+    pub fn row_names(&self) -> &Vec<String> {
+        &self.row_names
+    }
+
+    /// The times of the rows of a timetable, usually a `datetime` or
+    /// `duration` array. `None` for plain tables.
+    pub fn row_times(&self) -> Option<&Value> {
+        self.row_times.as_deref()
+    }
+
+    /// The names of the two dimensions, `Row` and `Variables` (or `Time` and
+    /// `Variables` for timetables) unless changed by the user.
+    pub fn dimension_names(&self) -> &Vec<String> {
+        &self.dimension_names
+    }
+
+    /// The description of the table, empty if there is none.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The units of the columns. Either empty or one (possibly empty) entry
+    /// per column.
+    pub fn variable_units(&self) -> &Vec<String> {
+        &self.variable_units
+    }
+
+    /// The descriptions of the columns. Either empty or one (possibly empty)
+    /// entry per column.
+    pub fn variable_descriptions(&self) -> &Vec<String> {
+        &self.variable_descriptions
+    }
+
+    /// Decodes a `table` object. The columns are stored in the cell array
+    /// `data`, their names in `varnames` and the row names in `rownames`.
+    /// Everything else is part of the `props` structure.
+    pub(crate) fn from_table(array: &ClassdefArray) -> Option<Self> {
+        if array.size().iter().product::<usize>() != 1 {
+            return None;
+        }
+        let props = match array.property(0, "props") {
+            Some(Value::Struct(props)) => Some(props),
+            _ => None,
+        };
+        let prop = |field_name| props.and_then(|props| props.field(0, field_name));
+        let mut table = Table::from_columns(array, TableKind::Table, "nrows", "varnames")?;
+        table.row_names = strings(array.property(0, "rownames"));
+        table.dimension_names = strings(prop("DimensionNames"));
+        table.description = text(prop("Description"));
+        table.variable_units = strings(prop("VariableUnits"));
+        table.variable_descriptions = strings(prop("VariableDescriptions"));
+        table.saved_properties = array.properties(0).cloned();
+        Some(table)
+    }
+
+    /// Decodes a `timetable` object. Unlike tables, timetables store most
+    /// of their properties as separate object properties, with the
+    /// remaining ones in the `arrayProps` structure.
+    pub(crate) fn from_timetable(array: &ClassdefArray) -> Option<Self> {
+        if array.size().iter().product::<usize>() != 1 {
+            return None;
+        }
+        let mut table = Table::from_columns(array, TableKind::Timetable, "numRows", "varNames")?;
+        table.row_times = Some(Box::new(array.property(0, "rowTimes")?.clone()));
+        table.dimension_names = strings(array.property(0, "dimNames"));
+        if let Some(Value::Struct(array_props)) = array.property(0, "arrayProps") {
+            table.description = text(array_props.field(0, "Description"));
+        }
+        table.variable_units = strings(array.property(0, "varUnits"));
+        table.variable_descriptions = strings(array.property(0, "varDescriptions"));
+        table.saved_properties = array.properties(0).cloned();
+        Some(table)
+    }
+
+    /// Reads the columns and their names, which both kinds of tables store
+    /// the same way, leaving all other properties empty.
+    fn from_columns(
+        array: &ClassdefArray,
+        kind: TableKind,
+        num_rows_property: &str,
+        variable_names_property: &str,
+    ) -> Option<Self> {
+        let columns = match array.property(0, "data")? {
+            Value::Cell(data) => data.cells().clone(),
+            _ => return None,
+        };
+        let variable_names = array.property(0, variable_names_property)?.to_strings()?;
+        if variable_names.len() != columns.len() {
+            return None;
+        }
+        let num_rows = array
+            .numeric_property(0, num_rows_property)
+            .and_then(|num_rows| num_rows.data().real_to_f64().first().copied())
+            .map(|num_rows| num_rows as usize)
+            .or_else(|| columns.first().and_then(num_rows_of))
+            .unwrap_or(0);
+        Some(Table {
+            name: array.name().to_owned(),
+            kind,
+            num_rows,
+            variable_names,
+            columns,
+            row_names: Vec::new(),
+            row_times: None,
+            dimension_names: Vec::new(),
+            description: String::new(),
+            variable_units: Vec::new(),
+            variable_descriptions: Vec::new(),
+            saved_properties: None,
+        })
+    }
+
+    /// Encodes a `table` as the properties of its object, see `from_table`.
+    /// Loaded tables and timetables keep all of their saved properties,
+    /// while new timetables can't be encoded since their row times can't
+    /// be written.
+    pub(crate) fn to_properties(&self) -> Option<Vec<(String, Value)>> {
+        if let Some(saved_properties) = &self.saved_properties {
+            return Some(saved_properties.clone());
+        }
+        if self.kind != TableKind::Table {
+            return None;
+        }
+        let num_columns = self.columns.len();
+        let props = StructArray::new(
+            "",
+            vec![1, 1],
+            [
+                "Description",
+                "UserData",
+                "DimensionNames",
+                "VariableDescriptions",
+                "VariableUnits",
+                "VariableContinuity",
+            ]
+            .iter()
+            .map(|&field_name| field_name.to_owned())
+            .collect(),
+            vec![
+                Value::Char(CharArray::from_text("", &self.description)),
+                empty(),
+                Value::from_strings(vec![1, self.dimension_names.len()], &self.dimension_names),
+                cellstr_row(&self.variable_descriptions),
+                cellstr_row(&self.variable_units),
+                empty(),
+            ],
+        )
+        .ok()?;
+        let row_names = if self.row_names.is_empty() {
+            Value::from_strings(vec![0, 0], &[])
+        } else {
+            Value::from_strings(vec![self.row_names.len(), 1], &self.row_names)
+        };
+        let data = CellArray::new("", vec![1, num_columns], self.columns.clone()).ok()?;
+        Some(vec![
+            ("ndims".to_owned(), Value::from_f64(2.0)),
+            ("nrows".to_owned(), Value::from_f64(self.num_rows as f64)),
+            ("rownames".to_owned(), row_names),
+            ("nvars".to_owned(), Value::from_f64(num_columns as f64)),
+            (
+                "varnames".to_owned(),
+                Value::from_strings(vec![1, num_columns], &self.variable_names),
+            ),
+            ("data".to_owned(), Value::Cell(data)),
+            ("props".to_owned(), Value::Struct(props)),
+        ])
+    }
+}
+
+/// An empty `0x0` double, Matlab's `[]`.
+fn empty() -> Value {
+    Value::Numeric(Array {
+        name: String::new(),
+        size: vec![0, 0],
+        data: NumericData::Double {
+            real: Vec::new(),
+            imag: None,
+        },
+        logical: false,
+    })
+}
+
+/// A `1xN` cell array of texts, or `{}` if there are none.
+fn cellstr_row(strings: &[String]) -> Value {
+    if strings.is_empty() {
+        Value::from_strings(vec![0, 0], strings)
+    } else {
+        Value::from_strings(vec![1, strings.len()], strings)
+    }
+}
+
+/// The number of rows of a column, in case the table doesn't store it.
+fn num_rows_of(column: &Value) -> Option<usize> {
+    let size = match column {
+        Value::Numeric(array) => array.size(),
+        Value::Sparse(array) => array.size(),
+        Value::Char(array) => array.size(),
+        Value::Cell(array) => array.size(),
+        Value::Struct(array) => array.size(),
+        Value::String(array) => array.size(),
+        Value::Datetime(array) => array.size(),
+        Value::Duration(array) => array.size(),
+        Value::Categorical(array) => array.size(),
+        _ => return None,
+    };
+    size.first().copied()
+}
+
+fn strings(value: Option<&Value>) -> Vec<String> {
+    value.and_then(Value::to_strings).unwrap_or_default()
+}
+
+fn text(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::Char(text)) => text.to_string(),
+        _ => String::new(),
+    }
+}
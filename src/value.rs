@@ -0,0 +1,657 @@
+use crate::half_precision;
+use crate::mcos::{self, Subsystem};
+use crate::parse;
+use crate::{
+    check_name, check_size, Array, CategoricalArray, ClassdefArray, DatetimeArray, DurationArray,
+    Error, FunctionHandle, Map, NumericData, SparseArray, StringArray, Table,
+};
+use std::sync::Arc;
+
+/// Any value that can be stored in a .mat file.
+///
+/// Values nested inside of cell and structure arrays are stored the same way
+/// as top-level variables, except that their name is empty.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Numeric(Array),
+    Sparse(SparseArray),
+    Char(CharArray),
+    Cell(CellArray),
+    Struct(StructArray),
+    Object(ObjectArray),
+    Classdef(ClassdefArray),
+    String(StringArray),
+    Datetime(DatetimeArray),
+    Duration(DurationArray),
+    Categorical(CategoricalArray),
+    Table(Table),
+    Map(Map),
+    FunctionHandle(FunctionHandle),
+    Raw(RawValue),
+}
+
+/// A character array.
+///
+/// Matlab stores characters as UTF-16 code units, which is what this array
+/// holds. Like numerical data the characters are stored in column-major
+/// order, so the rows of a character matrix are interleaved.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let file = std::fs::File::open("tests/char_cell_struct.mat")?;
+/// # let mat_file = matfile::MatFile::parse(file)?;
+/// if let Some(matfile::Value::Char(text)) = mat_file.find_value_by_name("greeting") {
+///     println!("{}", text);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CharArray {
+    name: String,
+    size: Vec<usize>,
+    data: Vec<u16>,
+}
+
+/// A cell array, i.e. an array of arbitrary values.
+///
+/// The cells are stored in column-major order.
+#[derive(Clone, Debug)]
+pub struct CellArray {
+    name: String,
+    size: Vec<usize>,
+    cells: Vec<Value>,
+}
+
+/// A structure array.
+///
+/// Every element of the array has the same set of fields. The values are
+/// stored one element after the other in column-major order, with the fields
+/// of each element stored in the order given by `field_names`.
+#[derive(Clone, Debug)]
+pub struct StructArray {
+    name: String,
+    size: Vec<usize>,
+    field_names: Vec<String>,
+    values: Vec<Value>,
+}
+
+/// An array of objects of a Matlab class defined with the old-style `@class`
+/// directory syntax.
+///
+/// These objects are stored just like structure arrays, with the properties
+/// of the class as fields.
+#[derive(Clone, Debug)]
+pub struct ObjectArray {
+    class_name: String,
+    fields: StructArray,
+}
+
+/// A variable that couldn't be decoded, e.g. because it is of a type that
+/// isn't supported.
+///
+/// Its data element is kept as it is stored in the file, so that writing the
+/// file again doesn't lose it. The name and the Matlab class of the variable
+/// are available if they can be read.
+///
+/// Raw values referencing objects (like a cell array holding a string next
+/// to an undecodable value) also keep the subsystem data of their file,
+/// which is written along with them. They can't be written together with
+/// other objects or raw values of other files referencing objects.
+#[derive(Clone, Debug)]
+pub struct RawValue {
+    name: String,
+    class_name: Option<String>,
+    data: Vec<u8>,
+    is_little_endian: bool,
+    subsystem: Option<Arc<[u8]>>,
+}
+
+impl Value {
+    /// The name of this value. Empty for values nested in cell or structure
+    /// arrays.
+    pub fn name(&self) -> &str {
+        match self {
+            Value::Numeric(array) => array.name(),
+            Value::Sparse(array) => array.name(),
+            Value::Char(array) => array.name(),
+            Value::Cell(array) => array.name(),
+            Value::Struct(array) => array.name(),
+            Value::Object(array) => array.name(),
+            Value::Classdef(array) => array.name(),
+            Value::String(array) => array.name(),
+            Value::Datetime(array) => array.name(),
+            Value::Duration(array) => array.name(),
+            Value::Categorical(array) => array.name(),
+            Value::Table(table) => table.name(),
+            Value::Map(map) => map.name(),
+            Value::FunctionHandle(handle) => handle.name(),
+            Value::Raw(raw) => raw.name(),
+        }
+    }
+
+    /// Renames this value.
+    pub(crate) fn set_name(&mut self, name: &str) {
+        let field = match self {
+            Value::Numeric(array) => &mut array.name,
+            Value::Sparse(array) => &mut array.name,
+            Value::Char(array) => &mut array.name,
+            Value::Cell(array) => &mut array.name,
+            Value::Struct(array) => &mut array.name,
+            Value::Object(array) => &mut array.fields.name,
+            Value::Classdef(array) => &mut array.name,
+            Value::String(array) => &mut array.name,
+            Value::Datetime(array) => &mut array.name,
+            Value::Duration(array) => &mut array.name,
+            Value::Categorical(array) => &mut array.name,
+            Value::Table(table) => &mut table.name,
+            Value::Map(map) => &mut map.name,
+            Value::FunctionHandle(handle) => &mut handle.name,
+            Value::Raw(raw) => &mut raw.name,
+        };
+        *field = name.to_owned();
+    }
+
+    /// Reads a list of texts, as Matlab stores them in a cell array of
+    /// character vectors, a string array or a single character vector.
+    pub(crate) fn to_strings(&self) -> Option<Vec<String>> {
+        match self {
+            Value::Char(text) => Some(vec![text.to_string()]),
+            Value::String(strings) => strings.strings().iter().cloned().collect(),
+            Value::Cell(cell) => cell
+                .cells()
+                .iter()
+                .map(|value| match value {
+                    Value::Char(text) => Some(text.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
+    /// Creates a cell array of character vectors, which is how Matlab stores
+    /// lists of texts in the properties of built-in classes.
+    pub(crate) fn from_strings(size: Vec<usize>, strings: &[String]) -> Self {
+        Value::Cell(CellArray {
+            name: String::new(),
+            size,
+            cells: strings
+                .iter()
+                .map(|text| Value::Char(CharArray::from_text("", text)))
+                .collect(),
+        })
+    }
+
+    /// Creates a scalar double.
+    pub(crate) fn from_f64(value: f64) -> Self {
+        Value::Numeric(Array {
+            name: String::new(),
+            size: vec![1, 1],
+            data: NumericData::Double {
+                real: vec![value],
+                imag: None,
+            },
+            logical: false,
+        })
+    }
+
+    /// Creates a scalar logical.
+    pub(crate) fn from_bool(value: bool) -> Self {
+        Value::Numeric(Array {
+            name: String::new(),
+            size: vec![1, 1],
+            data: NumericData::UInt8 {
+                real: vec![u8::from(value)],
+                imag: None,
+            },
+            logical: true,
+        })
+    }
+
+    /// Decodes objects of built-in classes into their dedicated types, all
+    /// other objects are kept as they are.
+    pub(crate) fn from_classdef(array: ClassdefArray) -> Self {
+        let decoded = match array.class_name() {
+            "string" => StringArray::from_classdef(&array).map(Value::String),
+            "datetime" => DatetimeArray::from_classdef(&array).map(Value::Datetime),
+            "duration" => DurationArray::from_classdef(&array).map(Value::Duration),
+            "categorical" => CategoricalArray::from_classdef(&array).map(Value::Categorical),
+            "table" => Table::from_table(&array).map(Value::Table),
+            "timetable" => Table::from_timetable(&array).map(Value::Table),
+            "containers.Map" => Map::from_classdef(&array).map(Value::Map),
+            "half" => half_precision::from_classdef(&array).map(Value::Numeric),
+            _ => None,
+        };
+        decoded.unwrap_or(Value::Classdef(array))
+    }
+
+    /// Converts a parsed data element, using the subsystem to resolve
+    /// references to classdef objects.
+    pub(crate) fn try_from(
+        data_element: parse::DataElement,
+        subsystem: &Subsystem,
+    ) -> Result<Option<Self>, Error> {
+        match data_element {
+            parse::DataElement::NumericMatrix(flags, dims, name, real, imag) => {
+                let data = NumericData::try_from(flags.class, real, imag)?;
+                Ok(Some(Value::Numeric(Array {
+                    name,
+                    size: size_from_dimensions(dims),
+                    data,
+                    logical: flags.logical,
+                })))
+            }
+            parse::DataElement::SparseMatrix(
+                flags,
+                dims,
+                name,
+                row_indices,
+                column_offsets,
+                real,
+                imag,
+            ) => Ok(Some(Value::Sparse(SparseArray::try_from(
+                flags,
+                dims,
+                name,
+                row_indices,
+                column_offsets,
+                real,
+                imag,
+            )?))),
+            parse::DataElement::CharacterMatrix(_flags, dims, name, data) => {
+                Ok(Some(Value::Char(CharArray {
+                    name,
+                    size: size_from_dimensions(dims),
+                    data,
+                })))
+            }
+            parse::DataElement::CellMatrix(_flags, dims, name, cells) => {
+                Ok(nested_values(cells, subsystem)?.map(|cells| {
+                    Value::Cell(CellArray {
+                        name,
+                        size: size_from_dimensions(dims),
+                        cells,
+                    })
+                }))
+            }
+            parse::DataElement::StructureMatrix(_flags, dims, name, field_names, values) => {
+                Ok(nested_values(values, subsystem)?.map(|values| {
+                    Value::Struct(StructArray {
+                        name,
+                        size: size_from_dimensions(dims),
+                        field_names,
+                        values,
+                    })
+                }))
+            }
+            parse::DataElement::ObjectMatrix(
+                _flags,
+                dims,
+                name,
+                class_name,
+                field_names,
+                values,
+            ) => Ok(nested_values(values, subsystem)?.map(|values| {
+                Value::Object(ObjectArray {
+                    class_name,
+                    fields: StructArray {
+                        name,
+                        size: size_from_dimensions(dims),
+                        field_names,
+                        values,
+                    },
+                })
+            })),
+            parse::DataElement::FunctionHandle(_flags, _dims, name, description) => {
+                match Value::try_from(*description, subsystem)? {
+                    Some(Value::Struct(description)) => {
+                        Ok(FunctionHandle::from_description(name, &description)
+                            .map(Value::FunctionHandle))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            parse::DataElement::Opaque(_flags, name, type_system, class_name, reference) => {
+                subsystem.resolve(name, &type_system, class_name, *reference)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn size_from_dimensions(dims: parse::Dimensions) -> Vec<usize> {
+    dims.into_iter().map(|d| d as usize).collect()
+}
+
+/// Converts the elements of a cell or structure array. Since skipping some of
+/// them would mess up the layout of the array, the whole array is ignored if
+/// any element is of unsupported type.
+fn nested_values(
+    data_elements: Vec<parse::DataElement>,
+    subsystem: &Subsystem,
+) -> Result<Option<Vec<Value>>, Error> {
+    let values: Vec<Option<Value>> = data_elements
+        .into_iter()
+        .map(|data_element| Value::try_from(data_element, subsystem))
+        .collect::<Result<_, _>>()?;
+    Ok(values.into_iter().collect())
+}
+
+impl RawValue {
+    /// Keeps a data element of a file, given with its tag, along with the
+    /// subsystem data element of the file if it references objects.
+    pub(crate) fn new(
+        data: &[u8],
+        endianness: nom::number::Endianness,
+        subsystem: &Subsystem,
+    ) -> Self {
+        let identity = parse::identify_data_element(endianness, data);
+        let subsystem = subsystem
+            .element()
+            .filter(|_| {
+                parse::parse_next_data_element(endianness)(data)
+                    .is_ok_and(|(_, data_element)| mcos::references_objects(&data_element))
+            })
+            .cloned();
+        RawValue {
+            name: identity
+                .as_ref()
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default(),
+            class_name: identity.map(|(_, class_name)| class_name),
+            data: data.to_vec(),
+            is_little_endian: endianness == nom::number::Endianness::Little,
+            subsystem,
+        }
+    }
+
+    /// The name of the variable, or an empty string if it can't be read.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The Matlab class of the variable (e.g. "double" or the name of the
+    /// class of an object), if it can be read.
+    pub fn class_name(&self) -> Option<&str> {
+        self.class_name.as_deref()
+    }
+
+    /// The complete data element, including its tag, in the byte order of
+    /// the file it was read from.
+    pub fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    /// Whether the data element is stored in little-endian byte order.
+    /// Only little-endian data elements can be written.
+    pub fn is_little_endian(&self) -> bool {
+        self.is_little_endian
+    }
+
+    /// The subsystem data element kept for the objects referenced by this
+    /// value.
+    pub(crate) fn subsystem(&self) -> Option<&Arc<[u8]>> {
+        self.subsystem.as_ref()
+    }
+}
+
+impl CharArray {
+    /// Creates a row vector holding the given text, or an empty 0x0 array
+    /// if the text is empty.
+    pub fn from_text(name: &str, text: &str) -> Self {
+        let data: Vec<u16> = text.encode_utf16().collect();
+        CharArray {
+            name: name.to_owned(),
+            size: vec![if data.is_empty() { 0 } else { 1 }, data.len()],
+            data,
+        }
+    }
+
+    /// Creates a character matrix with one row per text. Like Matlab's
+    /// `char` function, shorter rows are padded with spaces.
+    pub fn from_rows<S: AsRef<str>>(name: &str, rows: &[S]) -> Self {
+        let rows: Vec<Vec<u16>> = rows
+            .iter()
+            .map(|row| row.as_ref().encode_utf16().collect())
+            .collect();
+        let num_columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let data = (0..num_columns)
+            .flat_map(|column| {
+                rows.iter()
+                    .map(move |row| row.get(column).copied().unwrap_or(b' ' as u16))
+            })
+            .collect();
+        CharArray {
+            name: name.to_owned(),
+            size: vec![rows.len(), num_columns],
+            data,
+        }
+    }
+
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.size.len()
+    }
+
+    /// The UTF-16 code units stored in this array, in column-major order.
+    pub fn data(&self) -> &Vec<u16> {
+        &self.data
+    }
+
+    /// The rows of a two-dimensional character array as strings.
+    ///
+    /// Invalid UTF-16 sequences are replaced by the replacement character.
+    pub fn rows(&self) -> Vec<String> {
+        let num_rows = self.size.first().copied().unwrap_or(0);
+        (0..num_rows)
+            .map(|row| {
+                let units: Vec<u16> = self
+                    .data
+                    .iter()
+                    .skip(row)
+                    .step_by(num_rows)
+                    .copied()
+                    .collect();
+                String::from_utf16_lossy(&units)
+            })
+            .collect()
+    }
+}
+
+/// Formats the rows of the array separated by line breaks.
+impl std::fmt::Display for CharArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.rows().join("\n"))
+    }
+}
+
+impl CellArray {
+    /// Creates a cell array of the given size from values in column-major
+    /// order. The names of the values are ignored. Raw values can't be
+    /// written as cells.
+    pub fn new(name: &str, size: Vec<usize>, cells: Vec<Value>) -> Result<Self, Error> {
+        let num_elements = check_size(&size)?;
+        if cells.len() != num_elements {
+            return Err(Error::InvalidArgument(format!(
+                "a cell array of size {:?} needs {} cells",
+                size, num_elements
+            )));
+        }
+        Ok(CellArray {
+            name: name.to_owned(),
+            size,
+            cells,
+        })
+    }
+
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.size.len()
+    }
+
+    /// The values of the cells in column-major order.
+    pub fn cells(&self) -> &Vec<Value> {
+        &self.cells
+    }
+}
+
+impl StructArray {
+    /// Creates a structure array of the given size. The values of all
+    /// fields are given element after element, see the type level
+    /// documentation. Field names have to be unique valid Matlab identifiers
+    /// of at most 63 characters. The names of the values are ignored. Raw
+    /// values can't be written as fields.
+    pub fn new(
+        name: &str,
+        size: Vec<usize>,
+        field_names: Vec<String>,
+        values: Vec<Value>,
+    ) -> Result<Self, Error> {
+        let num_elements = check_size(&size)?;
+        check_field_names(&field_names)?;
+        let num_values = num_elements.checked_mul(field_names.len()).ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "a structure array of size {:?} with {} fields is too large",
+                size,
+                field_names.len()
+            ))
+        })?;
+        if values.len() != num_values {
+            return Err(Error::InvalidArgument(format!(
+                "a structure array of size {:?} with {} fields needs {} values",
+                size,
+                field_names.len(),
+                num_values
+            )));
+        }
+        Ok(StructArray {
+            name: name.to_owned(),
+            size,
+            field_names,
+            values,
+        })
+    }
+
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        &self.size
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.size.len()
+    }
+
+    /// The names of the fields shared by all elements of this array.
+    pub fn field_names(&self) -> &Vec<String> {
+        &self.field_names
+    }
+
+    /// The values of all fields of all elements, see the type level
+    /// documentation for their order.
+    pub fn values(&self) -> &Vec<Value> {
+        &self.values
+    }
+
+    /// Returns the value of the field `field_name` of the element at
+    /// (column-major) position `index`, if both exist.
+    pub fn field(&self, index: usize, field_name: &str) -> Option<&Value> {
+        let field_index = self.field_names.iter().position(|f| f == field_name)?;
+        self.values
+            .get(index * self.field_names.len() + field_index)
+    }
+}
+
+/// The longest field name Matlab supports
+pub(crate) const MAX_FIELD_NAME_LENGTH: usize = 63;
+
+/// Checks that field names are unique valid identifiers that aren't too
+/// long.
+pub(crate) fn check_field_names(field_names: &[String]) -> Result<(), Error> {
+    for (index, field_name) in field_names.iter().enumerate() {
+        check_name(field_name)?;
+        if field_name.len() > MAX_FIELD_NAME_LENGTH {
+            return Err(Error::InvalidArgument(format!(
+                "the field name \"{}\" is longer than {} characters",
+                field_name, MAX_FIELD_NAME_LENGTH
+            )));
+        }
+        if field_names[..index].contains(field_name) {
+            return Err(Error::InvalidArgument(format!(
+                "the field name \"{}\" is used more than once",
+                field_name
+            )));
+        }
+    }
+    Ok(())
+}
+
+impl ObjectArray {
+    /// The name of this array.
+    pub fn name(&self) -> &str {
+        self.fields.name()
+    }
+
+    /// The size of this array.
+    pub fn size(&self) -> &Vec<usize> {
+        self.fields.size()
+    }
+
+    /// The number of dimensions of this array. Is at least two.
+    pub fn ndims(&self) -> usize {
+        self.fields.ndims()
+    }
+
+    /// The name of the class of the objects in this array.
+    pub fn class_name(&self) -> &str {
+        &self.class_name
+    }
+
+    /// The names of the fields shared by all objects of this array.
+    pub fn field_names(&self) -> &Vec<String> {
+        self.fields.field_names()
+    }
+
+    /// The values of all fields of all objects, in the same order as for
+    /// structure arrays.
+    pub fn values(&self) -> &Vec<Value> {
+        self.fields.values()
+    }
+
+    /// Returns the value of the field `field_name` of the object at
+    /// (column-major) position `index`, if both exist.
+    pub fn field(&self, index: usize, field_name: &str) -> Option<&Value> {
+        self.fields.field(index, field_name)
+    }
+
+    /// The fields of the objects as a structure array.
+    pub fn as_struct(&self) -> &StructArray {
+        &self.fields
+    }
+}
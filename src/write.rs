@@ -0,0 +1,855 @@
+use crate::half_precision;
+use crate::mcos::SubsystemWriter;
+use crate::parse::{ArrayType, DataType};
+use crate::value::{check_field_names, MAX_FIELD_NAME_LENGTH};
+use crate::{
+    check_size, Array, CharArray, Error, MatFile, NumericData, SparseArray, StructArray, TableKind,
+    Value,
+};
+use libflate::zlib;
+use std::convert::TryFrom;
+use std::io::Write;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+// https://www.mathworks.com/help/pdf_doc/matlab/matfile_format.pdf
+
+const FLAG_COMPLEX: u32 = 0x0800;
+const FLAG_LOGICAL: u32 = 0x0200;
+
+/// How character arrays are stored in a written file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CharEncoding {
+    /// UTF-16 code units, the way Matlab stores characters itself
+    #[default]
+    Utf16,
+    /// UTF-8, which is more compact for mostly ASCII text. Character
+    /// matrices whose column-major order splits a surrogate pair (i.e.
+    /// multi-row matrices holding characters outside of the Basic
+    /// Multilingual Plane) can't be stored this way.
+    Utf8,
+}
+
+/// Options controlling how a .mat file is written.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mat_file = matfile::MatFile::from_values(vec![matfile::Value::Char(
+///     matfile::CharArray::from_text("greeting", "Hello, world!"),
+/// )])?;
+/// let options = matfile::WriteOptions::new().char_encoding(matfile::CharEncoding::Utf8);
+/// let mut data = Vec::new();
+/// mat_file.write_with_options(&mut data, &options)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+    char_encoding: CharEncoding,
+    pub(crate) compress: bool,
+}
+
+impl WriteOptions {
+    /// The default options: characters are stored as UTF-16 and variables
+    /// aren't compressed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how character arrays are stored.
+    pub fn char_encoding(mut self, char_encoding: CharEncoding) -> Self {
+        self.char_encoding = char_encoding;
+        self
+    }
+
+    /// Sets whether variables are compressed (with zlib), which Matlab does
+    /// by default. Files are uncompressed by default.
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+}
+
+impl MatFile {
+    /// Writes all values as a (version 5, uncompressed) ".mat" file using
+    /// the default options.
+    ///
+    /// ```rust
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mask = matfile::Array::new_logical("mask", vec![1, 3], &[true, false, true])?;
+    /// let mat_file = matfile::MatFile::from_values(vec![matfile::Value::Numeric(mask)])?;
+    /// let file = std::fs::File::create(std::env::temp_dir().join("mask.mat"))?;
+    /// mat_file.write(file)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Error> {
+        self.write_with_options(writer, &WriteOptions::default())
+    }
+
+    /// Writes all values as a (version 5) ".mat" file.
+    pub fn write_with_options<W: Write>(
+        &self,
+        mut writer: W,
+        options: &WriteOptions,
+    ) -> Result<(), Error> {
+        for part in self.encode(options)? {
+            writer.write_all(&part).map_err(Error::IOError)?;
+        }
+        Ok(())
+    }
+
+    /// Like `write`, but writing asynchronously with the `tokio` runtime.
+    #[cfg(feature = "tokio")]
+    pub async fn write_async<W: AsyncWrite + Unpin>(&self, writer: W) -> Result<(), Error> {
+        self.write_with_options_async(writer, &WriteOptions::default())
+            .await
+    }
+
+    /// Like `write_with_options`, but writing asynchronously with the
+    /// `tokio` runtime. The values are serialized up front, and the writer
+    /// is flushed at the end.
+    #[cfg(feature = "tokio")]
+    pub async fn write_with_options_async<W: AsyncWrite + Unpin>(
+        &self,
+        mut writer: W,
+        options: &WriteOptions,
+    ) -> Result<(), Error> {
+        for part in self.encode(options)? {
+            writer.write_all(&part).await.map_err(Error::IOError)?;
+        }
+        writer.flush().await.map_err(Error::IOError)
+    }
+
+    /// Serializes the header, the variables and the subsystem data if there
+    /// are any objects.
+    fn encode(&self, options: &WriteOptions) -> Result<Vec<Vec<u8>>, Error> {
+        // The header points to the subsystem data following the variables,
+        // which is only known once all variables have been serialized
+        let (body, subsystem) = encode_values(&self.values, options)?;
+        let subsystem_offset = subsystem.as_ref().map(|_| (128 + body.len()) as u64);
+        let mut parts = vec![header(subsystem_offset).to_vec(), body];
+        parts.extend(subsystem);
+        Ok(parts)
+    }
+}
+
+/// Serializes values as the data elements following the header, and the
+/// subsystem data holding their objects if there are any.
+pub(crate) fn encode_values(
+    values: &[Value],
+    options: &WriteOptions,
+) -> Result<(Vec<u8>, Option<Vec<u8>>), Error> {
+    let mut subsystem = SubsystemWriter::default();
+    let mut body = Vec::new();
+    for value in values {
+        body.extend_from_slice(&encode_value(value, options, &mut subsystem)?);
+    }
+    Ok((body, encode_subsystem(options, subsystem)?))
+}
+
+/// Serializes a single variable, adding its objects to `subsystem`. Each
+/// variable is serialized on its own since padding is relative to the start
+/// of the buffer, while compressed (raw) variables can end anywhere.
+pub(crate) fn encode_value(
+    value: &Value,
+    options: &WriteOptions,
+    subsystem: &mut SubsystemWriter,
+) -> Result<Vec<u8>, Error> {
+    let mut encoder = Encoder {
+        options,
+        subsystem: std::mem::take(subsystem),
+        in_subsystem: false,
+    };
+    let mut element = Vec::new();
+    let result = write_value(&mut element, value, value.name(), false, &mut encoder);
+    *subsystem = encoder.subsystem;
+    result?;
+    // Raw values are written exactly as they were read
+    if options.compress && !matches!(value, Value::Raw(_)) {
+        compress(&element)
+    } else {
+        Ok(element)
+    }
+}
+
+/// Serializes the subsystem data, if there are any objects.
+pub(crate) fn encode_subsystem(
+    options: &WriteOptions,
+    mut subsystem: SubsystemWriter,
+) -> Result<Option<Vec<u8>>, Error> {
+    // Raw values referencing objects need the subsystem data they were read
+    // with, whose object ids can't be combined with those of new objects
+    if let Some(original) = subsystem.take_original() {
+        if !subsystem.is_empty() {
+            return Err(Error::InvalidArgument(
+                "raw values referencing objects can't be written together with other objects"
+                    .to_owned(),
+            ));
+        }
+        return Ok(Some(original.to_vec()));
+    }
+    if subsystem.is_empty() {
+        return Ok(None);
+    }
+    let mut encoder = Encoder {
+        options,
+        subsystem,
+        in_subsystem: false,
+    };
+    write_subsystem(&mut encoder).map(Some)
+}
+
+/// The maximum number of codes of a deflate block. Readers can only start
+/// inflating at block boundaries, so blocks are kept smaller than
+/// libflate's default, which is closer to the blocks written by Matlab.
+pub(crate) const DEFLATE_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Wraps a data element into a compressed data element.
+fn compress(element: &[u8]) -> Result<Vec<u8>, Error> {
+    let options = zlib::EncodeOptions::new().block_size(DEFLATE_BLOCK_SIZE);
+    let mut encoder = zlib::Encoder::with_options(Vec::new(), options).map_err(Error::IOError)?;
+    // libflate only ends blocks between writes
+    for chunk in element.chunks(DEFLATE_BLOCK_SIZE) {
+        encoder.write_all(chunk).map_err(Error::IOError)?;
+    }
+    let data = encoder.finish().into_result().map_err(Error::IOError)?;
+    let mut compressed = Vec::with_capacity(data.len() + 8);
+    compressed.extend_from_slice(&(DataType::Compressed as u32).to_le_bytes());
+    compressed.extend_from_slice(&byte_size(data.len())?.to_le_bytes());
+    compressed.extend_from_slice(&data);
+    Ok(compressed)
+}
+
+/// The state of a file being written.
+struct Encoder<'a> {
+    options: &'a WriteOptions,
+    subsystem: SubsystemWriter,
+    /// Whether property values of objects are being written, which store
+    /// references to other objects as plain uint32 arrays
+    in_subsystem: bool,
+}
+
+/// The 128 byte file header: descriptive text, the subsystem data offset,
+/// the version and the endian indicator.
+pub(crate) fn header(subsystem_offset: Option<u64>) -> [u8; 128] {
+    let mut header = [b' '; 128];
+    let text = format!(
+        "MATLAB 5.0 MAT-file, written by matfile {}",
+        env!("CARGO_PKG_VERSION")
+    );
+    header[..text.len()].copy_from_slice(text.as_bytes());
+    header[116..124].copy_from_slice(&subsystem_offset.unwrap_or(0).to_le_bytes());
+    header[124..126].copy_from_slice(&0x0100u16.to_le_bytes());
+    header[126..128].copy_from_slice(b"IM");
+    header
+}
+
+/// Serializes a value as a matrix data element with the given name (which
+/// is empty for values nested in other values).
+fn write_value(
+    buf: &mut Vec<u8>,
+    value: &Value,
+    name: &str,
+    nested: bool,
+    encoder: &mut Encoder,
+) -> Result<(), Error> {
+    match value {
+        // Half precision arrays are stored as objects of the `half` class
+        Value::Numeric(array) => match half_precision::to_properties(array) {
+            Some(properties) => {
+                write_objects(buf, name, "half", &[1, 1], vec![properties], encoder)
+            }
+            None => write_numeric(buf, array, name),
+        },
+        Value::Sparse(array) => write_sparse(buf, array, name),
+        Value::Char(array) => write_char(buf, array, name, encoder.options),
+        Value::Cell(array) => write_matrix(buf, ArrayType::Cell, 0, array.size(), name, |buf| {
+            for cell in array.cells() {
+                write_value(buf, cell, "", true, encoder)?;
+            }
+            Ok(())
+        }),
+        Value::Struct(array) => write_struct(buf, array, name, None, encoder),
+        Value::Object(array) => write_struct(
+            buf,
+            array.as_struct(),
+            name,
+            Some(array.class_name()),
+            encoder,
+        ),
+        Value::Classdef(array) => write_objects(
+            buf,
+            name,
+            array.class_name(),
+            array.size(),
+            array.objects().clone(),
+            encoder,
+        ),
+        Value::String(array) => write_objects(
+            buf,
+            name,
+            "string",
+            &[1, 1],
+            vec![array.to_properties()],
+            encoder,
+        ),
+        Value::Categorical(array) => write_objects(
+            buf,
+            name,
+            "categorical",
+            &[1, 1],
+            vec![array.to_properties()],
+            encoder,
+        ),
+        Value::Datetime(array) => write_objects(
+            buf,
+            name,
+            "datetime",
+            &[1, 1],
+            vec![array.to_properties()],
+            encoder,
+        ),
+        Value::Duration(array) => write_objects(
+            buf,
+            name,
+            "duration",
+            &[1, 1],
+            vec![array.to_properties()],
+            encoder,
+        ),
+        Value::Table(table) => match table.to_properties() {
+            Some(properties) => {
+                let class_name = match table.kind() {
+                    TableKind::Table => "table",
+                    TableKind::Timetable => "timetable",
+                };
+                write_objects(buf, name, class_name, &[1, 1], vec![properties], encoder)
+            }
+            None => Err(Error::InvalidArgument(
+                "only loaded timetables can be written".to_owned(),
+            )),
+        },
+        Value::Map(map) => write_objects(
+            buf,
+            name,
+            "containers.Map",
+            &[1, 1],
+            vec![map.to_properties()?],
+            encoder,
+        ),
+        // Function handles are described by a nested struct
+        Value::FunctionHandle(handle) => {
+            let description = handle.to_description()?;
+            write_matrix(buf, ArrayType::Function, 0, &[1, 1], name, |buf| {
+                write_struct(buf, &description, "", None, encoder)
+            })
+        }
+        // Data elements that couldn't be decoded are written as they were
+        // read, including their name and compression, which is only valid
+        // for variables
+        Value::Raw(_) if nested => Err(Error::InvalidArgument(
+            "raw values can only be written as variables, not nested in other values".to_owned(),
+        )),
+        Value::Raw(raw) if raw.is_little_endian() => {
+            if let Some(subsystem) = raw.subsystem() {
+                encoder.subsystem.keep_original(subsystem)?;
+            }
+            buf.extend_from_slice(raw.data());
+            Ok(())
+        }
+        Value::Raw(_) => Err(Error::InvalidArgument(
+            "raw values of big-endian files can't be written".to_owned(),
+        )),
+    }
+}
+
+/// Writes a reference to an array of classdef objects, whose properties are
+/// added to the subsystem data.
+fn write_objects(
+    buf: &mut Vec<u8>,
+    name: &str,
+    class_name: &str,
+    size: &[usize],
+    objects: Vec<Vec<(String, Value)>>,
+    encoder: &mut Encoder,
+) -> Result<(), Error> {
+    check_size(size)?;
+    let reference = encoder.subsystem.add_objects(class_name, size, objects);
+    let reference = Array {
+        name: String::new(),
+        size: vec![reference.len(), 1],
+        data: NumericData::UInt32 {
+            real: reference,
+            imag: None,
+        },
+        logical: false,
+    };
+    if encoder.in_subsystem {
+        write_numeric(buf, &reference, name)
+    } else {
+        write_opaque(buf, name, class_name, |buf| {
+            write_numeric(buf, &reference, "")
+        })
+    }
+}
+
+/// Writes the subsystem data: a uint8 array holding a shortened file
+/// header followed by a struct whose `MCOS` field holds the `FileWrapper__`
+/// object, see the `mcos` module.
+fn write_subsystem(encoder: &mut Encoder) -> Result<Vec<u8>, Error> {
+    encoder.in_subsystem = true;
+    let mut property_values = Vec::new();
+    let mut num_values = 0;
+    loop {
+        let values = encoder.subsystem.take_values();
+        if values.is_empty() {
+            break;
+        }
+        for value in &values {
+            write_value(&mut property_values, value, "", true, encoder)?;
+        }
+        num_values += values.len();
+    }
+    let empty = || Array {
+        name: String::new(),
+        size: vec![0, 0],
+        data: NumericData::Double {
+            real: Vec::new(),
+            imag: None,
+        },
+        logical: false,
+    };
+    let metadata = encoder.subsystem.metadata();
+    let metadata = Array {
+        name: String::new(),
+        size: vec![metadata.len(), 1],
+        data: NumericData::UInt8 {
+            real: metadata,
+            imag: None,
+        },
+        logical: false,
+    };
+    // The metadata, an unused cell, the property values and a cell holding
+    // the default property values of every class (none are written)
+    let num_classes = encoder.subsystem.num_classes();
+    let num_cells = num_values + 3;
+    let mut mcos = Vec::new();
+    write_opaque(&mut mcos, "", "FileWrapper__", |buf| {
+        write_matrix(buf, ArrayType::Cell, 0, &[num_cells, 1], "", |buf| {
+            write_numeric(buf, &metadata, "")?;
+            write_numeric(buf, &empty(), "")?;
+            buf.extend_from_slice(&property_values);
+            write_matrix(buf, ArrayType::Cell, 0, &[num_classes + 1, 1], "", |buf| {
+                for _ in 0..=num_classes {
+                    write_numeric(buf, &empty(), "")?;
+                }
+                Ok(())
+            })
+        })
+    })?;
+    let mut data = Vec::new();
+    data.extend_from_slice(&0x0100u16.to_le_bytes());
+    data.extend_from_slice(b"IM");
+    data.extend_from_slice(&[0; 4]);
+    write_matrix(&mut data, ArrayType::Struct, 0, &[1, 1], "", |buf| {
+        write_field_names(buf, &["MCOS".to_owned()])?;
+        buf.extend_from_slice(&mcos);
+        Ok(())
+    })?;
+    let data = Array {
+        name: String::new(),
+        size: vec![data.len(), 1],
+        data: NumericData::UInt8 {
+            real: data,
+            imag: None,
+        },
+        logical: false,
+    };
+    let mut subsystem = Vec::new();
+    write_numeric(&mut subsystem, &data, "")?;
+    Ok(subsystem)
+}
+
+fn write_numeric(buf: &mut Vec<u8>, array: &Array, name: &str) -> Result<(), Error> {
+    let class = numeric_class(&array.data)?;
+    let mut flags = 0;
+    if array.data.is_complex() {
+        flags |= FLAG_COMPLEX;
+    }
+    if array.logical {
+        flags |= FLAG_LOGICAL;
+    }
+    write_matrix(buf, class, flags, &array.size, name, |buf| {
+        write_numeric_data(buf, &array.data)
+    })
+}
+
+/// The class of arrays holding the given type of data.
+pub(crate) fn numeric_class(data: &NumericData) -> Result<ArrayType, Error> {
+    Ok(match data {
+        NumericData::Int8 { .. } => ArrayType::Int8,
+        NumericData::UInt8 { .. } => ArrayType::UInt8,
+        NumericData::Int16 { .. } => ArrayType::Int16,
+        NumericData::UInt16 { .. } => ArrayType::UInt16,
+        NumericData::Int32 { .. } => ArrayType::Int32,
+        NumericData::UInt32 { .. } => ArrayType::UInt32,
+        NumericData::Int64 { .. } => ArrayType::Int64,
+        NumericData::UInt64 { .. } => ArrayType::UInt64,
+        NumericData::Single { .. } => ArrayType::Single,
+        NumericData::Double { .. } => ArrayType::Double,
+        NumericData::Half { .. } => {
+            return Err(Error::InvalidArgument(
+                "writing half precision arrays is not supported".to_owned(),
+            ))
+        }
+    })
+}
+
+/// Appends the real part of numeric data as little-endian bytes, without a
+/// tag, and returns their data type.
+pub(crate) fn extend_real_bytes(buf: &mut Vec<u8>, data: &NumericData) -> DataType {
+    fn real<T: Element>(buf: &mut Vec<u8>, real: &[T]) -> DataType {
+        for value in real {
+            value.extend_le_bytes(buf);
+        }
+        T::DATA_TYPE
+    }
+    match data {
+        NumericData::Int8 { real: values, .. } => real(buf, values),
+        NumericData::UInt8 { real: values, .. } => real(buf, values),
+        NumericData::Int16 { real: values, .. } => real(buf, values),
+        NumericData::UInt16 { real: values, .. } => real(buf, values),
+        NumericData::Int32 { real: values, .. } => real(buf, values),
+        NumericData::UInt32 { real: values, .. } => real(buf, values),
+        NumericData::Int64 { real: values, .. } => real(buf, values),
+        NumericData::UInt64 { real: values, .. } => real(buf, values),
+        NumericData::Single { real: values, .. } => real(buf, values),
+        NumericData::Double { real: values, .. } => real(buf, values),
+        NumericData::Half { real: values, .. } => real(buf, values),
+    }
+}
+
+/// The beginning of a numeric matrix data element up to the tag of its
+/// real part, whose data (of the given type and length) and padding are
+/// written separately. Unlike `write_tag` the long format is always used for
+/// that tag, so the length of this header doesn't depend on the data.
+pub(crate) fn numeric_matrix_header(
+    class: ArrayType,
+    size: &[usize],
+    name: &str,
+    data_type: DataType,
+    data_len: usize,
+) -> Result<Vec<u8>, Error> {
+    check_size(size)?;
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&(DataType::Matrix as u32).to_le_bytes());
+    buf.extend_from_slice(&[0; 4]);
+    write_values(&mut buf, &[class as u32, 0])?;
+    let dims: Vec<i32> = size.iter().map(|&d| d as i32).collect();
+    write_values(&mut buf, &dims)?;
+    write_subelement(&mut buf, DataType::Int8, name.as_bytes())?;
+    buf.extend_from_slice(&(data_type as u32).to_le_bytes());
+    buf.extend_from_slice(&byte_size(data_len)?.to_le_bytes());
+    let len = byte_size(buf.len() - 8 + data_len + padding(data_len))?;
+    buf[4..8].copy_from_slice(&len.to_le_bytes());
+    Ok(buf)
+}
+
+/// Writes the real and (optional) imaginary part of numeric data.
+pub(crate) fn write_numeric_data(buf: &mut Vec<u8>, data: &NumericData) -> Result<(), Error> {
+    fn parts<T: Element>(
+        buf: &mut Vec<u8>,
+        real: &[T],
+        imag: &Option<Vec<T>>,
+    ) -> Result<(), Error> {
+        write_values(buf, real)?;
+        match imag {
+            Some(imag) => write_values(buf, imag),
+            None => Ok(()),
+        }
+    }
+    match data {
+        NumericData::Int8 { real, imag } => parts(buf, real, imag),
+        NumericData::UInt8 { real, imag } => parts(buf, real, imag),
+        NumericData::Int16 { real, imag } => parts(buf, real, imag),
+        NumericData::UInt16 { real, imag } => parts(buf, real, imag),
+        NumericData::Int32 { real, imag } => parts(buf, real, imag),
+        NumericData::UInt32 { real, imag } => parts(buf, real, imag),
+        NumericData::Int64 { real, imag } => parts(buf, real, imag),
+        NumericData::UInt64 { real, imag } => parts(buf, real, imag),
+        NumericData::Single { real, imag } => parts(buf, real, imag),
+        NumericData::Double { real, imag } => parts(buf, real, imag),
+        NumericData::Half { real, imag } => parts(buf, real, imag),
+    }
+}
+
+/// Writes a sparse matrix: the row indices, the column offsets and the
+/// values of the nonzeros. Like Matlab, the allocated number of nonzeros
+/// (nzmax) is at least one.
+fn write_sparse(buf: &mut Vec<u8>, array: &SparseArray, name: &str) -> Result<(), Error> {
+    let mut flags = 0;
+    if array.data().is_complex() {
+        flags |= FLAG_COMPLEX;
+    }
+    if array.is_logical() {
+        flags |= FLAG_LOGICAL;
+    }
+    // Indices are stored as 32 bit integers. The last column offset is the
+    // number of nonzeros, so this checks the nzmax below too.
+    let indices = |indices: &[usize]| -> Result<Vec<i32>, Error> {
+        indices
+            .iter()
+            .map(|&index| {
+                i32::try_from(index).map_err(|_| {
+                    Error::InvalidArgument(format!("the sparse matrix \"{}\" is too large", name))
+                })
+            })
+            .collect()
+    };
+    let row_indices = indices(array.row_indices())?;
+    let column_offsets = indices(array.column_offsets())?;
+    let nzmax = array.num_nonzeros().max(1) as u32;
+    write_matrix_with_nzmax(
+        buf,
+        ArrayType::Sparse,
+        flags,
+        nzmax,
+        array.size(),
+        name,
+        |buf| {
+            write_values(buf, &row_indices)?;
+            write_values(buf, &column_offsets)?;
+            write_numeric_data(buf, array.data())
+        },
+    )
+}
+
+/// Writes a structure array, or an object array if there is a class name.
+fn write_struct(
+    buf: &mut Vec<u8>,
+    array: &StructArray,
+    name: &str,
+    class_name: Option<&str>,
+    encoder: &mut Encoder,
+) -> Result<(), Error> {
+    let class = match class_name {
+        Some(_) => ArrayType::Object,
+        None => ArrayType::Struct,
+    };
+    write_matrix(buf, class, 0, array.size(), name, |buf| {
+        if let Some(class_name) = class_name {
+            write_subelement(buf, DataType::Int8, class_name.as_bytes())?;
+        }
+        write_field_names(buf, array.field_names())?;
+        for value in array.values() {
+            write_value(buf, value, "", true, encoder)?;
+        }
+        Ok(())
+    })
+}
+
+/// Writes the field name length and field names subelements. The field
+/// names are stored NUL-padded to the same length: 32 bytes, or 64 bytes if
+/// any of them is longer than 31 characters.
+fn write_field_names(buf: &mut Vec<u8>, field_names: &[String]) -> Result<(), Error> {
+    check_field_names(field_names)?;
+    let longest = field_names.iter().map(String::len).max();
+    let field_name_length = if longest.unwrap_or(0) < 32 {
+        32
+    } else {
+        MAX_FIELD_NAME_LENGTH + 1
+    };
+    let mut bytes = Vec::with_capacity(field_names.len() * field_name_length);
+    for field_name in field_names {
+        bytes.extend_from_slice(field_name.as_bytes());
+        bytes.resize(bytes.len() + field_name_length - field_name.len(), 0);
+    }
+    write_values(buf, &[field_name_length as i32])?;
+    write_subelement(buf, DataType::Int8, &bytes)
+}
+
+fn write_char(
+    buf: &mut Vec<u8>,
+    array: &CharArray,
+    name: &str,
+    options: &WriteOptions,
+) -> Result<(), Error> {
+    write_matrix(
+        buf,
+        ArrayType::Char,
+        0,
+        array.size(),
+        name,
+        |buf| match options.char_encoding {
+            CharEncoding::Utf16 => {
+                let bytes: Vec<u8> = array.data().iter().flat_map(|c| c.to_le_bytes()).collect();
+                write_subelement(buf, DataType::Utf16, &bytes)
+            }
+            CharEncoding::Utf8 => {
+                let text: String = std::char::decode_utf16(array.data().iter().copied())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| {
+                        Error::InvalidArgument(format!(
+                            "the character array \"{}\" can't be stored as UTF-8",
+                            array.name()
+                        ))
+                    })?;
+                write_subelement(buf, DataType::Utf8, text.as_bytes())
+            }
+        },
+    )
+}
+
+/// Writes a matrix data element: the array flags, dimensions and name
+/// subelements followed by whatever `contents` writes.
+fn write_matrix(
+    buf: &mut Vec<u8>,
+    class: ArrayType,
+    flags: u32,
+    size: &[usize],
+    name: &str,
+    contents: impl FnOnce(&mut Vec<u8>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    write_matrix_with_nzmax(buf, class, flags, 0, size, name, contents)
+}
+
+/// Writes a matrix data element whose array flags subelement stores the
+/// number of nonzeros allocated for a sparse matrix.
+fn write_matrix_with_nzmax(
+    buf: &mut Vec<u8>,
+    class: ArrayType,
+    flags: u32,
+    nzmax: u32,
+    size: &[usize],
+    name: &str,
+    contents: impl FnOnce(&mut Vec<u8>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    check_size(size)?;
+    write_element(buf, |buf| {
+        write_values(buf, &[class as u32 | flags, nzmax])?;
+        let dims: Vec<i32> = size.iter().map(|&d| d as i32).collect();
+        write_values(buf, &dims)?;
+        write_subelement(buf, DataType::Int8, name.as_bytes())?;
+        contents(buf)
+    })
+}
+
+/// Writes an opaque matrix data element, which has no dimensions but a type
+/// system and class name, followed by whatever `contents` writes.
+fn write_opaque(
+    buf: &mut Vec<u8>,
+    name: &str,
+    class_name: &str,
+    contents: impl FnOnce(&mut Vec<u8>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    write_element(buf, |buf| {
+        write_values(buf, &[ArrayType::Opaque as u32, 0])?;
+        write_subelement(buf, DataType::Int8, name.as_bytes())?;
+        write_subelement(buf, DataType::Int8, b"MCOS")?;
+        write_subelement(buf, DataType::Int8, class_name.as_bytes())?;
+        contents(buf)
+    })
+}
+
+/// Writes a matrix data element tag followed by whatever `contents` writes.
+fn write_element(
+    buf: &mut Vec<u8>,
+    contents: impl FnOnce(&mut Vec<u8>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let start = buf.len();
+    buf.extend_from_slice(&(DataType::Matrix as u32).to_le_bytes());
+    // The size is filled in once the contents are known
+    buf.extend_from_slice(&[0; 4]);
+    contents(buf)?;
+    let len = byte_size(buf.len() - start - 8)?;
+    buf[start + 4..start + 8].copy_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+/// Writes a subelement holding the given bytes.
+fn write_subelement(buf: &mut Vec<u8>, data_type: DataType, bytes: &[u8]) -> Result<(), Error> {
+    write_tag(buf, data_type, bytes.len())?;
+    buf.extend_from_slice(bytes);
+    pad(buf);
+    Ok(())
+}
+
+/// Writes a subelement holding values of their natural data type. Matlab
+/// would compress them to the smallest type able to hold them, but storing
+/// them unchanged is just as valid.
+fn write_values<T: Element>(buf: &mut Vec<u8>, values: &[T]) -> Result<(), Error> {
+    write_tag(buf, T::DATA_TYPE, std::mem::size_of_val(values))?;
+    for value in values {
+        value.extend_le_bytes(buf);
+    }
+    pad(buf);
+    Ok(())
+}
+
+/// Writes the tag of a subelement, using the small data element format if
+/// its data fits into the tag.
+fn write_tag(buf: &mut Vec<u8>, data_type: DataType, len: usize) -> Result<(), Error> {
+    let len = byte_size(len)?;
+    if len <= 4 {
+        buf.extend_from_slice(&(len << 16 | data_type as u32).to_le_bytes());
+    } else {
+        buf.extend_from_slice(&(data_type as u32).to_le_bytes());
+        buf.extend_from_slice(&len.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Pads a subelement to the next 8 byte boundary. All data elements start
+/// at such a boundary of the file, which is why it is enough to look at the
+/// length of the buffer.
+fn pad(buf: &mut Vec<u8>) {
+    while buf.len() % 8 != 0 {
+        buf.push(0);
+    }
+}
+
+/// The number of bytes needed to pad data of the given length.
+pub(crate) fn padding(len: usize) -> usize {
+    (8 - len % 8) % 8
+}
+
+/// The data element size field is 32 bits wide.
+pub(crate) fn byte_size(len: usize) -> Result<u32, Error> {
+    u32::try_from(len).map_err(|_| {
+        Error::InvalidArgument(format!(
+            "{} bytes are too large for a version 5 .mat file",
+            len
+        ))
+    })
+}
+
+/// A type that can be stored in a numeric subelement.
+trait Element {
+    const DATA_TYPE: DataType;
+
+    fn extend_le_bytes(&self, buf: &mut Vec<u8>);
+}
+
+macro_rules! element {
+    ($type:ty, $data_type:ident) => {
+        impl Element for $type {
+            const DATA_TYPE: DataType = DataType::$data_type;
+
+            fn extend_le_bytes(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+    };
+}
+
+element!(i8, Int8);
+element!(u8, UInt8);
+element!(i16, Int16);
+element!(u16, UInt16);
+element!(i32, Int32);
+element!(u32, UInt32);
+element!(i64, Int64);
+element!(u64, UInt64);
+element!(f32, Single);
+element!(f64, Double);